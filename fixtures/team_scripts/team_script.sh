@@ -0,0 +1,6 @@
+#!/bin/bash
+#tags = ["core_approved", "team",]
+#developer = [ "example", "https://example.org" ]
+#call_format = "bash {{script}} {{ip}}"
+
+echo $@
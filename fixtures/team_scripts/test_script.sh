@@ -0,0 +1,10 @@
+#!/bin/bash
+#tags = ["core_approved", "team",]
+#developer = [ "example", "https://example.org" ]
+#ports_separator = " "
+#call_format = "bash {{script}} {{ip}} {{port}}"
+
+# Overrides the test_script.sh of fixtures/.rustscan_scripts when both
+# folders are given with --scripts-dir.
+
+echo team $@
@@ -1,19 +1,58 @@
-//! Provides functions to parse input IP addresses, CIDRs or files.
+//! Provides functions to parse input IP addresses, CIDRs, IP ranges or files.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{prelude::*, BufReader};
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
 use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 
 use cidr_utils::cidr::IpCidr;
 use hickory_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    system_conf::read_system_conf,
     Resolver,
 };
 use log::debug;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 
 use crate::input::Opts;
-use crate::warning;
+use crate::{etagln, warning};
+
+/// The most hosts a single CIDR or IP range may expand to by default, a /8
+/// for IPv4 and a /104 for IPv6, see `--max-range-hosts`.
+pub const MAX_HOSTS_PER_TARGET: u64 = 1 << 24;
+
+/// How many pairs of overlapping targets the overlap warning names.
+const MAX_OVERLAPS_LISTED: usize = 5;
+
+/// How long `reverse_lookups` waits for a PTR record, short enough not to
+/// hold up the results much when the DNS server ignores them.
+const PTR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many PTR records are looked up at once.
+const PTR_LOOKUPS_IN_PARALLEL: usize = 32;
+
+/// An input target, as given on the command line or in a file, along with
+/// the IPs it resolved or expanded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub ips: Vec<IpAddr>,
+}
+
+impl Target {
+    /// Whether the target was a host name that got resolved, as opposed to
+    /// an IP, a CIDR or an IP range.
+    pub fn is_hostname(&self) -> bool {
+        IpCidr::from_str(&self.name).is_err()
+            && parse_ip_range(&self.name).is_none()
+            && parse_octet_ranges(&self.name).is_none()
+    }
+}
 
 /// Parses the string(s) into IP addresses.
 ///
@@ -28,16 +67,135 @@ use crate::warning;
 /// let ips = parse_addresses(&opts);
 /// ```
 pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
-    let mut ips: Vec<IpAddr> = Vec::new();
+    unique_ips(&parse_targets(input))
+}
+
+/// Every IP of the targets once, in the order they first appear. The same
+/// host can come from several inputs, e.g. a CIDR and one of its IPs.
+pub fn unique_ips(targets: &[Target]) -> Vec<IpAddr> {
+    let mut seen = HashSet::new();
+    targets
+        .iter()
+        .flat_map(|target| &target.ips)
+        .filter(|ip| seen.insert(**ip))
+        .copied()
+        .collect()
+}
+
+/// Whether the IP is routed on the internet, as opposed to private,
+/// loopback, link-local, shared (CGNAT), documentation, benchmarking,
+/// multicast or reserved space.
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(&IpAddr::V4(ip));
+            }
+            let [first, second, ..] = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && second == 0xdb8))
+        }
+    }
+}
+
+/// The targets with public IPs, see `is_public`, along with how many of
+/// their IPs are.
+pub fn public_targets(targets: &[Target]) -> Vec<(&str, usize)> {
+    targets
+        .iter()
+        .map(|target| {
+            let public = target.ips.iter().filter(|ip| is_public(ip)).count();
+            (target.name.as_str(), public)
+        })
+        .filter(|(_, public)| *public > 0)
+        .collect()
+}
+
+/// Shuffles the IPs to scan, the same way every time for a given seed.
+pub fn shuffle_ips(ips: &mut [IpAddr], seed: Option<u64>) {
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    ips.shuffle(&mut rng);
+}
+
+/// Reorders the IPs to scan round robin across their /24 subnets, /120 for
+/// IPv6, so a large range is covered evenly instead of one subnet after the
+/// other. Subnets take turns in the order they first show up.
+pub fn interleave_ips(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let mut subnets: Vec<Vec<IpAddr>> = Vec::new();
+    let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+    for ip in ips {
+        let prefix = match ip {
+            IpAddr::V4(ip) => ip.octets()[..3].to_vec(),
+            IpAddr::V6(ip) => ip.octets()[..15].to_vec(),
+        };
+        let subnet = *index.entry(prefix).or_insert_with(|| {
+            subnets.push(Vec::new());
+            subnets.len() - 1
+        });
+        subnets[subnet].push(*ip);
+    }
+
+    let rounds = subnets.iter().map(Vec::len).max().unwrap_or_default();
+    (0..rounds)
+        .flat_map(|round| subnets.iter().filter_map(move |subnet| subnet.get(round)))
+        .copied()
+        .collect()
+}
+
+/// The host name every IP was resolved from, for the IPs that came from a
+/// host name target. An IP several host names resolved to keeps the first.
+pub fn hostnames(targets: &[Target]) -> HashMap<IpAddr, String> {
+    let mut hostnames = HashMap::new();
+    for target in targets.iter().filter(|target| target.is_hostname()) {
+        for ip in &target.ips {
+            hostnames.entry(*ip).or_insert_with(|| target.name.clone());
+        }
+    }
+    hostnames
+}
+
+/// Same as `parse_addresses`, but keeps track of the input target every IP
+/// came from. Targets left without IPs, unresolved or excluded, are dropped.
+pub fn parse_targets(input: &Opts) -> Vec<Target> {
+    resolve_targets(input).0
+}
+
+/// Same as `parse_targets`, along with the host names that didn't resolve,
+/// in the order they were given, for `--require-all-resolve`.
+pub fn resolve_targets(input: &Opts) -> (Vec<Target>, Vec<String>) {
+    let mut targets: Vec<Target> = Vec::new();
+    let mut unresolved: Vec<String> = Vec::new();
     let mut unresolved_addresses: Vec<&str> = Vec::new();
-    let backup_resolver = get_resolver(&input.resolver);
+    let backup_resolver = HostResolver::new(input);
 
     for address in &input.addresses {
-        let parsed_ips = parse_address(address, &backup_resolver);
-        if !parsed_ips.is_empty() {
-            ips.extend(parsed_ips);
-        } else {
-            unresolved_addresses.push(address);
+        match backup_resolver.resolve(address, range_limit(input)) {
+            Ok(parsed_ips) if !parsed_ips.is_empty() => targets.push(Target {
+                name: address.clone(),
+                ips: parsed_ips,
+            }),
+            Ok(_) => unresolved_addresses.push(address),
+            Err(e) => {
+                warn(input, e);
+            }
         }
     }
 
@@ -46,27 +204,194 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
         let file_path = Path::new(file_path);
 
         if !file_path.is_file() {
-            warning!(
-                format!("Host {file_path:?} could not be resolved."),
-                input.greppable,
-                input.accessible
-            );
+            warn(input, format!("Host {file_path:?} could not be resolved."));
+            unresolved.push(file_path.display().to_string());
 
             continue;
         }
 
-        if let Ok(x) = read_ips_from_file(file_path, &backup_resolver) {
-            ips.extend(x);
+        if let Ok(x) = read_ips_from_file(file_path, &backup_resolver, input, &mut unresolved) {
+            targets.extend(x);
         } else {
-            warning!(
-                format!("Host {file_path:?} could not be resolved."),
-                input.greppable,
-                input.accessible
-            );
+            warn(input, format!("Host {file_path:?} could not be resolved."));
+            unresolved.push(file_path.display().to_string());
         }
     }
 
-    ips
+    if let Some(target_file) = &input.target_file {
+        match read_target_file(target_file) {
+            Ok(lines) => {
+                for target in lines {
+                    match backup_resolver.resolve(&target, range_limit(input)) {
+                        Ok(parsed_ips) if !parsed_ips.is_empty() => targets.push(Target {
+                            name: target,
+                            ips: parsed_ips,
+                        }),
+                        Ok(_) => {
+                            warn(input, format!("Host {target:?} could not be resolved."));
+                            unresolved.push(target);
+                        }
+                        Err(e) => {
+                            warn(input, e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn(
+                    input,
+                    format!("Target file {target_file:?} could not be read: {e}"),
+                );
+            }
+        }
+    }
+
+    let mut excluded = match &input.exclude_addresses {
+        Some(exclude_addresses) => parse_excluded_addresses(exclude_addresses, input),
+        None => Vec::new(),
+    };
+
+    if let Some(exclude_file) = &input.exclude_file {
+        match read_exclude_file(exclude_file, &backup_resolver) {
+            Ok(file_excluded) => excluded.extend(file_excluded),
+            Err(e) => {
+                // Scanning without the whole exclude list could mean touching
+                // a host that must not be, so nothing gets scanned at all.
+                warn(input, format!("{e}, refusing to scan without it."));
+                return (Vec::new(), unresolved);
+            }
+        }
+    }
+
+    if input.ipv4_only || input.ipv6_only {
+        for target in &mut targets {
+            target.ips.retain(|ip| {
+                if input.ipv4_only {
+                    ip.is_ipv4()
+                } else {
+                    ip.is_ipv6()
+                }
+            });
+            if target.ips.is_empty() {
+                let family = if input.ipv4_only { "IPv4" } else { "IPv6" };
+                warn(
+                    input,
+                    format!("Host {:?} has no {family} address.", target.name),
+                );
+            }
+        }
+        targets.retain(|target| !target.ips.is_empty());
+    }
+
+    if !excluded.is_empty() {
+        for target in &mut targets {
+            target
+                .ips
+                .retain(|ip| !excluded.iter().any(|cidr| cidr.contains(ip)));
+        }
+        targets.retain(|target| !target.ips.is_empty());
+    }
+
+    let overlaps = overlaps(&targets);
+    if !overlaps.is_empty() {
+        let hosts: usize = overlaps.iter().map(|(_, _, hosts)| hosts).sum();
+        let mut listed: Vec<String> = overlaps
+            .iter()
+            .take(MAX_OVERLAPS_LISTED)
+            .map(|(first, second, hosts)| format!("{first} and {second} ({hosts})"))
+            .collect();
+        if overlaps.len() > MAX_OVERLAPS_LISTED {
+            listed.push(format!("{} more", overlaps.len() - MAX_OVERLAPS_LISTED));
+        }
+        warn(
+            input,
+            format!(
+                "{hosts} host(s) are listed by several targets and only scanned once: {}.",
+                listed.join(", ")
+            ),
+        );
+    }
+
+    (targets, unresolved)
+}
+
+/// The targets sharing IPs, as the names of the target an IP first came
+/// from and of a later one repeating it, with how many IPs the later one
+/// repeats. Pairs are in the order of the later target.
+fn overlaps(targets: &[Target]) -> Vec<(&str, &str, usize)> {
+    let mut first_target: HashMap<IpAddr, usize> = HashMap::new();
+    let mut repeated: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for (index, target) in targets.iter().enumerate() {
+        for ip in &target.ips {
+            let first = *first_target.entry(*ip).or_insert(index);
+            if first != index {
+                *repeated.entry((index, first)).or_insert(0) += 1;
+            }
+        }
+    }
+    repeated
+        .into_iter()
+        .map(|((later, first), hosts)| {
+            (
+                targets[first].name.as_str(),
+                targets[later].name.as_str(),
+                hosts,
+            )
+        })
+        .collect()
+}
+
+/// Parses the `--exclude-addresses` entries, single IPs being treated as a
+/// CIDR containing only that host.
+fn parse_excluded_addresses(exclude_addresses: &[String], input: &Opts) -> Vec<IpCidr> {
+    let mut excluded = Vec::with_capacity(exclude_addresses.len());
+    for address in exclude_addresses {
+        match IpCidr::from_str(address.trim()) {
+            Ok(cidr) => excluded.push(cidr),
+            Err(_) => {
+                warn(
+                    input,
+                    format!("Excluded address {address:?} is not a valid IP or CIDR."),
+                );
+            }
+        }
+    }
+    excluded
+}
+
+/// Warns about a target that can't be scanned as given: on stderr in quiet
+/// mode, so stdout only holds results, and not at all in greppable mode.
+fn warn(input: &Opts, message: impl std::fmt::Display) {
+    if input.quiet {
+        etagln!(Warning, "{message}");
+    } else {
+        warning!(message, input.greppable, input.accessible);
+    }
+}
+
+/// Reads an `--exclude-file`, one IP, CIDR or host per line, with the same
+/// comment rules as a target file. Every entry has to be valid and hosts
+/// have to resolve, otherwise the whole file is rejected.
+fn read_exclude_file(path: &Path, resolver: &HostResolver) -> Result<Vec<IpCidr>, String> {
+    let entries = read_target_file(path)
+        .map_err(|e| format!("Exclude file {path:?} could not be read: {e}"))?;
+
+    let mut excluded = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if let Ok(cidr) = IpCidr::from_str(&entry) {
+            excluded.push(cidr);
+            continue;
+        }
+        match resolver.resolve(&entry, None) {
+            Ok(ips) if !ips.is_empty() => excluded.extend(ips.into_iter().map(IpCidr::new_host)),
+            _ => {
+                return Err(format!(
+                    "Excluded host {entry:?} from {path:?} could not be resolved"
+                ))
+            }
+        }
+    }
+    Ok(excluded)
 }
 
 /// Given a string, parse it as a host, IP address, or CIDR.
@@ -77,24 +402,202 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
 /// If the address is a domain, we can self-resolve the domain locally
 /// or resolve it by dns resolver list.
 ///
+/// IP ranges are written `start-end`, e.g. `192.168.1.10-192.168.1.50`, or
+/// with just the last octet as the end for IPv4, e.g. `192.168.1.10-50`.
+///
+/// IPv4 addresses can also be written the way Nmap takes them, with any octet
+/// a range, e.g. `192.168.0-5.1-254` for every address of each of the
+/// combinations. A range may leave out either end, `*` is all of 0-255, and
+/// several can be given separated by commas, though only in a target file as
+/// `--addresses` splits on commas.
+///
+/// CIDRs and IP ranges expanding to more than `range_limit` hosts are
+/// refused with an error, so that something like an IPv6 /64 or `*.*.*.*`
+/// doesn't try to enumerate all of its addresses. `None` doesn't limit, see
+/// [`range_limit`].
+///
 /// ```rust
-/// # use rustscan::address::parse_address;
+/// # use rustscan::address::{parse_address, MAX_HOSTS_PER_TARGET};
 /// # use hickory_resolver::Resolver;
-/// let ips = parse_address("127.0.0.1", &Resolver::default().unwrap());
+/// let resolver = Resolver::default().unwrap();
+/// let ips = parse_address("127.0.0.1", &resolver, Some(MAX_HOSTS_PER_TARGET));
+/// assert!(parse_address("2001:db8::/64", &resolver, Some(MAX_HOSTS_PER_TARGET)).is_err());
 /// ```
-pub fn parse_address(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
-    IpCidr::from_str(address)
-        .map(|cidr| cidr.iter().map(|c| c.address()).collect())
+pub fn parse_address(
+    address: &str,
+    resolver: &Resolver,
+    range_limit: Option<u64>,
+) -> Result<Vec<IpAddr>, String> {
+    if let Some(ips) = expand_literal(address, range_limit) {
+        return ips;
+    }
+
+    Ok(lookup_host(address, resolver))
+}
+
+/// The most hosts a CIDR or IP range of the targets may expand to,
+/// `--max-range-hosts` unless `--allow-huge-range` lifts the limit.
+pub fn range_limit(input: &Opts) -> Option<u64> {
+    (!input.allow_huge_range).then_some(input.max_range_hosts)
+}
+
+/// Refuses a `target` expanding to more hosts than `range_limit`.
+fn check_host_count(
+    target: impl std::fmt::Display,
+    host_count: u128,
+    range_limit: Option<u64>,
+) -> Result<(), String> {
+    match range_limit {
+        Some(limit) if host_count > limit.into() => Err(format!(
+            "{target} expands to {host_count} hosts, more than the {limit} allowed. Use --allow-huge-range or --max-range-hosts to scan it anyway."
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The IPs of a host name, from the system resolver or else `resolver`.
+fn lookup_host(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
+    format!("{}:{}", &address, 80)
+        .to_socket_addrs()
         .ok()
-        .or_else(|| {
-            format!("{}:{}", &address, 80)
-                .to_socket_addrs()
-                .ok()
-                .map(|mut iter| vec![iter.next().unwrap().ip()])
-        })
+        .map(|mut iter| vec![iter.next().unwrap().ip()])
         .unwrap_or_else(|| resolve_ips_from_host(address, resolver))
 }
 
+/// Expands the address if it's a CIDR, an IP range or octet ranges, none
+/// for anything else, IPs included.
+fn expand_literal(address: &str, range_limit: Option<u64>) -> Option<Result<Vec<IpAddr>, String>> {
+    if let Ok(cidr) = IpCidr::from_str(address) {
+        return Some(expand_cidr(&cidr, range_limit));
+    }
+    if let Some((start, end)) = parse_ip_range(address) {
+        return Some(expand_ip_range(start, end, range_limit));
+    }
+    let octets = parse_octet_ranges(address)?;
+    Some(octets.and_then(|octets| expand_octet_ranges(address, &octets, range_limit)))
+}
+
+/// Parses Nmap style octet ranges, see `parse_address`, into the values of
+/// each octet in order. Anything that isn't four octets with at least one of
+/// them a range is none, and a range ending before it starts is an error.
+fn parse_octet_ranges(address: &str) -> Option<Result<Vec<Vec<u8>>, String>> {
+    let parts: Vec<&str> = address.split('.').collect();
+    if parts.len() != 4 || parts.iter().all(|part| part.parse::<u8>().is_ok()) {
+        return None;
+    }
+
+    let bound = |bound: &str, open: u8| -> Option<u8> {
+        if bound.is_empty() {
+            Some(open)
+        } else {
+            bound.parse().ok()
+        }
+    };
+    let mut octets = Vec::with_capacity(4);
+    for part in parts {
+        let mut values = BTreeSet::new();
+        for item in part.split(',') {
+            let (first, last) = match item.split_once('-') {
+                _ if item == "*" => (0, u8::MAX),
+                Some((first, last)) => (bound(first, 0)?, bound(last, u8::MAX)?),
+                None => {
+                    let value = item.parse().ok()?;
+                    (value, value)
+                }
+            };
+            if last < first {
+                return Some(Err(format!(
+                    "the octet range {item} of {address} ends before it starts."
+                )));
+            }
+            values.extend(first..=last);
+        }
+        octets.push(values.into_iter().collect());
+    }
+    Some(Ok(octets))
+}
+
+/// Expands octet ranges into every combination of their values, with the
+/// same limit as `expand_cidr`.
+fn expand_octet_ranges(
+    address: &str,
+    octets: &[Vec<u8>],
+    range_limit: Option<u64>,
+) -> Result<Vec<IpAddr>, String> {
+    let host_count = octets.iter().map(|values| values.len() as u128).product();
+    check_host_count(address, host_count, range_limit)?;
+
+    let mut ips = Vec::new();
+    for &a in &octets[0] {
+        for &b in &octets[1] {
+            for &c in &octets[2] {
+                ips.extend(
+                    octets[3]
+                        .iter()
+                        .map(|&d| IpAddr::V4(Ipv4Addr::new(a, b, c, d))),
+                );
+            }
+        }
+    }
+    Ok(ips)
+}
+
+/// Parses a `start-end` IP range, see `parse_address`. Anything else, host
+/// names with dashes included, is none.
+fn parse_ip_range(address: &str) -> Option<(IpAddr, IpAddr)> {
+    let (start, end) = address.split_once('-')?;
+    let start: IpAddr = start.trim().parse().ok()?;
+    let end = end.trim();
+    let end = match (start, end.parse::<u8>()) {
+        (IpAddr::V4(start), Ok(last_octet)) => {
+            let [a, b, c, _] = start.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, last_octet))
+        }
+        _ => end.parse().ok()?,
+    };
+    Some((start, end))
+}
+
+/// Expands an IP range into every address from `start` to `end`, both
+/// included, with the same limit as `expand_cidr`.
+fn expand_ip_range(
+    start: IpAddr,
+    end: IpAddr,
+    range_limit: Option<u64>,
+) -> Result<Vec<IpAddr>, String> {
+    let check = |first: u128, last: u128| {
+        if last < first {
+            return Err(format!("the range {start}-{end} ends before it starts."));
+        }
+        let host_count = (last - first).saturating_add(1);
+        check_host_count(format_args!("{start}-{end}"), host_count, range_limit)
+    };
+
+    match (start, end) {
+        (IpAddr::V4(first), IpAddr::V4(last)) => {
+            let (first, last) = (u32::from(first), u32::from(last));
+            check(first.into(), last.into())?;
+            Ok((first..=last).map(|ip| IpAddr::V4(ip.into())).collect())
+        }
+        (IpAddr::V6(first), IpAddr::V6(last)) => {
+            let (first, last) = (u128::from(first), u128::from(last));
+            check(first, last)?;
+            Ok((first..=last).map(|ip| IpAddr::V6(ip.into())).collect())
+        }
+        _ => Err(format!("the range {start}-{end} mixes IPv4 and IPv6.")),
+    }
+}
+
+/// Expands a CIDR into every address it contains, refusing the ones with
+/// more than `range_limit` hosts.
+fn expand_cidr(cidr: &IpCidr, range_limit: Option<u64>) -> Result<Vec<IpAddr>, String> {
+    let host_bits = u32::from(cidr.family().len() - cidr.network_length());
+    let host_count = 1u128.checked_shl(host_bits).unwrap_or(u128::MAX);
+    check_host_count(cidr, host_count, range_limit)?;
+
+    Ok(cidr.iter().map(|c| c.address()).collect())
+}
+
 /// Uses DNS to get the IPS associated with host
 fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr> {
     let mut ips: Vec<IpAddr> = Vec::new();
@@ -110,6 +613,119 @@ fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr
     ips
 }
 
+/// Resolves the host names of targets. The system resolver is asked first
+/// and `backup` only when it fails, unless a DNS server was given with
+/// `--dns-server`, which then answers every lookup.
+///
+/// Every name is only looked up once, later targets with the same name, in
+/// any case, get the IPs of the first lookup.
+struct HostResolver {
+    backup: Resolver,
+    dns_server_only: bool,
+    cache: RefCell<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl HostResolver {
+    fn new(input: &Opts) -> Self {
+        match input.dns_server {
+            Some(dns_server) => Self {
+                backup: dns_server_resolver(dns_server),
+                dns_server_only: true,
+                cache: RefCell::default(),
+            },
+            None => Self {
+                backup: get_resolver(&input.resolver),
+                dns_server_only: false,
+                cache: RefCell::default(),
+            },
+        }
+    }
+
+    /// Same as `parse_address`, going to the DNS server directly if one was
+    /// given.
+    fn resolve(&self, address: &str, range_limit: Option<u64>) -> Result<Vec<IpAddr>, String> {
+        if let Some(ips) = expand_literal(address, range_limit) {
+            return ips;
+        }
+        let name = address.to_lowercase();
+        if let Some(ips) = self.cache.borrow().get(&name) {
+            return Ok(ips.clone());
+        }
+        let ips = if self.dns_server_only {
+            self.backup
+                .lookup_ip(address)
+                .map(|addrs| addrs.iter().collect())
+                .unwrap_or_default()
+        } else {
+            lookup_host(address, &self.backup)
+        };
+        self.cache.borrow_mut().insert(name, ips.clone());
+        Ok(ips)
+    }
+}
+
+/// A resolver asking nothing but the given DNS server, over UDP and falling
+/// back to TCP for truncated answers.
+fn dns_server_resolver(dns_server: SocketAddr) -> Resolver {
+    Resolver::new(dns_server_config(dns_server), ResolverOpts::default()).unwrap()
+}
+
+fn dns_server_config(dns_server: SocketAddr) -> ResolverConfig {
+    let mut config = ResolverConfig::new();
+    config.add_name_server(NameServerConfig::new(dns_server, Protocol::Udp));
+    config.add_name_server(NameServerConfig::new(dns_server, Protocol::Tcp));
+    config
+}
+
+/// Looks up the PTR record of every IP with `--resolve-ptr`, asking the
+/// servers target resolution asks given the `--resolver` and `--dns-server`
+/// options. The lookups run concurrently and each
+/// gives up after `PTR_TIMEOUT`, IPs without an answer map to None.
+pub fn reverse_lookups(
+    ips: &[IpAddr],
+    resolver: &Option<String>,
+    dns_server: Option<SocketAddr>,
+) -> HashMap<IpAddr, Option<String>> {
+    let (config, mut options) = match dns_server {
+        Some(dns_server) => (dns_server_config(dns_server), ResolverOpts::default()),
+        None => resolver_config(resolver),
+    };
+    options.timeout = PTR_TIMEOUT;
+    options.attempts = 0;
+
+    let pending = std::sync::Mutex::new(ips.iter());
+    let ptrs = std::sync::Mutex::new(HashMap::with_capacity(ips.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..ips.len().min(PTR_LOOKUPS_IN_PARALLEL) {
+            // The blocking resolver runs one lookup at a time, so every
+            // thread gets its own.
+            let resolver = Resolver::new(config.clone(), options.clone());
+            let (pending, ptrs) = (&pending, &ptrs);
+            scope.spawn(move || loop {
+                let Some(ip) = pending.lock().unwrap().next().copied() else {
+                    break;
+                };
+                let lookup = match &resolver {
+                    Ok(resolver) => resolver.reverse_lookup(ip).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let ptr = match lookup {
+                    Ok(lookup) => lookup
+                        .iter()
+                        .next()
+                        .map(|name| name.to_utf8().trim_end_matches('.').to_owned()),
+                    Err(e) => {
+                        debug!("No PTR record for {}: {}", ip, e);
+                        None
+                    }
+                };
+                ptrs.lock().unwrap().insert(ip, ptr);
+            });
+        }
+    });
+    ptrs.into_inner().unwrap()
+}
+
 /// Derive a DNS resolver.
 ///
 /// 1. if the `resolver` parameter has been set:
@@ -121,6 +737,12 @@ fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr
 ///    2. finally, build a CloudFlare-based resolver (default
 ///       behaviour).
 fn get_resolver(resolver: &Option<String>) -> Resolver {
+    let (config, options) = resolver_config(resolver);
+    Resolver::new(config, options).unwrap()
+}
+
+/// The configuration `get_resolver` builds its resolver from.
+fn resolver_config(resolver: &Option<String>) -> (ResolverConfig, ResolverOpts) {
     match resolver {
         Some(r) => {
             let mut config = ResolverConfig::new();
@@ -137,13 +759,11 @@ fn get_resolver(resolver: &Option<String>) -> Resolver {
                     Protocol::Udp,
                 ));
             }
-            Resolver::new(config, ResolverOpts::default()).unwrap()
+            (config, ResolverOpts::default())
         }
-        None => match Resolver::from_system_conf() {
-            Ok(resolver) => resolver,
-            Err(_) => {
-                Resolver::new(ResolverConfig::cloudflare_tls(), ResolverOpts::default()).unwrap()
-            }
+        None => match read_system_conf() {
+            Ok(system_conf) => system_conf,
+            Err(_) => (ResolverConfig::cloudflare_tls(), ResolverOpts::default()),
         },
     }
 }
@@ -158,37 +778,90 @@ fn read_resolver_from_file(path: &str) -> Result<Vec<IpAddr>, std::io::Error> {
     Ok(ips)
 }
 
+/// Reads the targets of a `--target-file`, one host, IP or CIDR per line.
+/// A path of `-` reads from stdin instead.
+fn read_target_file(path: &Path) -> Result<Vec<String>, std::io::Error> {
+    if path == Path::new("-") {
+        Ok(read_target_lines(std::io::stdin().lock()))
+    } else {
+        Ok(read_target_lines(BufReader::new(File::open(path)?)))
+    }
+}
+
+/// Collects the non blank lines of a target list, dropping `#` comments.
+fn read_target_lines<R: BufRead>(reader: R) -> Vec<String> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let target = line.split('#').next().unwrap_or_default().trim();
+            (!target.is_empty()).then(|| target.to_owned())
+        })
+        .collect()
+}
+
 #[cfg(not(tarpaulin_include))]
 /// Parses an input file of IPs and uses those
 fn read_ips_from_file(
     ips: &std::path::Path,
-    backup_resolver: &Resolver,
-) -> Result<Vec<IpAddr>, std::io::Error> {
+    backup_resolver: &HostResolver,
+    input: &Opts,
+    unresolved: &mut Vec<String>,
+) -> Result<Vec<Target>, std::io::Error> {
     let file = File::open(ips)?;
     let reader = BufReader::new(file);
 
-    let mut ips: Vec<IpAddr> = Vec::new();
+    let mut targets: Vec<Target> = Vec::new();
 
     for address_line in reader.lines() {
         if let Ok(address) = address_line {
-            ips.extend(parse_address(&address, backup_resolver));
+            match backup_resolver.resolve(&address, range_limit(input)) {
+                Ok(parsed_ips) if !parsed_ips.is_empty() => targets.push(Target {
+                    name: address,
+                    ips: parsed_ips,
+                }),
+                Ok(_) => unresolved.push(address),
+                Err(e) => {
+                    warn(input, e);
+                }
+            }
         } else {
             debug!("Line in file is not valid");
         }
     }
 
-    Ok(ips)
+    Ok(targets)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_resolver, parse_addresses, Opts};
-    use std::net::Ipv4Addr;
+    use super::{
+        get_resolver, hostnames, interleave_ips, is_public, parse_addresses, parse_targets,
+        public_targets, resolve_targets, reverse_lookups, shuffle_ips, HostResolver, Opts, Target,
+        MAX_HOSTS_PER_TARGET,
+    };
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn host_names_resolved_once() {
+        let resolver = HostResolver::new(&Opts::default());
+        let localhost = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+
+        assert_eq!(resolver.resolve("localhost", None), Ok(localhost.clone()));
+        assert_eq!(resolver.resolve("LocalHost", None), Ok(localhost));
+        assert_eq!(resolver.resolve("10.0.0.0/31", None).unwrap().len(), 2);
+        assert_eq!(
+            resolver.cache.borrow().keys().collect::<Vec<_>>(),
+            vec!["localhost"]
+        );
+    }
 
     #[test]
     fn parse_correct_addresses() {
-        let mut opts = Opts::default();
-        opts.addresses = vec!["127.0.0.1".to_owned(), "192.168.0.0/30".to_owned()];
+        let opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned(), "192.168.0.0/30".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
 
         assert_eq!(
@@ -203,10 +876,369 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_ipv6_cidr() {
+        let opts = Opts {
+            addresses: vec!["2001:db8::/126".to_owned()],
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2),
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ip_ranges() {
+        let opts = Opts {
+            addresses: vec![
+                "192.168.1.254-192.168.2.1".to_owned(),
+                "10.0.0.10-12".to_owned(),
+                "2001:db8::ff-2001:db8::100".to_owned(),
+            ],
+            ..Default::default()
+        };
+        let targets = parse_targets(&opts);
+
+        assert_eq!(
+            super::unique_ips(&targets),
+            [
+                IpAddr::from(Ipv4Addr::new(192, 168, 1, 254)),
+                Ipv4Addr::new(192, 168, 1, 255).into(),
+                Ipv4Addr::new(192, 168, 2, 0).into(),
+                Ipv4Addr::new(192, 168, 2, 1).into(),
+                Ipv4Addr::new(10, 0, 0, 10).into(),
+                Ipv4Addr::new(10, 0, 0, 11).into(),
+                Ipv4Addr::new(10, 0, 0, 12).into(),
+                "2001:db8::ff".parse::<Ipv6Addr>().unwrap().into(),
+                "2001:db8::100".parse::<Ipv6Addr>().unwrap().into(),
+            ]
+        );
+        assert!(targets.iter().all(|target| !target.is_hostname()));
+    }
+
+    #[test]
+    fn parse_invalid_ip_ranges() {
+        let opts = Opts {
+            addresses: vec![
+                "10.0.0.12-10".to_owned(),
+                "10.0.0.1-::1".to_owned(),
+                "0.0.0.0-255.255.255.255".to_owned(),
+                "127.0.0.1".to_owned(),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(parse_addresses(&opts), [Ipv4Addr::new(127, 0, 0, 1)]);
+        assert_eq!(
+            super::expand_ip_range(
+                "10.0.0.12".parse().unwrap(),
+                "10.0.0.10".parse().unwrap(),
+                Some(MAX_HOSTS_PER_TARGET)
+            ),
+            Err("the range 10.0.0.12-10.0.0.10 ends before it starts.".to_owned())
+        );
+        assert_eq!(
+            super::expand_ip_range(
+                "10.0.0.0".parse().unwrap(),
+                "10.1.0.0".parse().unwrap(),
+                Some(MAX_HOSTS_PER_TARGET)
+            )
+            .map(|ips| ips.len()),
+            Ok(65_537)
+        );
+        assert_eq!(super::parse_ip_range("my-host"), None);
+    }
+
+    #[test]
+    fn parse_octet_ranges() {
+        let opts = Opts {
+            addresses: vec!["10.0-1.5.1-2".to_owned(), "10.9.9.-1".to_owned()],
+            ..Default::default()
+        };
+        let targets = parse_targets(&opts);
+
+        assert_eq!(
+            super::unique_ips(&targets),
+            [
+                IpAddr::from(Ipv4Addr::new(10, 0, 5, 1)),
+                Ipv4Addr::new(10, 0, 5, 2).into(),
+                Ipv4Addr::new(10, 1, 5, 1).into(),
+                Ipv4Addr::new(10, 1, 5, 2).into(),
+                Ipv4Addr::new(10, 9, 9, 0).into(),
+                Ipv4Addr::new(10, 9, 9, 1).into(),
+            ]
+        );
+        assert!(targets.iter().all(|target| !target.is_hostname()));
+        assert_eq!(
+            super::parse_octet_ranges("192.168.1,3-4,3.*"),
+            Some(Ok(vec![
+                vec![192],
+                vec![168],
+                vec![1, 3, 4],
+                (0..=255).collect()
+            ]))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_octet_ranges() {
+        assert_eq!(super::parse_octet_ranges("10.0.0.1"), None);
+        assert_eq!(super::parse_octet_ranges("my-host.example.com"), None);
+        assert_eq!(super::parse_octet_ranges("10.0.0.1-300"), None);
+        assert_eq!(
+            super::parse_octet_ranges("10.0.5-1.1"),
+            Some(Err(
+                "the octet range 5-1 of 10.0.5-1.1 ends before it starts.".to_owned()
+            ))
+        );
+
+        assert!(super::expand_literal("10.*.*.1-2", Some(1 << 16))
+            .unwrap()
+            .is_err());
+        assert!(super::expand_literal("*.*.*.*", Some(MAX_HOSTS_PER_TARGET))
+            .unwrap()
+            .is_err());
+        assert_eq!(
+            super::expand_literal("10.*.*.1-2", Some(MAX_HOSTS_PER_TARGET))
+                .unwrap()
+                .map(|ips| ips.len()),
+            Ok(131_072)
+        );
+    }
+
+    #[test]
+    fn parse_huge_cidr_refused() {
+        let opts = Opts {
+            addresses: vec!["2001:db8::/64".to_owned(), "127.0.0.1".to_owned()],
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 1)]);
+    }
+
+    #[test]
+    fn parse_large_ipv4_cidr() {
+        let opts = Opts {
+            addresses: vec!["10.0.0.0/15".to_owned()],
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips.len(), 1 << 17);
+    }
+
+    #[test]
+    fn huge_range_limit_configurable() {
+        let opts = Opts {
+            addresses: vec![
+                "2001:db8::/111".to_owned(),
+                "2001:db8:1::/104".to_owned(),
+                "10.0.0.0/14".to_owned(),
+            ],
+            max_range_hosts: 1 << 17,
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips.len(), 1 << 17);
+        assert_eq!(
+            super::expand_literal("2001:db8::1-2001:db8::ff", Some(16)),
+            Some(Err("2001:db8::1-2001:db8::ff expands to 255 hosts, more than the 16 allowed. Use --allow-huge-range or --max-range-hosts to scan it anyway.".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parse_huge_cidr_allowed() {
+        let opts = Opts {
+            addresses: vec!["2001:db8::/111".to_owned()],
+            allow_huge_range: true,
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips.len(), 1 << 17);
+    }
+
+    #[test]
+    fn parse_addresses_with_exclusions() {
+        let opts = Opts {
+            addresses: vec!["192.168.0.0/30".to_owned(), "10.0.0.1".to_owned()],
+            exclude_addresses: Some(vec!["192.168.0.1".to_owned(), "10.0.0.0/8".to_owned()]),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [
+                Ipv4Addr::new(192, 168, 0, 0),
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_everything_excluded() {
+        let opts = Opts {
+            addresses: vec!["192.168.0.0/30".to_owned()],
+            exclude_addresses: Some(vec!["192.168.0.0/24".to_owned(), "not an ip".to_owned()]),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert!(ips.is_empty());
+    }
+
+    #[test]
+    fn parse_targets_keeps_inputs_apart() {
+        let opts = Opts {
+            addresses: vec!["192.168.0.0/31".to_owned(), "192.168.0.1".to_owned()],
+            exclude_addresses: Some(vec!["10.0.0.0/8".to_owned()]),
+            target_file: Some("fixtures/targets.txt".into()),
+            ..Default::default()
+        };
+        let targets = parse_targets(&opts);
+
+        let target = |name: &str, ips: Vec<Ipv4Addr>| Target {
+            name: name.to_owned(),
+            ips: ips.into_iter().map(Into::into).collect(),
+        };
+        assert_eq!(
+            targets,
+            [
+                target(
+                    "192.168.0.0/31",
+                    vec![Ipv4Addr::new(192, 168, 0, 0), Ipv4Addr::new(192, 168, 0, 1)]
+                ),
+                target("192.168.0.1", vec![Ipv4Addr::new(192, 168, 0, 1)]),
+                target("127.0.0.1", vec![Ipv4Addr::new(127, 0, 0, 1)]),
+                target(
+                    "192.168.0.0/31",
+                    vec![Ipv4Addr::new(192, 168, 0, 0), Ipv4Addr::new(192, 168, 0, 1)]
+                ),
+                target("127.0.0.1", vec![Ipv4Addr::new(127, 0, 0, 1)]),
+            ]
+        );
+        assert_eq!(
+            super::overlaps(&targets),
+            [
+                ("192.168.0.0/31", "192.168.0.1", 1),
+                ("192.168.0.0/31", "192.168.0.0/31", 2),
+                ("127.0.0.1", "127.0.0.1", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_single_family() {
+        let addresses = vec![
+            "127.0.0.1".to_owned(),
+            "::1".to_owned(),
+            "10.0.0.0/31".to_owned(),
+        ];
+        let opts = Opts {
+            addresses: addresses.clone(),
+            ipv4_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_addresses(&opts),
+            [
+                Ipv4Addr::new(127, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 0),
+                Ipv4Addr::new(10, 0, 0, 1),
+            ]
+        );
+
+        let opts = Opts {
+            addresses,
+            ipv6_only: true,
+            ..Default::default()
+        };
+        assert_eq!(parse_addresses(&opts), [Ipv6Addr::LOCALHOST]);
+    }
+
+    #[test]
+    fn parse_exclude_file() {
+        let opts = Opts {
+            addresses: vec![
+                "192.168.0.0/30".to_owned(),
+                "10.1.2.3".to_owned(),
+                "127.0.0.1".to_owned(),
+            ],
+            exclude_file: Some("fixtures/exclude.txt".into()),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [
+                Ipv4Addr::new(192, 168, 0, 0),
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_missing_exclude_file() {
+        let opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned()],
+            exclude_file: Some("fixtures/no_such_exclude.txt".into()),
+            ..Default::default()
+        };
+
+        assert!(parse_addresses(&opts).is_empty());
+    }
+
+    #[test]
+    fn parse_target_file() {
+        let opts = Opts {
+            addresses: vec!["192.168.0.1".to_owned()],
+            target_file: Some("fixtures/targets.txt".into()),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [
+                Ipv4Addr::new(192, 168, 0, 1),
+                Ipv4Addr::new(127, 0, 0, 1),
+                Ipv4Addr::new(192, 168, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_missing_target_file() {
+        let opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned()],
+            target_file: Some("fixtures/no_such_targets.txt".into()),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 1)]);
+    }
+
     #[test]
     fn parse_correct_host_addresses() {
-        let mut opts = Opts::default();
-        opts.addresses = vec!["google.com".to_owned()];
+        let opts = Opts {
+            addresses: vec!["google.com".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
 
         assert_eq!(ips.len(), 1);
@@ -214,17 +1246,37 @@ mod tests {
 
     #[test]
     fn parse_correct_and_incorrect_addresses() {
-        let mut opts = Opts::default();
-        opts.addresses = vec!["127.0.0.1".to_owned(), "im_wrong".to_owned()];
+        let opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned(), "im_wrong".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
 
         assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 1),]);
     }
 
+    #[test]
+    fn unresolved_hosts_listed() {
+        let opts = Opts {
+            addresses: vec![
+                "127.0.0.1".to_owned(),
+                "im_wrong".to_owned(),
+                "300.10.1.1".to_owned(),
+            ],
+            ..Default::default()
+        };
+        let (targets, unresolved) = resolve_targets(&opts);
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(unresolved, ["im_wrong", "300.10.1.1"]);
+    }
+
     #[test]
     fn parse_incorrect_addresses() {
-        let mut opts = Opts::default();
-        opts.addresses = vec!["im_wrong".to_owned(), "300.10.1.1".to_owned()];
+        let opts = Opts {
+            addresses: vec!["im_wrong".to_owned(), "300.10.1.1".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
 
         assert!(ips.is_empty());
@@ -232,8 +1284,10 @@ mod tests {
     #[test]
     fn parse_hosts_file_and_incorrect_hosts() {
         // Host file contains IP, Hosts, incorrect IPs, incorrect hosts
-        let mut opts = Opts::default();
-        opts.addresses = vec!["fixtures/hosts.txt".to_owned()];
+        let opts = Opts {
+            addresses: vec!["fixtures/hosts.txt".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
         assert_eq!(ips.len(), 3);
     }
@@ -241,8 +1295,10 @@ mod tests {
     #[test]
     fn parse_empty_hosts_file() {
         // Host file contains IP, Hosts, incorrect IPs, incorrect hosts
-        let mut opts = Opts::default();
-        opts.addresses = vec!["fixtures/empty_hosts.txt".to_owned()];
+        let opts = Opts {
+            addresses: vec!["fixtures/empty_hosts.txt".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
         assert_eq!(ips.len(), 0);
     }
@@ -250,12 +1306,54 @@ mod tests {
     #[test]
     fn parse_naughty_host_file() {
         // Host file contains IP, Hosts, incorrect IPs, incorrect hosts
-        let mut opts = Opts::default();
-        opts.addresses = vec!["fixtures/naughty_string.txt".to_owned()];
+        let opts = Opts {
+            addresses: vec!["fixtures/naughty_string.txt".to_owned()],
+            ..Default::default()
+        };
         let ips = parse_addresses(&opts);
         assert_eq!(ips.len(), 0);
     }
 
+    /// Answers every A query with `answer`, and every other query without
+    /// any record, like a DNS server knowing a single IPv4 host.
+    fn fake_dns_server(answer: Ipv4Addr) -> std::net::SocketAddr {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                // Without EDNS the query ends with its question, the name
+                // followed by the type and class.
+                let qtype = u16::from_be_bytes([buf[len - 4], buf[len - 3]]);
+                let mut reply = buf[..len].to_vec();
+                reply[2] = 0x81; // A response to a recursive query
+                reply[3] = 0x80; // Recursion available, no error
+                if qtype == 1 {
+                    reply[7] = 1; // One answer
+                    reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+                    reply.extend_from_slice(&answer.octets());
+                }
+                let _ = socket.send_to(&reply, peer);
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn dns_server_resolves_targets() {
+        let dns_server = fake_dns_server(Ipv4Addr::new(10, 20, 30, 40));
+        let opts = Opts {
+            addresses: vec!["scanme.internal".to_owned(), "192.168.0.1".to_owned()],
+            dns_server: Some(dns_server),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse_addresses(&opts),
+            [Ipv4Addr::new(10, 20, 30, 40), Ipv4Addr::new(192, 168, 0, 1)]
+        );
+    }
+
     #[test]
     fn resolver_default_cloudflare() {
         let opts = Opts::default();
@@ -269,13 +1367,147 @@ mod tests {
 
     #[test]
     fn resolver_args_google_dns() {
-        let mut opts = Opts::default();
         // https://developers.google.com/speed/public-dns
-        opts.resolver = Some("8.8.8.8,8.8.4.4".to_owned());
+        let opts = Opts {
+            resolver: Some("8.8.8.8,8.8.4.4".to_owned()),
+            ..Default::default()
+        };
 
         let resolver = get_resolver(&opts.resolver);
         let lookup = resolver.lookup_ip("www.example.com.").unwrap();
 
         assert!(lookup.iter().next().is_some());
     }
+
+    #[test]
+    fn public_ips_told_apart() {
+        for ip in [
+            "8.8.8.8",
+            "93.184.216.34",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.0.1",
+            "100.64.0.1",
+            "192.0.2.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "2001:db8::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn public_targets_counted() {
+        let targets = vec![
+            Target {
+                name: "10.0.0.0/31".to_owned(),
+                ips: vec!["10.0.0.0".parse().unwrap(), "10.0.0.1".parse().unwrap()],
+            },
+            Target {
+                name: "example.com".to_owned(),
+                ips: vec![
+                    "93.184.216.34".parse().unwrap(),
+                    "2606:2800:220:1::".parse().unwrap(),
+                    "fd00::1".parse().unwrap(),
+                ],
+            },
+        ];
+
+        assert_eq!(public_targets(&targets), vec![("example.com", 2)]);
+        assert!(public_targets(&targets[..1]).is_empty());
+    }
+
+    #[test]
+    fn reverse_lookups_without_answer() {
+        // Nothing listens on the discard port, so no PTR record comes back.
+        let dns_server = Some("127.0.0.1:9".parse().unwrap());
+        let ips: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "fd00::1".parse().unwrap()];
+
+        let ptrs = reverse_lookups(&ips, &None, dns_server);
+
+        assert_eq!(ptrs.len(), 2);
+        assert!(ptrs.values().all(Option::is_none));
+        assert!(reverse_lookups(&[], &None, dns_server).is_empty());
+    }
+
+    #[test]
+    fn hostnames_of_resolved_targets() {
+        let targets = vec![
+            Target {
+                name: "example.com".to_owned(),
+                ips: vec!["93.184.216.34".parse().unwrap()],
+            },
+            Target {
+                name: "10.0.0.0/31".to_owned(),
+                ips: vec!["10.0.0.0".parse().unwrap(), "10.0.0.1".parse().unwrap()],
+            },
+            Target {
+                name: "www.example.com".to_owned(),
+                ips: vec![
+                    "93.184.216.34".parse().unwrap(),
+                    "10.0.0.1".parse().unwrap(),
+                ],
+            },
+            Target {
+                name: "::1".to_owned(),
+                ips: vec!["::1".parse().unwrap()],
+            },
+        ];
+
+        let hostnames = hostnames(&targets);
+
+        assert_eq!(hostnames.len(), 2);
+        assert_eq!(
+            hostnames[&"93.184.216.34".parse::<IpAddr>().unwrap()],
+            "example.com"
+        );
+        assert_eq!(
+            hostnames[&"10.0.0.1".parse::<IpAddr>().unwrap()],
+            "www.example.com"
+        );
+    }
+
+    #[test]
+    fn shuffle_ips_with_seed() {
+        let ips: Vec<IpAddr> = (0..=255)
+            .map(|i| IpAddr::V4(Ipv4Addr::new(10, 0, 0, i)))
+            .collect();
+        let mut first = ips.clone();
+        let mut second = ips.clone();
+
+        shuffle_ips(&mut first, Some(42));
+        shuffle_ips(&mut second, Some(42));
+
+        assert_eq!(first, second);
+        assert_ne!(first, ips);
+        first.sort_unstable();
+        assert_eq!(first, ips);
+    }
+
+    #[test]
+    fn ips_interleaved_across_subnets() {
+        let ip = |c, d| IpAddr::V4(Ipv4Addr::new(10, 0, c, d));
+        let ips = vec![ip(0, 0), ip(0, 1), ip(0, 2), ip(1, 0), ip(1, 1), ip(2, 0)];
+
+        assert_eq!(
+            interleave_ips(&ips),
+            vec![ip(0, 0), ip(1, 0), ip(2, 0), ip(0, 1), ip(1, 1), ip(0, 2)]
+        );
+    }
 }
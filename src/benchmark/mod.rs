@@ -16,7 +16,8 @@
 //! // Print Benchmark Summary
 //! info!("{}", bm.summary());
 //! ```
-use std::time::Instant;
+use serde_derive::Serialize;
+use std::time::{Duration, Instant};
 
 /// A Benchmark struct to hold NamedTimers with name, start and end Instants,
 #[derive(Debug)]
@@ -42,17 +43,35 @@ impl Benchmark {
         let mut summary = String::from("\nRustScan Benchmark Summary");
 
         for timer in &self.named_timers {
-            if timer.start.is_some() && timer.end.is_some() {
-                let runtime_secs = timer
-                    .end
-                    .unwrap()
-                    .saturating_duration_since(timer.start.unwrap())
-                    .as_secs_f32();
+            if let Some(runtime) = timer.duration() {
+                let runtime_secs = runtime.as_secs_f32();
                 summary.push_str(&format!("\n{0: <10} | {1: <10}s", timer.name, runtime_secs));
             }
         }
         summary
     }
+
+    /// Serializes every finished timer as a `{"name", "duration_ms"}` object,
+    /// meant for tracking scan timings across versions.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let report: Vec<TimerReport> = self
+            .named_timers
+            .iter()
+            .filter_map(|timer| {
+                timer.duration().map(|runtime| TimerReport {
+                    name: timer.name,
+                    duration_ms: runtime.as_millis(),
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&report)
+    }
+}
+
+#[derive(Serialize)]
+struct TimerReport {
+    name: &'static str,
+    duration_ms: u128,
 }
 
 /// The purpose of NamedTimer is to hold a name,
@@ -77,6 +96,14 @@ impl NamedTimer {
     pub fn end(&mut self) {
         self.end = Some(Instant::now());
     }
+
+    /// Time elapsed between start and end, None while the timer is running.
+    pub fn duration(&self) -> Option<Duration> {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => Some(end.saturating_duration_since(start)),
+            _ => None,
+        }
+    }
 }
 
 #[test]
@@ -92,3 +119,19 @@ fn benchmark() {
         .contains("\nRustScan Benchmark Summary\ntest       | 0."));
     assert!(!benchmarks.summary().contains("only_start"));
 }
+
+#[test]
+fn benchmark_json() {
+    let mut benchmarks = Benchmark::init();
+    let mut test_timer = NamedTimer::start("test");
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    test_timer.end();
+    benchmarks.push(test_timer);
+    benchmarks.push(NamedTimer::start("only_start"));
+
+    let report: serde_json::Value = serde_json::from_str(&benchmarks.to_json().unwrap()).unwrap();
+    let timers = report.as_array().unwrap();
+    assert_eq!(timers.len(), 1);
+    assert_eq!(timers[0]["name"], "test");
+    assert!(timers[0]["duration_ms"].as_u64().unwrap() >= 10);
+}
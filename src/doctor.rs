@@ -0,0 +1,231 @@
+//! The checks of `--doctor`, looking at what commonly gets in the way of a
+//! first scan on a new machine: a file limit too low for the batch size, nmap
+//! missing for the default script, or custom scripts that don't parse.
+//!
+//! ```rust
+//! # use rustscan::doctor::find_on_path;
+//! # use std::ffi::OsString;
+//! assert_eq!(find_on_path("nmap", &OsString::new()), None);
+//! ```
+use crate::input::{Opts, ScriptsRequired};
+use crate::scripts::{find_scripts, list_scripts_dir, ScriptConfig, ScriptFile};
+use crate::ulimit;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// Whether the scan is good to go as far as this check goes.
+    pub passed: bool,
+    /// What was found, e.g. `nmap found at /usr/bin/nmap`.
+    pub summary: String,
+    /// What to do about it, for the checks that didn't pass or could do
+    /// better.
+    pub advice: Option<String>,
+}
+
+impl Check {
+    fn passed(summary: String) -> Self {
+        Self {
+            passed: true,
+            summary,
+            advice: None,
+        }
+    }
+
+    fn failed(summary: String, advice: String) -> Self {
+        Self {
+            passed: false,
+            summary,
+            advice: Some(advice),
+        }
+    }
+}
+
+/// Runs every check against the environment the scan in `opts` would run in.
+pub fn run(opts: &Opts) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    #[cfg(unix)]
+    checks.push(match ulimit::file_limit() {
+        Ok(limit) => file_limit_check(limit, opts),
+        Err(e) => Check::failed(
+            format!("can't read the file limit: {e}"),
+            "set the batch size with -b to what the machine allows.".to_owned(),
+        ),
+    });
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    checks.push(nmap_check(find_on_path("nmap", &path), opts.scripts));
+
+    checks.extend(scripts_checks(opts));
+    checks
+}
+
+/// Whether the batch size of `opts` fits the file limit.
+fn file_limit_check(limit: u64, opts: &Opts) -> Check {
+    let recommended = ulimit::recommended_batch_size(limit, opts);
+    let notes = ulimit::batch_size_notes(limit, opts);
+    let summary = format!(
+        "file limit {limit}, batch size {} {}",
+        opts.batch_size,
+        if recommended < opts.batch_size {
+            format!("would be lowered to {recommended}")
+        } else {
+            "fits".to_owned()
+        }
+    );
+    let advice = (!notes.is_empty()).then(|| notes.join(" "));
+    Check {
+        passed: recommended >= opts.batch_size,
+        summary,
+        advice,
+    }
+}
+
+/// The first `program` in the folders of a `PATH` like list.
+pub fn find_on_path(program: &str, path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether nmap is there for the scripts that need it. Without scripts, a
+/// missing nmap doesn't stop anything.
+fn nmap_check(nmap: Option<PathBuf>, scripts: ScriptsRequired) -> Check {
+    match nmap {
+        Some(path) => Check::passed(format!("nmap found at {}", path.display())),
+        None if scripts == ScriptsRequired::None => Check::passed(
+            "nmap not found on PATH, not needed with --scripts none".to_owned(),
+        ),
+        None if scripts == ScriptsRequired::Custom => Check::passed(
+            "nmap not found on PATH, custom scripts calling it will fail".to_owned(),
+        ),
+        None => Check::failed(
+            "nmap not found on PATH, the default script runs it".to_owned(),
+            "install nmap, e.g. `apt install nmap` or `brew install nmap`, or scan with --scripts none.".to_owned(),
+        ),
+    }
+}
+
+/// Whether the custom scripts and their config can be read.
+fn scripts_checks(opts: &Opts) -> Vec<Check> {
+    match opts.scripts {
+        ScriptsRequired::None => return vec![Check::passed("scripts turned off".to_owned())],
+        ScriptsRequired::Default => {
+            return vec![Check::passed(
+                "default script only, no scripts folder to read".to_owned(),
+            )]
+        }
+        ScriptsRequired::Custom => {}
+    }
+
+    let mut checks = Vec::new();
+    let listed = if opts.scripts_dir.is_empty() {
+        match dirs::home_dir() {
+            Some(home) => vec![(home.join(".rustscan_scripts"), find_scripts(home))],
+            None => Vec::new(),
+        }
+    } else {
+        opts.scripts_dir
+            .iter()
+            .map(|dir| (dir.clone(), list_scripts_dir(dir)))
+            .collect()
+    };
+    for (dir, files) in listed {
+        match files {
+            Ok(files) => checks.push(scripts_dir_check(&dir, files)),
+            Err(e) => checks.push(Check::failed(
+                e.to_string(),
+                format!(
+                    "create {} or point --scripts-dir at your scripts.",
+                    dir.display()
+                ),
+            )),
+        }
+    }
+
+    checks.push(match ScriptConfig::read_config() {
+        Ok(_) => Check::passed("script config parses".to_owned()),
+        Err(e) => Check::failed(
+            e.to_string(),
+            "custom scripts need ~/.rustscan_scripts.toml, with at least the tags scripts are picked by, e.g. tags = [\"core_approved\"].".to_owned(),
+        ),
+    });
+    checks
+}
+
+/// Whether every script of a folder has a header that parses.
+fn scripts_dir_check(dir: &Path, files: Vec<PathBuf>) -> Check {
+    let count = files.len();
+    let broken: Vec<String> = files
+        .into_iter()
+        .filter_map(|file| ScriptFile::read(file).err())
+        .map(|e| e.to_string())
+        .collect();
+    if broken.is_empty() {
+        Check::passed(format!("{count} script(s) in {} parse", dir.display()))
+    } else {
+        Check::failed(
+            format!(
+                "{} of {count} script(s) in {} don't parse",
+                broken.len(),
+                dir.display()
+            ),
+            broken.join("\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_limit_check, find_on_path, nmap_check, scripts_dir_check};
+    use crate::input::{Opts, ScriptsRequired};
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn file_limit_against_batch_size() {
+        let opts = Opts {
+            batch_size: 4_500,
+            ..Default::default()
+        };
+        let low = file_limit_check(1_024, &opts);
+        assert!(!low.passed);
+        assert_eq!(
+            low.summary,
+            "file limit 1024, batch size 4500 would be lowered to 512"
+        );
+        assert!(low.advice.unwrap().contains("--ulimit"));
+
+        assert!(file_limit_check(1_000_000, &opts).passed);
+    }
+
+    #[test]
+    fn nmap_looked_up_on_path() {
+        let path = std::env::join_paths(["/nonexistent", "fixtures/.rustscan_scripts"]).unwrap();
+        assert_eq!(
+            find_on_path("test_script.sh", &path),
+            Some(PathBuf::from("fixtures/.rustscan_scripts/test_script.sh"))
+        );
+        assert_eq!(find_on_path("nmap", &OsString::new()), None);
+
+        assert!(!nmap_check(None, ScriptsRequired::Default).passed);
+        assert!(nmap_check(None, ScriptsRequired::None).passed);
+    }
+
+    #[test]
+    fn broken_scripts_reported() {
+        let files = vec![
+            PathBuf::from("fixtures/.rustscan_scripts/test_script.sh"),
+            PathBuf::from("fixtures/.rustscan_scripts/test_script_invalid_headers.txt"),
+        ];
+        let check = scripts_dir_check(Path::new("fixtures"), files);
+        assert!(!check.passed);
+        assert_eq!(check.summary, "1 of 2 script(s) in fixtures don't parse");
+        assert!(check.advice.unwrap().starts_with(
+            "Invalid script header fixtures/.rustscan_scripts/test_script_invalid_headers.txt"
+        ));
+    }
+}
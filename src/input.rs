@@ -1,9 +1,16 @@
 //! Provides a means to read, parse and hold configuration options for scans.
-use clap::{Parser, ValueEnum};
-use serde_derive::Deserialize;
-use std::collections::HashMap;
+use crate::port_strategy::service_port;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use syslog::Facility;
 
 const LOWEST_PORT_NUMBER: u16 = 1;
 const TOP_PORT_NUMBER: u16 = 65535;
@@ -11,10 +18,56 @@ const TOP_PORT_NUMBER: u16 = 65535;
 /// Represents the strategy in which the port scanning will run.
 ///   - Serial will run from start to end, for example 1 to 1_000.
 ///   - Random will randomize the order in which ports will be scanned.
+///   - Reverse will run from end to start, for example 1_000 to 1.
+///   - WeightedRandom randomizes the order too, but common ports tend to be
+///     scanned first.
 #[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
 pub enum ScanOrder {
     Serial,
     Random,
+    Reverse,
+    WeightedRandom,
+}
+
+/// The transport protocols ports can be scanned over.
+#[derive(Deserialize, Serialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// When the output gets colours.
+///   - auto colours what goes to a terminal, unless NO_COLOR is set.
+///   - always colours the output even when piped.
+///   - never prints it plain.
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// How much advice is printed for a host where no open port was found.
+///   - off prints nothing.
+///   - short prints a single line.
+///   - verbose also suggests lowering the batch size or raising the timeout.
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Advice {
+    Off,
+    Short,
+    Verbose,
 }
 
 /// Represents the scripts variant.
@@ -28,6 +81,175 @@ pub enum ScriptsRequired {
     Custom,
 }
 
+/// Represents the format results are written to stdout in.
+///   - text is the human readable output RustScan always had.
+///   - json prints a single JSON document once the scan is done.
+///   - csv prints an `ip,port,state,banner` row per open port once the scan
+///     is done.
+///   - jsonl prints a JSON object per open port as soon as it is found.
+///   - nmap-grepable prints nmap's `-oG` lines once the scan is done.
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Jsonl,
+    #[serde(rename = "nmap-grepable")]
+    NmapGrepable,
+}
+
+/// The syslog severity open ports are logged at with `--syslog`.
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogSeverity {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+/// An nmap style timing template, `-T0` to `-T5`, presetting the timeout,
+/// tries, rate and batch size from the most careful to the fastest.
+///   - Paranoid (0) and Sneaky (1) probe a port at a time or nearly, slowly
+///     enough to stay under most intrusion detection thresholds.
+///   - Polite (2) keeps the load low for fragile hosts and shared links.
+///   - Normal (3) is what the options default to.
+///   - Aggressive (4) and Insane (5) trade accuracy on slow links for speed,
+///     meant for fast and reliable networks.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Timing {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+/// The options a `Timing` template sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingPreset {
+    pub timeout: u32,
+    pub tries: u8,
+    pub max_rate: Option<u32>,
+    pub batch_size: u16,
+}
+
+impl Timing {
+    pub fn preset(self) -> TimingPreset {
+        let (timeout, tries, max_rate, batch_size) = match self {
+            Timing::Paranoid => (5_000, 3, Some(1), 1),
+            Timing::Sneaky => (3_000, 3, Some(10), 10),
+            Timing::Polite => (2_000, 2, Some(100), 100),
+            Timing::Normal => (1_500, 1, None, 4_500),
+            Timing::Aggressive => (1_000, 1, None, 10_000),
+            Timing::Insane => (500, 1, None, 15_000),
+        };
+        TimingPreset {
+            timeout,
+            tries,
+            max_rate,
+            batch_size,
+        }
+    }
+}
+
+/// Parses a `-T` timing template, its number or its name in any case, e.g.
+/// `4` or `aggressive`.
+fn parse_timing(input: &str) -> Result<Timing, String> {
+    match input.to_lowercase().as_str() {
+        "0" | "paranoid" => Ok(Timing::Paranoid),
+        "1" | "sneaky" => Ok(Timing::Sneaky),
+        "2" | "polite" => Ok(Timing::Polite),
+        "3" | "normal" => Ok(Timing::Normal),
+        "4" | "aggressive" => Ok(Timing::Aggressive),
+        "5" | "insane" => Ok(Timing::Insane),
+        _ => Err(format!(
+            "'{input}' is not a timing template, use 0 to 5 or a name like polite or aggressive."
+        )),
+    }
+}
+
+/// The exit code of a run, for scripts and CI jobs to branch on.
+///   - OpenPorts, 0, means the scan completed and found open ports. Dry runs
+///     exit with it as well.
+///   - NoOpenPorts, 1, means the scan completed without finding any.
+///   - UsageError, 2, means the arguments or the configuration are invalid,
+///     nothing was scanned.
+///   - Partial, 3, means the scan was interrupted or reached `--max-time`,
+///     whatever it found was still reported.
+///
+/// A second Ctrl-C quits right away with 130, as shells expect of SIGINT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    OpenPorts = 0,
+    NoOpenPorts = 1,
+    UsageError = 2,
+    Partial = 3,
+}
+
+impl Exit {
+    /// The code of a scan that ran, partial results taking precedence.
+    pub fn of_scan(found_open_ports: bool, partial: bool) -> Self {
+        match (partial, found_open_ports) {
+            (true, _) => Self::Partial,
+            (false, true) => Self::OpenPorts,
+            (false, false) => Self::NoOpenPorts,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+/// A batch size override for the IPs of a CIDR, set in the config file as
+/// `[[host_tuning]]` tables.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HostTuning {
+    pub cidr: String,
+    pub batch_size: u16,
+}
+
+/// Ports only scanned on the hosts where one of `when_open` was found open,
+/// written `PORTS -> PORTS` in the `--ports` format, e.g. `443 -> 8443` or
+/// `80,443 -> 8000-8100`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct PortRule {
+    pub when_open: Vec<u16>,
+    pub ports: Vec<u16>,
+}
+
+impl TryFrom<String> for PortRule {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        parse_port_rule(&rule)
+    }
+}
+
+/// Parses a `--port-rule`, see `PortRule`.
+fn parse_port_rule(input: &str) -> Result<PortRule, String> {
+    let (when_open, ports) = input.split_once("->").ok_or_else(|| {
+        format!("'{input}' is not a port rule, write it PORTS -> PORTS. Example: 443 -> 8443.")
+    })?;
+    Ok(PortRule {
+        when_open: parse_ports(when_open.trim())?,
+        ports: parse_ports(ports.trim())?,
+    })
+}
+
 /// Represents the range of ports to be scanned.
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PortRange {
@@ -35,28 +257,140 @@ pub struct PortRange {
     pub end: u16,
 }
 
-#[cfg(not(tarpaulin_include))]
+/// Parses a single port, rejecting 0 as nothing can listen on it.
+fn parse_port(input: &str) -> Result<u16, String> {
+    match input.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(format!(
+            "'{input}' is not a valid port, ports go from 1 to 65535."
+        )),
+    }
+}
+
+/// Parses a `--knock` sequence, ports separated by commas. Unlike
+/// `parse_ports` the order and repeated ports are kept, both matter to
+/// knock daemons.
+fn parse_knock(input: &str) -> Result<PortList, String> {
+    input.split(',').map(str::trim).map(parse_port).collect()
+}
+
+/// Parses a `--fwmark`, in decimal or in hex with a `0x` prefix as `ip rule`
+/// shows them.
+fn parse_fwmark(input: &str) -> Result<u32, String> {
+    let mark = match input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => input.parse(),
+    };
+    mark.map_err(|_| format!("'{input}' is not a valid firewall mark. Example: 100 or 0x64."))
+}
+
+/// Parses a `start-end` range of ports, both ends included.
 fn parse_range(input: &str) -> Result<PortRange, String> {
-    let range = input
-        .split('-')
-        .map(str::parse)
-        .collect::<Result<Vec<u16>, std::num::ParseIntError>>();
-
-    if range.is_err() {
-        return Err(String::from(
-            "the range format must be 'start-end'. Example: 1-1000.",
+    let Some((start, end)) = input.split_once('-').filter(|(_, end)| !end.contains('-')) else {
+        return Err(format!(
+            "'{input}' is not a valid port range. Example: 1000-2000."
+        ));
+    };
+    let (start, end) = (parse_port(start)?, parse_port(end)?);
+    if start > end {
+        return Err(format!(
+            "the range '{input}' ends before it starts. Example: 1000-2000."
         ));
     }
+    Ok(PortRange { start, end })
+}
 
-    match range.unwrap().as_slice() {
-        [start, end] => Ok(PortRange {
-            start: *start,
-            end: *end,
-        }),
-        _ => Err(String::from(
-            "the range format must be 'start-end'. Example: 1-1000.",
-        )),
+/// Parses a `--dns-server`, an IP with an optional port that defaults to 53.
+/// IPv6 addresses with a port go in brackets, e.g. `[fd00::53]:5353`.
+fn parse_dns_server(input: &str) -> Result<SocketAddr, String> {
+    input
+        .parse()
+        .or_else(|_| input.parse().map(|ip| SocketAddr::new(ip, 53)))
+        .map_err(|_| format!("'{input}' is not a valid DNS server, use IP or IP:PORT."))
+}
+
+/// Parses a `--syslog-facility`, the name of the facility with or without
+/// its `log_` prefix.
+fn parse_syslog_facility(input: &str) -> Result<Facility, String> {
+    Facility::from_str(input).map_err(|()| {
+        format!("'{input}' is not a syslog facility. Example: user, daemon or local0.")
+    })
+}
+
+/// Parses a `--max-time`, a number with an optional `ms`, `s`, `m` or `h`
+/// unit, e.g. `300s` or `5m`. A bare number is in seconds.
+fn parse_max_time(input: &str) -> Result<Duration, String> {
+    let invalid = || format!("'{input}' is not a valid duration. Example: 300s, 5m or 1h.");
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => return Ok(Duration::from_millis(amount)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(invalid()),
+    };
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// A list of ports. Aliased so clap parses `--ports` as a single value
+/// instead of one value per port.
+type PortList = Vec<u16>;
+
+/// Parses a comma separated list of ports, port ranges and the service names
+/// of `SERVICES`, e.g. `22,http,1000-2000`, into a list of ports. Ports
+/// listed more than once are only kept the first time.
+fn parse_ports(input: &str) -> Result<PortList, String> {
+    let mut seen = std::collections::HashSet::new();
+    let mut ports = Vec::new();
+
+    for token in input.split(',').map(str::trim) {
+        // Names can contain dashes too, e.g. netbios-ssn.
+        let token_ports = if token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let port = service_port(token).ok_or_else(|| {
+                format!(
+                    "'{token}' is neither a port nor a known service name. Example: ssh or http."
+                )
+            })?;
+            port..=port
+        } else if token.contains('-') {
+            let range = parse_range(token)?;
+            range.start..=range.end
+        } else {
+            let port = parse_port(token)?;
+            port..=port
+        };
+        ports.extend(token_ports.filter(|port| seen.insert(*port)));
     }
+
+    Ok(ports)
+}
+
+/// Reads a `--ports-file`, any number of ports and port ranges per line in
+/// the `--ports` format. Blank lines and `#` comments are skipped.
+pub fn read_ports_file(path: &Path) -> Result<Vec<u16>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Ports file {path:?} could not be read: {e}"))?;
+
+    let mut lines = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        parse_ports(line).map_err(|e| format!("Line {} of ports file {path:?}: {e}", index + 1))?;
+        lines.push(line);
+    }
+    parse_ports(&lines.join(","))
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -64,7 +398,8 @@ fn parse_range(input: &str) -> Result<PortRange, String> {
     name = "rustscan",
     version = env!("CARGO_PKG_VERSION"),
     max_term_width = 120,
-    help_template = "{bin} {version}\n{about}\n\nUSAGE:\n    {usage}\n\nOPTIONS:\n{options}",
+    help_template = "{bin} {version}\n{about}\n\nUSAGE:\n    {usage}\n\nOPTIONS:\n{options}{after-help}",
+    after_help = "EXIT CODES:\n    0  open ports were found\n    1  no open ports were found\n    2  invalid arguments or configuration\n    3  the scan was interrupted or reached --max-time or --max-findings, results are partial",
 )]
 #[allow(clippy::struct_excessive_bools)]
 /// Fast Port Scanner built in Rust.
@@ -77,9 +412,15 @@ pub struct Opts {
     #[arg(short, long, value_delimiter = ',')]
     pub addresses: Vec<String>,
 
-    /// A list of comma separated ports to be scanned. Example: 80,443,8080.
-    #[arg(short, long, value_delimiter = ',')]
-    pub ports: Option<Vec<u16>>,
+    /// A file with one CIDR, IP or host per line to be scanned alongside the
+    /// addresses. Blank lines and '#' comments are skipped, '-' reads stdin.
+    #[arg(long, value_parser)]
+    pub target_file: Option<PathBuf>,
+
+    /// A list of comma separated ports, port ranges and service names to be
+    /// scanned. Example: 22,80,443,8000-8100 or ssh,http,https.
+    #[arg(short, long, value_parser = parse_ports)]
+    pub ports: Option<PortList>,
 
     /// A range of ports with format start-end. Example: 1-1000.
     #[arg(short, long, conflicts_with = "ports", value_parser = parse_range)]
@@ -93,6 +434,17 @@ pub struct Opts {
     #[arg(short, long, value_parser)]
     pub config_path: Option<PathBuf>,
 
+    /// Abort when the config file contains keys rustscan doesn't know,
+    /// instead of silently ignoring them.
+    #[arg(long)]
+    pub strict_config: bool,
+
+    /// A JSON file with the complete definition of a scan, see `Spec`. Its
+    /// targets, ports, excludes, timeout, tries and scripts override the
+    /// config file and the command line.
+    #[arg(long, value_name = "FILE", value_parser)]
+    pub spec: Option<PathBuf>,
+
     /// Greppable mode. Only output the ports. No Nmap. Useful for grep or outputting to a file.
     #[arg(short, long)]
     pub greppable: bool,
@@ -101,10 +453,97 @@ pub struct Opts {
     #[arg(long)]
     pub accessible: bool,
 
+    /// Quiet mode. Only print results on stdout, leaving out the advice and
+    /// the benchmark summary. Errors still go to stderr.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Verbose mode. Explain what may have gone wrong when no open port
+    /// was found on a host, as --advice verbose does.
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// How much advice to print for every host where no open port was
+    /// found: off, a short line, or verbose with hints on the batch size
+    /// and timeout.
+    #[arg(long, value_enum, value_name = "LEVEL", default_value = "short")]
+    pub advice: Advice,
+
+    /// Only report hosts with at least one open port, skipping the advice
+    /// printed for every host where nothing was found.
+    #[arg(long)]
+    pub open_only: bool,
+
+    /// Only print how many ports are open on every host, as `ip: N open`,
+    /// without listing them or running scripts.
+    #[arg(long)]
+    pub count: bool,
+
+    /// Print a summary per input target, e.g. per CIDR, of how many of its
+    /// hosts had open ports and how many ports were open in total.
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Print every open port found with the number of hosts it is open on,
+    /// the most common ones first.
+    #[arg(long)]
+    pub port_summary: bool,
+
+    /// List every port that wasn't found closed with its state on the port
+    /// lines, e.g. `10.0.0.1 -> [80:open,443:open,53:filtered]`, instead of
+    /// the open ports only, one line per host. In a TCP and UDP scan the UDP
+    /// ports are marked, e.g. `53/udp:open|filtered`. Applies to greppable
+    /// mode and --scripts none.
+    #[arg(long)]
+    pub port_states: bool,
+
+    /// Print statistics at the end of the scan: connection attempts and
+    /// retries, open, closed and filtered counts and the rate achieved.
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Show the host name next to the IPs resolved from one, e.g.
+    /// `example.com (93.184.216.34) -> [80,443]`.
+    #[arg(long)]
+    pub show_hostnames: bool,
+
+    /// Look up the PTR record of the hosts with open ports once the scan is
+    /// done, e.g. `93.184.216.34 (PTR example.com) -> [80,443]`.
+    #[arg(long)]
+    pub resolve_ptr: bool,
+
+    /// Print the resolved targets, batch size, ports and scripts the scan
+    /// would use, then exit without scanning.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print every IP that would be scanned, one per line, once CIDRs and
+    /// ranges are expanded and exclusions applied, then exit without
+    /// scanning. Implies --quiet.
+    #[arg(long)]
+    pub list_targets: bool,
+
+    /// Check the machine RustScan runs on instead of scanning: the file
+    /// limit against the batch size, nmap being on the PATH for the default
+    /// script, and the custom scripts and their config parsing. Exits with 2
+    /// when something needs attention.
+    #[arg(long)]
+    pub doctor: bool,
+
+    /// Skip the scan and run the scripts right away, taking every port given
+    /// with --ports or --ports-file as open. For ports a previous scan found.
+    #[arg(long)]
+    pub only_scripts: bool,
+
     /// A comma-delimited list or file of DNS resolvers.
     #[arg(long)]
     pub resolver: Option<String>,
 
+    /// Resolve the host names to scan with this DNS server only, instead of
+    /// the system resolver. Example: 10.0.0.53 or 10.0.0.53:5353.
+    #[arg(long, value_name = "IP[:PORT]", value_parser = parse_dns_server, conflicts_with = "resolver")]
+    pub dns_server: Option<SocketAddr>,
+
     /// The batch size for port scanning, it increases or slows the speed of
     /// scanning. Depends on the open file limit of your OS.  If you do 65535
     /// it will do every port at the same time. Although, your OS may not
@@ -116,29 +555,150 @@ pub struct Opts {
     #[arg(short, long, default_value = "1500")]
     pub timeout: u32,
 
-    /// The number of tries before a port is assumed to be closed.
-    /// If set to 0, rustscan will correct it to 1.
+    /// The timeout in milliseconds for TCP probes, overriding --timeout.
+    #[arg(long, value_name = "MS")]
+    pub tcp_timeout: Option<u32>,
+
+    /// The timeout in milliseconds for UDP probes, overriding --timeout.
+    /// UDP ports usually need longer to answer, if they answer at all.
+    #[arg(long, value_name = "MS")]
+    pub udp_timeout: Option<u32>,
+
+    /// The shortest timeout in milliseconds any try may get, whatever the
+    /// other timeout options and --retry-backoff come to.
+    #[arg(long, value_name = "MS")]
+    pub min_timeout: Option<u32>,
+
+    /// The longest timeout in milliseconds any try may get, whatever the
+    /// other timeout options and --retry-backoff come to.
+    #[arg(long, value_name = "MS")]
+    pub max_timeout: Option<u32>,
+
+    /// The number of tries before a port is assumed to be closed, 1 being a
+    /// single probe without any retry. If set to 0, rustscan will correct it
+    /// to 1 with a warning.
     #[arg(long, default_value = "1")]
     pub tries: u8,
 
+    /// Stop starting new probes after this long, waiting for the ones in
+    /// flight, and report what was found as partial results. Example: 300s, 5m.
+    #[arg(long, value_name = "DURATION", value_parser = parse_max_time)]
+    pub max_time: Option<Duration>,
+
+    /// Stop starting new probes once this many open ports were found across
+    /// all hosts, and report them as partial results. Handy to confirm that
+    /// some service is up without enumerating them all.
+    #[arg(long, value_name = "N")]
+    pub max_findings: Option<usize>,
+
+    /// Skip the rest of a host once this many of its ports were found open,
+    /// reporting the results as partial.
+    #[arg(long, value_name = "N")]
+    pub max_findings_per_host: Option<usize>,
+
+    /// Multiply the timeout by this factor after every failed try, up to 10
+    /// seconds. Example: -t 1000 --tries 3 --retry-backoff 2 waits 1s, 2s then 4s.
+    #[arg(long)]
+    pub retry_backoff: Option<f64>,
+
+    /// Wait a random delay of up to this many milliseconds before every
+    /// retry, so ports that timed out together aren't retried in one burst.
+    #[arg(long, value_name = "MS")]
+    pub retry_jitter: Option<u64>,
+
+    /// Shrink the batch size while probes time out or fail, and grow it back
+    /// once they stop. Useful against fragile devices.
+    #[arg(long)]
+    pub adaptive: bool,
+
+    /// How long to wait, in milliseconds, before trying a connection again
+    /// that failed for lack of local resources such as free ephemeral ports.
+    /// These retries don't count against --tries. Defaults to 50.
+    #[arg(long, value_name = "MS")]
+    pub local_error_backoff: Option<u64>,
+
+    /// Route every TCP connection through a SOCKS5 proxy, or an HTTP proxy
+    /// tunnelling them with CONNECT. Example: socks5://127.0.0.1:1080 or
+    /// http://proxy.corp:3128.
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// The user:pass an HTTP proxy takes, sent as Basic authentication.
+    #[arg(long, value_name = "USER:PASS", requires = "proxy")]
+    pub proxy_auth: Option<String>,
+
     /// Automatically ups the ULIMIT with the value you provided.
     #[arg(short, long)]
     pub ulimit: Option<u64>,
 
     /// The order of scanning to be performed. The "serial" option will
-    /// scan ports in ascending order while the "random" option will scan
-    /// ports randomly.
+    /// scan ports in ascending order, the "reverse" option in descending
+    /// order, while the "random" option will scan ports randomly. The
+    /// "weighted-random" option is random too, but tends to scan common
+    /// ports first.
     #[arg(long, value_enum, ignore_case = true, default_value = "serial")]
     pub scan_order: ScanOrder,
 
+    /// Seed for the random scan order, so a rerun scans the ports in the
+    /// same sequence. Without it every run is shuffled differently.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Scan the hosts in a random order instead of the input order. Also
+    /// follows --seed.
+    #[arg(long)]
+    pub randomize_hosts: bool,
+
+    /// Scan the hosts round robin across their /24 subnets, so a large
+    /// range is covered evenly and results come from all over it early.
+    /// --randomize-hosts takes precedence.
+    #[arg(long)]
+    pub interleave: bool,
+
     /// Level of scripting required for the run.
     #[arg(long, value_enum, ignore_case = true, default_value = "default")]
     pub scripts: ScriptsRequired,
 
+    /// A folder of scripts to use with --scripts custom instead of
+    /// ~/.rustscan_scripts. Given more than once, a script of a later folder
+    /// replaces the script of the same file name of an earlier one.
+    #[arg(long, value_name = "DIR", value_parser)]
+    pub scripts_dir: Vec<PathBuf>,
+
+    /// Only run the custom scripts tagged with at least one of these tags,
+    /// instead of the ones picked by the tags of the script config.
+    /// Example: http,recon.
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    pub script_tags: Vec<String>,
+
+    /// The number of IPs scripts are run against at the same time.
+    #[arg(long, default_value = "4")]
+    pub script_parallelism: usize,
+
+    /// The number of seconds a script may run before it gets killed.
+    /// Overrides the timeout set in the script files.
+    #[arg(long)]
+    pub script_timeout: Option<u64>,
+
+    /// Print the output of scripts line by line as they write it, prefixed
+    /// with the IP, instead of all at once when each script is done.
+    #[arg(long)]
+    pub stream_scripts: bool,
+
     /// Use the top 1000 ports.
     #[arg(long)]
     pub top: bool,
 
+    /// Scan the N most common ports, most frequent first. Example: --top-ports 100.
+    #[arg(long, conflicts_with_all = ["ports", "range"])]
+    pub top_ports: Option<usize>,
+
+    /// A file listing the ports to scan, in the same format as --ports with
+    /// any number of lines and # comments. Combined with --ports or
+    /// --top-ports, the ports of both are scanned.
+    #[arg(long, value_name = "FILE", value_parser, conflicts_with = "range")]
+    pub ports_file: Option<PathBuf>,
+
     /// The Script arguments to run.
     /// To use the argument -A, end RustScan's args with '-- -A'.
     /// Example: 'rustscan -t 1500 -a 127.0.0.1 -- -A -sC'.
@@ -147,17 +707,255 @@ pub struct Opts {
     #[arg(last = true)]
     pub command: Vec<String>,
 
-    /// A list of comma separated ports to be excluded from scanning. Example: 80,443,8080.
-    #[arg(short, long, value_delimiter = ',')]
-    pub exclude_ports: Option<Vec<u16>>,
+    /// A list of comma separated ports and port ranges to be excluded from
+    /// scanning, in the same format as --ports. Example: 80,443,8000-9000.
+    #[arg(short, long, value_parser = parse_ports)]
+    pub exclude_ports: Option<PortList>,
+
+    /// A list of comma separated IPs or CIDRs to be excluded from scanning. Example: 192.168.0.1,192.168.0.128/25.
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_addresses: Option<Vec<String>>,
+
+    /// A file with one IP, CIDR or host per line to be excluded from scanning.
+    /// Nothing is scanned if it can't be read or one of its hosts doesn't resolve.
+    #[arg(long, value_parser)]
+    pub exclude_file: Option<PathBuf>,
+
+    /// Only scan the IPv4 addresses of the targets, dropping the IPv6 ones
+    /// host names resolve to.
+    #[arg(long, conflicts_with = "ipv6_only")]
+    pub ipv4_only: bool,
+
+    /// Only scan the IPv6 addresses of the targets, dropping the IPv4 ones
+    /// host names resolve to.
+    #[arg(long)]
+    pub ipv6_only: bool,
+
+    /// UDP scanning mode. Ports that answer are open, silent ones are
+    /// reported as open|filtered.
+    #[arg(long)]
+    pub udp: bool,
+
+    /// Scan the ports over each of these protocols in a single run, e.g.
+    /// tcp,udp. Open ports are reported per protocol.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with_all = ["udp", "checkpoint", "resume"]
+    )]
+    pub protocols: Option<Vec<Protocol>>,
+
+    /// Scan these ports over TCP instead of the ones of --ports or --range,
+    /// adding TCP to the protocols scanned. Example: 1-1024.
+    #[arg(
+        long,
+        value_name = "PORTS",
+        value_parser = parse_ports,
+        conflicts_with_all = ["checkpoint", "resume"]
+    )]
+    pub tcp_ports: Option<PortList>,
+
+    /// Scan these ports over UDP instead of the ones of --ports or --range,
+    /// adding UDP to the protocols scanned. Along with --tcp-ports, TCP and
+    /// UDP each get their own ports. Without --protocols, --udp, --ports,
+    /// --range or --top-ports, TCP is only scanned if --tcp-ports is given.
+    /// Example: 53,123,161.
+    #[arg(
+        long,
+        value_name = "PORTS",
+        value_parser = parse_ports,
+        conflicts_with_all = ["checkpoint", "resume"]
+    )]
+    pub udp_ports: Option<PortList>,
+
+    /// Run a TCP ping sweep on ports 80, 443 and 22 first and only scan the
+    /// hosts that answered it.
+    #[arg(long, conflicts_with = "no_ping")]
+    pub ping_first: bool,
+
+    /// Knock on these ports of every host, in this order, before scanning
+    /// it. For hosts behind a port knock daemon. Example: 1111,2222,3333.
+    #[arg(long, value_name = "PORTS", value_parser = parse_knock)]
+    pub knock: Option<PortList>,
+
+    /// Only scan some ports on the hosts where a prerequisite port was found
+    /// open, in a second wave once the other ports are done. Can be given
+    /// more than once. Example: "443 -> 8443" or "80,443 -> 8000-8100".
+    #[arg(long = "port-rule", value_name = "RULE", value_parser = parse_port_rule)]
+    pub port_rules: Vec<PortRule>,
+
+    /// Scan every host without a ping sweep, the default. Overrides a
+    /// `ping_first` set in the config file.
+    #[arg(long)]
+    pub no_ping: bool,
+
+    /// The format results are printed in. "json", "csv", "jsonl" and
+    /// "nmap-grepable" keep stdout free of anything but the results, imply
+    /// greppable mode and skip scripts. "jsonl" prints every open port on its
+    /// own line right away, "nmap-grepable" the lines of nmap's -oG.
+    #[arg(long, value_enum, ignore_case = true, default_value = "text")]
+    pub output_format: OutputFormat,
+
+    /// When to colour the output: auto colours it on a terminal unless
+    /// NO_COLOR is set, always and never don't look. --accessible implies
+    /// never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// The maximum number of connection attempts started per second,
+    /// regardless of the batch size. Useful on shared or metered networks.
+    #[arg(long)]
+    pub max_rate: Option<u32>,
+
+    /// A timing template from 0 (paranoid) to 5 (insane), like nmap's -T,
+    /// presetting the timeout, tries, max rate and batch size. Each of them
+    /// given on its own, on the command line or in the config, still wins.
+    /// Example: -T4 or -T polite.
+    #[arg(short = 'T', long, value_name = "TEMPLATE", value_parser = parse_timing)]
+    pub timing: Option<Timing>,
+
+    /// The maximum number of IPs scanned at the same time. Their ports are
+    /// still batched, and the next IP only starts when one is done.
+    #[arg(long, value_name = "N")]
+    pub max_hosts: Option<usize>,
+
+    /// The most hosts a CIDR, IP range or octet range may expand to. The
+    /// default of 16777216 is a /8 for IPv4 and a /104 for IPv6.
+    #[arg(long, value_name = "N", default_value = "16777216")]
+    pub max_range_hosts: u64,
+
+    /// Allow CIDRs and ranges of any size, lifting --max-range-hosts.
+    #[arg(long)]
+    pub allow_huge_range: bool,
+
+    /// Abort before scanning when any host name fails to resolve, listing
+    /// them, instead of scanning the targets that did.
+    #[arg(long)]
+    pub require_all_resolve: bool,
+
+    /// Scan targets with public IPs without asking first. Otherwise the scan
+    /// waits for a confirmation when run from a terminal, and warns when
+    /// it isn't.
+    #[arg(long)]
+    pub allow_public: bool,
+
+    /// Also write the results as Nmap compatible XML to the given path.
+    #[arg(long, value_parser)]
+    pub output_xml: Option<PathBuf>,
+
+    /// Append every open ip:port to the given file as soon as it is found,
+    /// so results survive an interrupted scan.
+    #[arg(long, value_parser)]
+    pub output_file: Option<PathBuf>,
+
+    /// Write Prometheus metrics to the given path once the scan is done:
+    /// the open ports per host, the hosts up and the scan duration. Meant
+    /// for the textfile collector of node_exporter.
+    #[arg(long, value_name = "PATH", value_parser)]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Also send every open port to the local syslog daemon as it is found,
+    /// one line of key=value pairs per port.
+    #[arg(long)]
+    pub syslog: bool,
+
+    /// The syslog facility of --syslog, e.g. user, daemon or local0 to local7.
+    #[arg(long, value_name = "FACILITY", default_value = "user", value_parser = parse_syslog_facility)]
+    pub syslog_facility: Facility,
+
+    /// The syslog severity of --syslog.
+    #[arg(long, value_enum, ignore_case = true, default_value = "notice")]
+    pub syslog_severity: SyslogSeverity,
+
+    /// The local address to send every probe from, on hosts with several
+    /// interfaces. It has to be one of this host's addresses.
+    #[arg(long, value_name = "IP", conflicts_with = "proxy")]
+    pub source_address: Option<IpAddr>,
+
+    /// The network interface to send every probe through, e.g. tun0, from
+    /// its own addresses. On Linux the sockets are bound to it as well when
+    /// permitted, which takes CAP_NET_RAW.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["proxy", "source_address"])]
+    pub interface: Option<String>,
+
+    /// The firewall mark (SO_MARK) to set on every probe socket, in decimal
+    /// or 0x hex, so `ip rule` policy routing can send the scan through a
+    /// given table or uplink. Linux only, takes CAP_NET_ADMIN.
+    #[arg(long, value_name = "MARK", conflicts_with = "proxy", value_parser = parse_fwmark)]
+    pub fwmark: Option<u32>,
+
+    /// Send the probes from the local ports of the given range in turn,
+    /// for firewalls that only let some source ports through.
+    /// Example: 40000-60000.
+    #[arg(long, value_name = "START-END", value_parser = parse_range, conflicts_with = "proxy")]
+    pub local_port_range: Option<PortRange>,
+
+    /// Write the scanned sockets to the given checkpoint file every few
+    /// seconds and when the scan ends, so it can be picked up with --resume.
+    #[arg(long, value_name = "FILE", value_parser, conflicts_with = "resume")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Resume the scan saved in the given checkpoint file, skipping the
+    /// sockets it already went through. The file is kept up to date as the
+    /// scan goes on, and is started anew if it doesn't exist.
+    #[arg(long, value_name = "FILE", value_parser)]
+    pub resume: Option<PathBuf>,
+
+    /// Write the benchmark timings in milliseconds as JSON to the given path
+    /// instead of printing the benchmark summary.
+    #[arg(long, value_parser)]
+    pub benchmark_json: Option<PathBuf>,
+
+    /// Make this many throwaway TCP connects to the first sockets of the
+    /// scan before the Portscan benchmark starts, so it measures a warm
+    /// steady state rather than cold caches. It gets its own --max-time,
+    /// which the scan doesn't lose any of. Off with 0.
+    #[arg(long, value_name = "N", default_value = "0")]
+    pub warmup: usize,
+
+    /// Grab the banner of every open TCP port, reading up to the given
+    /// number of bytes (256 by default).
+    #[arg(long, value_name = "BYTES", num_args = 0..=1, default_missing_value = "256")]
+    pub banner: Option<usize>,
+
+    /// Attempt a TLS handshake with every open TCP port and report the
+    /// negotiated TLS version and ALPN protocol of the ones that speak TLS.
+    #[arg(long)]
+    pub tls_probe: bool,
+
+    /// Show a live progress bar on stderr. Ignored in greppable and
+    /// accessible modes.
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Batch size overrides for the IPs of some CIDRs, only set from the
+    /// config file.
+    #[arg(skip)]
+    pub host_tuning: Option<Vec<HostTuning>>,
+
+    /// The options given on the command line, by field name. Neither the
+    /// environment nor the config file override them.
+    #[arg(skip)]
+    pub cli_args: HashSet<String>,
 }
 
 #[cfg(not(tarpaulin_include))]
 impl Opts {
     pub fn read() -> Self {
-        let mut opts = Opts::parse();
+        let matches = Opts::command().get_matches();
+        let mut opts = Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        opts.cli_args = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(ToString::to_string)
+            .collect();
 
-        if opts.ports.is_none() && opts.range.is_none() {
+        if opts.ports.is_none()
+            && opts.range.is_none()
+            && opts.top_ports.is_none()
+            && opts.ports_file.is_none()
+        {
             opts.range = Some(PortRange {
                 start: LOWEST_PORT_NUMBER,
                 end: TOP_PORT_NUMBER,
@@ -169,11 +967,61 @@ impl Opts {
 
     /// Reads the command line arguments into an Opts struct and merge
     /// values found within the user configuration file.
+    ///
+    /// Precedence, highest first:
+    ///   1. options given on the command line, see `cli_args`,
+    ///   2. RUSTSCAN_* environment variables, see `Config::from_env`,
+    ///   3. the config files layered by `Config::read`, unless --no-config
+    ///      is given,
+    ///   4. the defaults of the command line options.
     pub fn merge(&mut self, config: &Config) {
+        self.merge_layers(config, &Config::from_env());
+    }
+
+    /// Merges the config file and then the environment on top of it, so the
+    /// latter wins. Neither touches the options set on the command line.
+    fn merge_layers(&mut self, config: &Config, env: &Config) {
+        // The template goes under the layers, which still override the
+        // options it presets one by one.
+        if !self.cli_args.contains("timing") {
+            let config_timing = if self.no_config { None } else { config.timing };
+            if let Some(timing) = env.timing.or(config_timing) {
+                self.timing = Some(timing);
+            }
+        }
+        self.apply_timing();
+
         if !self.no_config {
             self.merge_required(config);
             self.merge_optional(config);
         }
+        self.merge_required(env);
+        self.merge_optional(env);
+
+        if self.no_ping {
+            self.ping_first = false;
+        }
+    }
+
+    /// Sets the options of the timing template, leaving out the ones given
+    /// on the command line.
+    fn apply_timing(&mut self) {
+        let Some(timing) = self.timing else {
+            return;
+        };
+        let preset = timing.preset();
+        if !self.cli_args.contains("timeout") {
+            self.timeout = preset.timeout;
+        }
+        if !self.cli_args.contains("tries") {
+            self.tries = preset.tries;
+        }
+        if !self.cli_args.contains("max_rate") {
+            self.max_rate = preset.max_rate;
+        }
+        if !self.cli_args.contains("batch_size") {
+            self.batch_size = preset.batch_size;
+        }
     }
 
     fn merge_required(&mut self, config: &Config) {
@@ -181,15 +1029,52 @@ impl Opts {
             ($($field: ident),+) => {
                 $(
                     if let Some(e) = &config.$field {
-                        self.$field = e.clone();
+                        if !self.cli_args.contains(stringify!($field)) {
+                            self.$field = e.clone();
+                        }
                     }
                 )+
             }
         }
 
         merge_required!(
-            addresses, greppable, accessible, batch_size, timeout, tries, scan_order, scripts,
-            command
+            addresses,
+            greppable,
+            accessible,
+            quiet,
+            verbose,
+            advice,
+            open_only,
+            count,
+            summary,
+            port_summary,
+            port_states,
+            stats,
+            show_hostnames,
+            resolve_ptr,
+            batch_size,
+            timeout,
+            tries,
+            scan_order,
+            randomize_hosts,
+            interleave,
+            scripts,
+            scripts_dir,
+            script_tags,
+            port_rules,
+            command,
+            udp,
+            ping_first,
+            adaptive,
+            tls_probe,
+            progress,
+            output_format,
+            color,
+            script_parallelism,
+            stream_scripts,
+            ipv4_only,
+            ipv6_only,
+            require_all_resolve
         );
     }
 
@@ -197,7 +1082,7 @@ impl Opts {
         macro_rules! merge_optional {
             ($($field: ident),+) => {
                 $(
-                    if config.$field.is_some() {
+                    if config.$field.is_some() && !self.cli_args.contains(stringify!($field)) {
                         self.$field = config.$field.clone();
                     }
                 )+
@@ -205,7 +1090,7 @@ impl Opts {
         }
 
         // Only use top ports when the user asks for them
-        if self.top && config.ports.is_some() {
+        if self.top && config.ports.is_some() && !self.cli_args.contains("ports") {
             let mut ports: Vec<u16> = Vec::with_capacity(config.ports.clone().unwrap().len());
             for entry in config.ports.clone().unwrap().keys() {
                 ports.push(entry.parse().unwrap());
@@ -213,7 +1098,36 @@ impl Opts {
             self.ports = Some(ports);
         }
 
-        merge_optional!(range, resolver, ulimit, exclude_ports);
+        merge_optional!(
+            range,
+            resolver,
+            ulimit,
+            exclude_ports,
+            exclude_addresses,
+            exclude_file,
+            max_rate,
+            max_hosts,
+            top_ports,
+            ports_file,
+            script_timeout,
+            target_file,
+            banner,
+            tcp_timeout,
+            udp_timeout,
+            min_timeout,
+            max_timeout,
+            retry_backoff,
+            retry_jitter,
+            proxy,
+            proxy_auth,
+            local_error_backoff,
+            source_address,
+            interface,
+            fwmark,
+            local_port_range,
+            host_tuning,
+            seed
+        );
     }
 }
 
@@ -221,22 +1135,104 @@ impl Default for Opts {
     fn default() -> Self {
         Self {
             addresses: vec![],
+            target_file: None,
             ports: None,
             range: None,
             greppable: true,
             batch_size: 0,
             timeout: 0,
+            tcp_timeout: None,
+            udp_timeout: None,
+            min_timeout: None,
+            max_timeout: None,
             tries: 0,
             ulimit: None,
             command: vec![],
             accessible: false,
+            quiet: false,
+            verbose: false,
+            advice: Advice::Short,
+            open_only: false,
+            count: false,
+            summary: false,
+            port_summary: false,
+            port_states: false,
+            stats: false,
+            show_hostnames: false,
+            resolve_ptr: false,
+            dry_run: false,
+            list_targets: false,
+            doctor: false,
+            only_scripts: false,
             resolver: None,
+            dns_server: None,
+            max_time: None,
+            max_findings: None,
+            max_findings_per_host: None,
             scan_order: ScanOrder::Serial,
             no_config: true,
             top: false,
+            top_ports: None,
+            ports_file: None,
             scripts: ScriptsRequired::Default,
+            scripts_dir: vec![],
+            script_tags: vec![],
+            script_parallelism: 4,
+            script_timeout: None,
+            stream_scripts: false,
+            ipv4_only: false,
+            ipv6_only: false,
+            require_all_resolve: false,
+            host_tuning: None,
             config_path: None,
+            strict_config: false,
+            spec: None,
             exclude_ports: None,
+            exclude_addresses: None,
+            exclude_file: None,
+            udp: false,
+            protocols: None,
+            tcp_ports: None,
+            udp_ports: None,
+            ping_first: false,
+            knock: None,
+            port_rules: vec![],
+            no_ping: false,
+            output_format: OutputFormat::Text,
+            color: ColorChoice::Auto,
+            max_rate: None,
+            timing: None,
+            max_hosts: None,
+            max_range_hosts: crate::address::MAX_HOSTS_PER_TARGET,
+            allow_huge_range: false,
+            allow_public: false,
+            output_xml: None,
+            output_file: None,
+            metrics_file: None,
+            syslog: false,
+            syslog_facility: Facility::LOG_USER,
+            syslog_severity: SyslogSeverity::Notice,
+            source_address: None,
+            interface: None,
+            fwmark: None,
+            local_port_range: None,
+            local_error_backoff: None,
+            checkpoint: None,
+            resume: None,
+            benchmark_json: None,
+            warmup: 0,
+            banner: None,
+            tls_probe: false,
+            progress: false,
+            retry_backoff: None,
+            retry_jitter: None,
+            adaptive: false,
+            proxy: None,
+            proxy_auth: None,
+            seed: None,
+            randomize_hosts: false,
+            interleave: false,
+            cli_args: HashSet::new(),
         }
     }
 }
@@ -252,15 +1248,68 @@ pub struct Config {
     range: Option<PortRange>,
     greppable: Option<bool>,
     accessible: Option<bool>,
+    quiet: Option<bool>,
+    verbose: Option<bool>,
+    advice: Option<Advice>,
+    open_only: Option<bool>,
+    count: Option<bool>,
+    summary: Option<bool>,
+    port_summary: Option<bool>,
+    port_states: Option<bool>,
+    stats: Option<bool>,
+    show_hostnames: Option<bool>,
+    resolve_ptr: Option<bool>,
     batch_size: Option<u16>,
     timeout: Option<u32>,
+    tcp_timeout: Option<u32>,
+    udp_timeout: Option<u32>,
+    min_timeout: Option<u32>,
+    max_timeout: Option<u32>,
     tries: Option<u8>,
     ulimit: Option<u64>,
     resolver: Option<String>,
     scan_order: Option<ScanOrder>,
     command: Option<Vec<String>>,
     scripts: Option<ScriptsRequired>,
+    scripts_dir: Option<Vec<PathBuf>>,
+    script_tags: Option<Vec<String>>,
+    port_rules: Option<Vec<PortRule>>,
     exclude_ports: Option<Vec<u16>>,
+    exclude_addresses: Option<Vec<String>>,
+    exclude_file: Option<PathBuf>,
+    udp: Option<bool>,
+    ping_first: Option<bool>,
+    output_format: Option<OutputFormat>,
+    color: Option<ColorChoice>,
+    max_rate: Option<u32>,
+    timing: Option<Timing>,
+    max_hosts: Option<usize>,
+    top_ports: Option<usize>,
+    ports_file: Option<PathBuf>,
+    script_parallelism: Option<usize>,
+    script_timeout: Option<u64>,
+    stream_scripts: Option<bool>,
+    ipv4_only: Option<bool>,
+    ipv6_only: Option<bool>,
+    require_all_resolve: Option<bool>,
+    host_tuning: Option<Vec<HostTuning>>,
+    target_file: Option<PathBuf>,
+    banner: Option<usize>,
+    tls_probe: Option<bool>,
+    progress: Option<bool>,
+    retry_backoff: Option<f64>,
+    retry_jitter: Option<u64>,
+    adaptive: Option<bool>,
+    proxy: Option<String>,
+    proxy_auth: Option<String>,
+    source_address: Option<IpAddr>,
+    interface: Option<String>,
+    fwmark: Option<u32>,
+    local_port_range: Option<PortRange>,
+    local_error_backoff: Option<u64>,
+    seed: Option<u64>,
+    randomize_hosts: Option<bool>,
+    interleave: Option<bool>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -277,25 +1326,234 @@ impl Config {
     /// scan_order: "Serial"
     /// exclude_ports = [8080, 9090, 80]
     ///
-    pub fn read(custom_config_path: Option<PathBuf>) -> Self {
-        let mut content = String::new();
-        let config_path = custom_config_path.unwrap_or_else(default_config_path);
-        if config_path.exists() {
-            content = match fs::read_to_string(config_path) {
-                Ok(content) => content,
-                Err(_) => String::new(),
+    /// With `strict` set, unknown keys abort the scan like invalid values do.
+    ///
+    /// Without a custom path, the files of `default_config_paths` are layered
+    /// on top of each other: a key set in a later file overrides the earlier
+    /// ones. Missing files are skipped.
+    pub fn read(custom_config_path: Option<PathBuf>, strict: bool) -> Self {
+        let config_paths = match custom_config_path {
+            Some(config_path) => vec![config_path],
+            None => default_config_paths(),
+        };
+
+        match Self::read_layers(&config_paths, strict) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Found {e}.\nAborting scan.\n");
+                Exit::UsageError.exit();
             }
         }
+    }
+
+    /// Reads and merges the config files that exist among `config_paths`,
+    /// later files taking precedence. Every file is checked on its own, so
+    /// errors point at the right file and line.
+    fn read_layers(config_paths: &[PathBuf], strict: bool) -> Result<Self, String> {
+        let mut merged = toml::Table::new();
+        for config_path in config_paths.iter().filter(|path| path.exists()) {
+            let content = fs::read_to_string(config_path).unwrap_or_default();
+            let in_file =
+                |e: String| format!("{e} in configuration file {}", config_path.display());
+            Self::parse(&content, strict).map_err(in_file)?;
+            let table = content
+                .parse::<toml::Table>()
+                .map_err(|e| in_file(e.to_string()))?;
+            merged.extend(table);
+        }
 
-        let config: Config = match toml::from_str(&content) {
+        Self::parse(&merged.to_string(), false)
+    }
+
+    /// Reads the RUSTSCAN_* environment variables into a Config, each named
+    /// after the config file key it sets, e.g. RUSTSCAN_BATCH_SIZE=1000 or
+    /// RUSTSCAN_EXCLUDE_PORTS=[80,443]. Values that aren't valid TOML are
+    /// taken as strings, so RUSTSCAN_RESOLVER=1.1.1.1 needs no quotes.
+    pub fn from_env() -> Self {
+        match Self::from_vars(std::env::vars()) {
             Ok(config) => config,
             Err(e) => {
-                println!("Found {e} in configuration file.\nAborting scan.\n");
-                std::process::exit(1);
+                println!("Found {e} in RUSTSCAN_* environment variables.\nAborting scan.\n");
+                Exit::UsageError.exit();
             }
+        }
+    }
+
+    fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Result<Self, String> {
+        let mut content = String::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix("RUSTSCAN_") else {
+                continue;
+            };
+            let key = key.to_lowercase();
+            let line = format!("{key} = {value}\n");
+            if line.parse::<toml::Table>().is_ok() {
+                content.push_str(&line);
+            } else {
+                content.push_str(&format!("{key} = {}\n", toml::Value::String(value)));
+            }
+        }
+        Self::parse(&content, false)
+    }
+
+    /// Parses the content of a configuration file. Type mismatches are
+    /// always an error, unknown keys only when `strict` is set.
+    fn parse(content: &str, strict: bool) -> Result<Self, String> {
+        let mut unknown_keys = Vec::new();
+        let config: Config = serde_ignored::deserialize(toml::Deserializer::new(content), |path| {
+            unknown_keys.push(path.to_string());
+        })
+        .map_err(|e| e.to_string())?;
+
+        match unknown_keys.first() {
+            Some(key) if strict => Err(match key_line(content, key) {
+                Some(line) => format!("unknown key `{key}` at line {line}"),
+                None => format!("unknown key `{key}`"),
+            }),
+            _ => Ok(config),
+        }
+    }
+}
+
+/// The 1-based line a top level key is set on, for error messages.
+fn key_line(content: &str, key: &str) -> Option<usize> {
+    let key = key.split('.').next().unwrap_or(key);
+    content
+        .lines()
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        })
+        .map(|index| index + 1)
+}
+
+/// A complete scan definition read from a JSON file with `--spec`, to be
+/// committed alongside the project and rerun identically. Unlike the config
+/// file, which holds defaults, the keys it sets win over the command line.
+///
+/// # Format
+///
+/// ```json
+/// {
+///     "addresses": ["10.0.0.0/24", "example.com"],
+///     "ports": "22,80,8000-8100",
+///     "exclude_ports": "8080,8090-8099",
+///     "exclude_addresses": ["10.0.0.1"],
+///     "timeout": 2000,
+///     "tries": 2,
+///     "scripts": "None",
+///     "command": ["-sV"]
+/// }
+/// ```
+///
+/// `ports` and `exclude_ports` take the `--ports` format, `range` is an
+/// object like `{"start": 1, "end": 1000}` instead. Every key is optional,
+/// unknown keys are an error.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub addresses: Option<Vec<String>>,
+    pub ports: Option<String>,
+    pub range: Option<PortRange>,
+    pub exclude_ports: Option<String>,
+    pub exclude_addresses: Option<Vec<String>>,
+    pub timeout: Option<u32>,
+    pub tries: Option<u8>,
+    pub scripts: Option<ScriptsRequired>,
+    pub command: Option<Vec<String>>,
+}
+
+impl Spec {
+    /// Reads the spec file at `path`, aborting the scan if it can't be read
+    /// or isn't a valid spec.
+    #[cfg(not(tarpaulin_include))]
+    pub fn read(path: &Path) -> Self {
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| Self::parse(&content))
+        {
+            Ok(spec) => spec,
+            Err(e) => {
+                println!(
+                    "Found {e} in spec file {}.\nAborting scan.\n",
+                    path.display()
+                );
+                Exit::UsageError.exit();
+            }
+        }
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let spec: Spec = serde_json::from_str(content).map_err(|e| e.to_string())?;
+        if spec.ports.is_some() && spec.range.is_some() {
+            return Err("both ports and range".to_owned());
+        }
+        for ports in spec.ports.iter().chain(&spec.exclude_ports) {
+            parse_ports(ports)?;
+        }
+        if let Some(range) = &spec.range {
+            parse_range(&format!("{}-{}", range.start, range.end))?;
+        }
+        Ok(spec)
+    }
+}
+
+impl Opts {
+    /// Whether the ports to scan were picked, by an option, the config or a
+    /// spec, rather than left to the full range `read` falls back to.
+    pub fn ports_given(&self) -> bool {
+        let full_range = PortRange {
+            start: LOWEST_PORT_NUMBER,
+            end: TOP_PORT_NUMBER,
         };
+        self.ports.is_some()
+            || self.top_ports.is_some()
+            || self.cli_args.contains("range")
+            || self
+                .range
+                .as_ref()
+                .is_some_and(|range| *range != full_range)
+    }
 
-        config
+    /// Overrides the options `spec` sets. Ports and a range replace each
+    /// other, as well as the top ports and ports file, so the spec alone
+    /// decides what is scanned.
+    pub fn apply_spec(&mut self, spec: Spec) {
+        if let Some(addresses) = spec.addresses {
+            self.addresses = addresses;
+            self.target_file = None;
+        }
+        if let Some(ports) = spec.ports {
+            self.ports = parse_ports(&ports).ok();
+            self.range = None;
+            self.top_ports = None;
+            self.ports_file = None;
+        }
+        if let Some(range) = spec.range {
+            self.range = Some(range);
+            self.ports = None;
+            self.top_ports = None;
+            self.ports_file = None;
+        }
+        if let Some(exclude_ports) = spec.exclude_ports {
+            self.exclude_ports = parse_ports(&exclude_ports).ok();
+        }
+        if spec.exclude_addresses.is_some() {
+            self.exclude_addresses = spec.exclude_addresses;
+        }
+        if let Some(timeout) = spec.timeout {
+            self.timeout = timeout;
+        }
+        if let Some(tries) = spec.tries {
+            self.tries = tries;
+        }
+        if let Some(scripts) = spec.scripts {
+            self.scripts = scripts;
+        }
+        if let Some(command) = spec.command {
+            self.command = command;
+        }
     }
 }
 
@@ -308,12 +1566,33 @@ pub fn default_config_path() -> PathBuf {
     config_path
 }
 
+/// The config files read when no custom path is given, from the lowest to
+/// the highest precedence:
+///   1. the system wide `/etc/rustscan.toml`, on unix only,
+///   2. the user's `~/.rustscan.toml`,
+///   3. the project's `.rustscan.toml` in the current directory.
+pub fn default_config_paths() -> Vec<PathBuf> {
+    vec![
+        #[cfg(unix)]
+        PathBuf::from("/etc/rustscan.toml"),
+        default_config_path(),
+        PathBuf::from(".rustscan.toml"),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use clap::{CommandFactory, Parser};
     use parameterized::parameterized;
+    use std::path::Path;
+    use std::time::Duration;
 
-    use super::{Config, Opts, PortRange, ScanOrder, ScriptsRequired};
+    use super::{
+        parse_dns_server, parse_fwmark, parse_knock, parse_max_time, parse_port_rule, parse_ports,
+        parse_range, parse_syslog_facility, parse_timing, read_ports_file, Config, Exit,
+        HostTuning, Opts, PortRange, PortRule, ScanOrder, ScriptsRequired, Spec, Timing,
+    };
+    use syslog::Facility;
 
     impl Config {
         fn default() -> Self {
@@ -324,14 +1603,67 @@ mod tests {
                 greppable: Some(true),
                 batch_size: Some(25_000),
                 timeout: Some(1_000),
+                tcp_timeout: None,
+                udp_timeout: None,
+                min_timeout: None,
+                max_timeout: None,
                 tries: Some(1),
                 ulimit: None,
                 command: Some(vec!["-A".to_owned()]),
                 accessible: Some(true),
+                quiet: None,
+                verbose: None,
+                advice: None,
+                open_only: None,
+                count: None,
+                summary: None,
+                port_summary: None,
+                port_states: None,
+                stats: None,
+                show_hostnames: None,
+                resolve_ptr: None,
                 resolver: None,
                 scan_order: Some(ScanOrder::Random),
                 scripts: None,
+                scripts_dir: None,
+                script_tags: None,
+                port_rules: None,
                 exclude_ports: None,
+                exclude_addresses: None,
+                exclude_file: None,
+                udp: Some(false),
+                ping_first: None,
+                output_format: None,
+                color: None,
+                max_rate: None,
+                timing: None,
+                max_hosts: None,
+                top_ports: None,
+                ports_file: None,
+                script_parallelism: None,
+                script_timeout: None,
+                stream_scripts: None,
+                ipv4_only: None,
+                ipv6_only: None,
+                require_all_resolve: None,
+                host_tuning: None,
+                target_file: None,
+                banner: None,
+                tls_probe: None,
+                progress: None,
+                retry_backoff: None,
+                retry_jitter: None,
+                adaptive: None,
+                proxy: None,
+                proxy_auth: None,
+                source_address: None,
+                interface: None,
+                fwmark: None,
+                local_port_range: None,
+                local_error_backoff: None,
+                seed: None,
+                randomize_hosts: None,
+                interleave: None,
             }
         }
     }
@@ -409,4 +1741,497 @@ mod tests {
         assert_eq!(opts.ulimit, config.ulimit);
         assert_eq!(opts.resolver, config.resolver);
     }
+
+    #[test]
+    fn config_unknown_keys() {
+        let content = "greppable = true\nbatchsize = 100\n";
+
+        let config = Config::parse(content, false).unwrap();
+        assert_eq!(config.greppable, Some(true));
+
+        assert_eq!(
+            Config::parse(content, true).unwrap_err(),
+            "unknown key `batchsize` at line 2"
+        );
+    }
+
+    #[test]
+    fn config_type_mismatch() {
+        for strict in [false, true] {
+            let e = Config::parse("timeout = \"fast\"\n", strict).unwrap_err();
+            assert!(e.contains("line 1"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn opts_no_ping_overrides_config() {
+        let mut opts = Opts {
+            no_ping: true,
+            ..Default::default()
+        };
+        let config = Config {
+            ping_first: Some(true),
+            ..Config::default()
+        };
+
+        opts.merge(&config);
+
+        assert!(!opts.ping_first);
+    }
+
+    #[test]
+    fn parse_mixed_ports() {
+        assert_eq!(parse_ports("22,80,443"), Ok(vec![22, 80, 443]));
+        assert_eq!(
+            parse_ports("22, 8000-8003,80,8002"),
+            Ok(vec![22, 8000, 8001, 8002, 8003, 80])
+        );
+        assert_eq!(parse_ports("65535-65535"), Ok(vec![65535]));
+        assert_eq!(
+            parse_ports("HTTP,https,ssh,dns,netbios-ssn,22,8000-8001"),
+            Ok(vec![80, 443, 22, 53, 139, 8000, 8001])
+        );
+    }
+
+    #[test]
+    fn parse_malformed_ports() {
+        assert_eq!(
+            parse_ports("22,htp"),
+            Err(
+                "'htp' is neither a port nor a known service name. Example: ssh or http."
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            parse_ports("80,70000"),
+            Err("'70000' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+        assert_eq!(
+            parse_ports("1-2-3"),
+            Err("'1-2-3' is not a valid port range. Example: 1000-2000.".to_owned())
+        );
+        assert_eq!(
+            parse_ports("2000-1000"),
+            Err("the range '2000-1000' ends before it starts. Example: 1000-2000.".to_owned())
+        );
+        assert!(parse_ports("22,,80").is_err());
+    }
+
+    #[test]
+    fn parse_out_of_bounds_ports() {
+        assert_eq!(
+            parse_ports("0,22"),
+            Err("'0' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+        assert_eq!(
+            parse_ports("0-100"),
+            Err("'0' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+        assert_eq!(
+            parse_range("8000-70000"),
+            Err("'70000' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+        assert_eq!(
+            parse_range("8000-80"),
+            Err("the range '8000-80' ends before it starts. Example: 1000-2000.".to_owned())
+        );
+        assert_eq!(
+            parse_range("1-65535"),
+            Ok(PortRange {
+                start: 1,
+                end: 65535
+            })
+        );
+    }
+
+    #[test]
+    fn opts_parse_mixed_ports() {
+        let opts = Opts::parse_from(["rustscan", "-a", "127.0.0.1", "-p", "22,1000-1002"]);
+        assert_eq!(opts.ports, Some(vec![22, 1000, 1001, 1002]));
+    }
+
+    #[test]
+    fn opts_parse_exclude_port_ranges() {
+        let opts = Opts::parse_from(["rustscan", "-a", "127.0.0.1", "-e", "80,8000-8002,ssh"]);
+        assert_eq!(opts.exclude_ports, Some(vec![80, 8000, 8001, 8002, 22]));
+        assert!(Opts::try_parse_from(["rustscan", "-e", "9000-8000"]).is_err());
+    }
+
+    #[test]
+    fn parse_port_rules() {
+        assert_eq!(
+            parse_port_rule("80,https -> 8000-8002"),
+            Ok(PortRule {
+                when_open: vec![80, 443],
+                ports: vec![8000, 8001, 8002],
+            })
+        );
+        assert!(parse_port_rule("443 8443").is_err());
+        assert!(parse_port_rule("443 -> 0").is_err());
+
+        let config = Config::parse("port_rules = [\"443->8443\"]", false).unwrap();
+        assert_eq!(
+            config.port_rules,
+            Some(vec![PortRule {
+                when_open: vec![443],
+                ports: vec![8443],
+            }])
+        );
+        assert!(Config::parse("port_rules = [\"443\"]", false).is_err());
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(Exit::of_scan(true, false).code(), 0);
+        assert_eq!(Exit::of_scan(false, false).code(), 1);
+        assert_eq!(Exit::UsageError.code(), 2);
+        assert_eq!(Exit::of_scan(true, true).code(), 3);
+        assert_eq!(Exit::of_scan(false, true).code(), 3);
+    }
+
+    #[test]
+    fn parse_knock_sequences() {
+        assert_eq!(parse_knock("1111, 2222,1111"), Ok(vec![1111, 2222, 1111]));
+        assert_eq!(
+            parse_knock("1111,0"),
+            Err("'0' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+    }
+
+    #[test]
+    fn parse_syslog_facilities() {
+        assert_eq!(
+            parse_syslog_facility("LOCAL3").map(|facility| facility as u8),
+            Ok(Facility::LOG_LOCAL3 as u8)
+        );
+        assert_eq!(
+            parse_syslog_facility("log_daemon").map(|facility| facility as u8),
+            Ok(Facility::LOG_DAEMON as u8)
+        );
+        assert_eq!(
+            parse_syslog_facility("local9").map(|facility| facility as u8),
+            Err("'local9' is not a syslog facility. Example: user, daemon or local0.".to_owned())
+        );
+    }
+
+    #[test]
+    fn spec_overrides_cli() {
+        let spec = Spec::parse(
+            r#"{
+                "addresses": ["10.0.0.0/30"],
+                "ports": "22,8000-8002",
+                "exclude_ports": "8001,9000-9002",
+                "timeout": 2000,
+                "tries": 2,
+                "scripts": "None",
+                "command": ["-sV"]
+            }"#,
+        )
+        .unwrap();
+        let mut opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned()],
+            range: Some(PortRange { start: 1, end: 10 }),
+            timeout: 500,
+            exclude_addresses: Some(vec!["10.0.0.1".to_owned()]),
+            ..Opts::default()
+        };
+        opts.apply_spec(spec);
+
+        assert_eq!(opts.addresses, vec!["10.0.0.0/30"]);
+        assert_eq!(opts.ports, Some(vec![22, 8000, 8001, 8002]));
+        assert_eq!(opts.range, None);
+        assert_eq!(opts.exclude_ports, Some(vec![8001, 9000, 9001, 9002]));
+        assert_eq!(opts.exclude_addresses, Some(vec!["10.0.0.1".to_owned()]));
+        assert_eq!(opts.timeout, 2000);
+        assert_eq!(opts.tries, 2);
+        assert_eq!(opts.scripts, ScriptsRequired::None);
+        assert_eq!(opts.command, vec!["-sV"]);
+    }
+
+    #[test]
+    fn ports_given() {
+        assert!(!Opts::default().ports_given());
+        let full_range = Opts {
+            range: Some(PortRange {
+                start: 1,
+                end: 65_535,
+            }),
+            ..Opts::default()
+        };
+        assert!(!full_range.ports_given());
+        assert!(Opts {
+            cli_args: std::collections::HashSet::from(["range".to_owned()]),
+            ..full_range
+        }
+        .ports_given());
+        assert!(Opts {
+            range: Some(PortRange {
+                start: 1,
+                end: 1_024
+            }),
+            ..Opts::default()
+        }
+        .ports_given());
+        assert!(Opts {
+            ports: Some(vec![80]),
+            ..Opts::default()
+        }
+        .ports_given());
+    }
+
+    #[test]
+    fn parse_invalid_specs() {
+        assert_eq!(Spec::parse("{}"), Ok(Spec::default()));
+        assert!(Spec::parse(r#"{"targets": ["10.0.0.1"]}"#)
+            .unwrap_err()
+            .starts_with("unknown field `targets`"));
+        assert_eq!(
+            Spec::parse(r#"{"ports": "22", "range": {"start": 1, "end": 2}}"#),
+            Err("both ports and range".to_owned())
+        );
+        assert!(Spec::parse(r#"{"ports": "22,x"}"#).is_err());
+        assert!(Spec::parse(r#"{"exclude_ports": "9000-8000"}"#).is_err());
+        assert_eq!(
+            Spec::parse(r#"{"range": {"start": 1000, "end": 1}}"#),
+            Err("the range '1000-1' ends before it starts. Example: 1000-2000.".to_owned())
+        );
+        assert_eq!(
+            Spec::parse(r#"{"range": {"start": 0, "end": 10}}"#),
+            Err("'0' is not a valid port, ports go from 1 to 65535.".to_owned())
+        );
+        assert!(Spec::parse(r#"{"tries": -1}"#).is_err());
+    }
+
+    #[test]
+    fn parse_fwmarks() {
+        assert_eq!(parse_fwmark("100"), Ok(100));
+        assert_eq!(parse_fwmark("0x64"), Ok(100));
+        assert_eq!(parse_fwmark("0XFF"), Ok(255));
+        for invalid in ["", "0x", "-1", "mark", "4294967296"] {
+            assert_eq!(
+                parse_fwmark(invalid),
+                Err(format!(
+                    "'{invalid}' is not a valid firewall mark. Example: 100 or 0x64."
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_max_times() {
+        assert_eq!(parse_max_time("300"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_max_time("300s"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_max_time("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_max_time("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_max_time("2h"), Ok(Duration::from_secs(7200)));
+        for invalid in ["", "s", "5d", "1.5m", "-1"] {
+            assert_eq!(
+                parse_max_time(invalid),
+                Err(format!(
+                    "'{invalid}' is not a valid duration. Example: 300s, 5m or 1h."
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_dns_servers() {
+        assert_eq!(
+            parse_dns_server("10.0.0.53"),
+            Ok("10.0.0.53:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("10.0.0.53:5353"),
+            Ok("10.0.0.53:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("fd00::53"),
+            Ok("[fd00::53]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("ns.internal"),
+            Err("'ns.internal' is not a valid DNS server, use IP or IP:PORT.".to_owned())
+        );
+    }
+
+    #[test]
+    fn read_ports_from_file() {
+        assert_eq!(
+            read_ports_file(Path::new("fixtures/ports.txt")),
+            Ok(vec![22, 80, 443, 8000, 8001, 8002])
+        );
+        assert!(read_ports_file(Path::new("fixtures/no_such_ports.txt"))
+            .unwrap_err()
+            .starts_with("Ports file \"fixtures/no_such_ports.txt\" could not be read"));
+        assert_eq!(
+            read_ports_file(Path::new("fixtures/hosts.txt")).unwrap_err(),
+            "Line 1 of ports file \"fixtures/hosts.txt\": '127.0.0.1' is not a valid port, ports go from 1 to 65535."
+        );
+    }
+
+    #[test]
+    fn config_from_env_vars() {
+        let vars = vec![
+            ("RUSTSCAN_BATCH_SIZE".to_owned(), "1000".to_owned()),
+            ("RUSTSCAN_RESOLVER".to_owned(), "1.1.1.1".to_owned()),
+            ("RUSTSCAN_EXCLUDE_PORTS".to_owned(), "[80, 443]".to_owned()),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ];
+
+        let config = Config::from_vars(vars.into_iter()).unwrap();
+
+        assert_eq!(config.batch_size, Some(1000));
+        assert_eq!(config.resolver, Some("1.1.1.1".to_owned()));
+        assert_eq!(config.exclude_ports, Some(vec![80, 443]));
+        assert!(Config::from_vars(
+            vec![("RUSTSCAN_TIMEOUT".to_owned(), "fast".to_owned())].into_iter()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn opts_merge_precedence() {
+        let mut opts = Opts {
+            batch_size: 10,
+            no_config: false,
+            cli_args: vec!["batch_size".to_owned()].into_iter().collect(),
+            ..Default::default()
+        };
+        let config = Config {
+            batch_size: Some(20),
+            timeout: Some(2_000),
+            tries: Some(2),
+            ..Config::default()
+        };
+        let env = Config {
+            batch_size: Some(30),
+            timeout: Some(3_000),
+            ..Config::parse("", false).unwrap()
+        };
+
+        opts.merge_layers(&config, &env);
+
+        assert_eq!(opts.batch_size, 10);
+        assert_eq!(opts.timeout, 3_000);
+        assert_eq!(opts.tries, 2);
+    }
+
+    #[test]
+    fn timing_template_under_single_options() {
+        let mut opts = Opts {
+            timeout: 700,
+            timing: Some(Timing::Polite),
+            no_config: false,
+            cli_args: vec!["timeout".to_owned(), "timing".to_owned()]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let config = Config {
+            timing: Some(Timing::Insane),
+            batch_size: Some(50),
+            ..Config::parse("", false).unwrap()
+        };
+
+        opts.merge_layers(&config, &Config::parse("", false).unwrap());
+
+        assert_eq!(opts.timing, Some(Timing::Polite));
+        assert_eq!(opts.timeout, 700);
+        assert_eq!(opts.tries, 2);
+        assert_eq!(opts.max_rate, Some(100));
+        assert_eq!(opts.batch_size, 50);
+    }
+
+    #[test]
+    fn parse_timing_templates() {
+        assert_eq!(parse_timing("0"), Ok(Timing::Paranoid));
+        assert_eq!(parse_timing("4"), Ok(Timing::Aggressive));
+        assert_eq!(parse_timing("Polite"), Ok(Timing::Polite));
+        assert!(parse_timing("6").is_err());
+        assert!(parse_timing("fast").is_err());
+
+        let opts = Opts::try_parse_from(["rustscan", "-T5", "-a", "127.0.0.1"]).unwrap();
+        assert_eq!(opts.timing, Some(Timing::Insane));
+    }
+
+    #[test]
+    fn opts_read_tracks_cli_args() {
+        let matches = Opts::command().get_matches_from(["rustscan", "-b", "10", "-a", "127.0.0.1"]);
+
+        assert_eq!(
+            matches.value_source("batch_size"),
+            Some(super::ValueSource::CommandLine)
+        );
+        assert_eq!(
+            matches.value_source("timeout"),
+            Some(super::ValueSource::DefaultValue)
+        );
+    }
+
+    #[test]
+    fn config_layers_later_files_win() {
+        let dir = std::env::temp_dir().join(format!("rustscan-layers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        std::fs::write(
+            &system,
+            "batch_size = 100\ntimeout = 2000\nrange = { start = 1, end = 10 }\n",
+        )
+        .unwrap();
+        std::fs::write(&user, "batch_size = 200\n").unwrap();
+
+        let config =
+            Config::read_layers(&[system, dir.join("missing.toml"), user.clone()], false).unwrap();
+
+        assert_eq!(config.batch_size, Some(200));
+        assert_eq!(config.timeout, Some(2000));
+        assert_eq!(config.range, Some(PortRange { start: 1, end: 10 }));
+
+        std::fs::write(&user, "batch_size = 200\nbatchsize = 1\n").unwrap();
+        assert_eq!(
+            Config::read_layers(std::slice::from_ref(&user), true).unwrap_err(),
+            format!(
+                "unknown key `batchsize` at line 2 in configuration file {}",
+                user.display()
+            )
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_host_tuning() {
+        let config: Config = toml::from_str(
+            r#"batch_size = 4500
+
+[[host_tuning]]
+cidr = "10.0.0.0/24"
+batch_size = 500
+
+[[host_tuning]]
+cidr = "192.168.1.20"
+batch_size = 10
+"#,
+        )
+        .unwrap();
+        let mut opts = Opts {
+            no_config: false,
+            ..Opts::default()
+        };
+        opts.merge_layers(&config, &Config::default());
+
+        assert_eq!(
+            opts.host_tuning,
+            Some(vec![
+                HostTuning {
+                    cidr: "10.0.0.0/24".to_owned(),
+                    batch_size: 500,
+                },
+                HostTuning {
+                    cidr: "192.168.1.20".to_owned(),
+                    batch_size: 10,
+                },
+            ])
+        );
+    }
 }
@@ -13,7 +13,7 @@
 //!
 //! use rustscan::input::{PortRange, ScanOrder};
 //! use rustscan::port_strategy::PortStrategy;
-//! use rustscan::scanner::Scanner;
+//! use rustscan::scanner::{ScanResult, Scanner};
 //!
 //! fn main() {
 //!     let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
@@ -21,7 +21,7 @@
 //!         start: 1,
 //!         end: 1_000,
 //!     };
-//!     let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+//!     let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
 //!     let scanner = Scanner::new(
 //!         &addrs,
 //!         10,
@@ -31,11 +31,15 @@
 //!         strategy,
 //!         true,
 //!         vec![9000],
+//!         false,
 //!     );
 //!
 //!     let scan_result = block_on(scanner.run());
 //!
-//!     println!("{:?}", scan_result);
+//!     for result in &scan_result {
+//!         println!("{}:{} {} in {:?}", result.ip, result.port, result.state, result.rtt);
+//!     }
+//!     println!("{:?}", ScanResult::open_sockets(&scan_result));
 //! }
 //! ```
 #![allow(clippy::needless_doctest_main)]
@@ -53,3 +57,9 @@ pub mod benchmark;
 pub mod scripts;
 
 pub mod address;
+
+pub mod output;
+
+pub mod ulimit;
+
+pub mod doctor;
@@ -3,27 +3,43 @@
 #![allow(clippy::doc_markdown, clippy::if_not_else, clippy::non_ascii_literal)]
 
 use rustscan::benchmark::{Benchmark, NamedTimer};
-use rustscan::input::{self, Config, Opts, ScriptsRequired};
-use rustscan::port_strategy::PortStrategy;
-use rustscan::scanner::Scanner;
-use rustscan::scripts::{init_scripts, Script, ScriptFile};
+use rustscan::doctor::{self, Check};
+use rustscan::input::{
+    read_ports_file, Advice, Config, Exit, HostTuning, Opts, OutputFormat, Protocol,
+    ScriptsRequired, Spec,
+};
+use rustscan::port_strategy::{PortStrategy, TOP_PORTS};
+use rustscan::scanner::{
+    checkpoint::Checkpoint, source_address, tls::TlsInfo, PortState, Proxy, ScanStats, Scanner,
+};
+use rustscan::scripts::{init_scripts, run_scripts, Script, ScriptFile};
+use rustscan::tui::{self, Stream, Tag};
+use rustscan::ulimit;
+use rustscan::{detail, etagln, tagln, warning};
 
 use futures::executor::block_on;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::IsTerminal;
 use std::net::IpAddr;
+use std::str::FromStr;
 use std::string::ToString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-use rustscan::address::parse_addresses;
+use rustscan::address::{
+    hostnames, interleave_ips, public_targets, resolve_targets, reverse_lookups, shuffle_ips,
+    unique_ips, Target,
+};
+use rustscan::output::{
+    csv, gnmap, json, prometheus::MetricsSink, syslog::SyslogSink, xml, HostResult,
+};
 
-extern crate colorful;
-extern crate dirs;
+use cidr_utils::cidr::IpCidr;
 
-// Average value for Ubuntu
-#[cfg(unix)]
-const DEFAULT_FILE_DESCRIPTORS_LIMIT: u64 = 8000;
-// Safest batch size based on experimentation
-const AVERAGE_BATCH_SIZE: u16 = 3000;
+extern crate dirs;
 
 #[macro_use]
 extern crate log;
@@ -38,90 +54,493 @@ fn main() {
     let mut rustscan_bench = NamedTimer::start("RustScan");
 
     let mut opts: Opts = Opts::read();
-    let config = Config::read(opts.config_path.clone());
+    let config = Config::read(opts.config_path.clone(), opts.strict_config);
     opts.merge(&config);
+    if let Some(path) = opts.spec.clone() {
+        opts.apply_spec(Spec::read(&path));
+    }
+    tui::init_color(opts.color, opts.accessible);
 
-    debug!("main() `opts` arguments are {:?}", opts);
+    if opts.doctor {
+        if !print_doctor(&doctor::run(&opts), opts.accessible) {
+            Exit::UsageError.exit();
+        }
+        return;
+    }
+
+    // Structured output needs stdout to itself, which greppable mode already
+    // takes care of for the scanner and address parsing.
+    if opts.output_format != OutputFormat::Text {
+        opts.greppable = true;
+    }
 
-    let scripts_to_run: Vec<ScriptFile> = match init_scripts(opts.scripts) {
-        Ok(scripts_to_run) => scripts_to_run,
-        Err(e) => {
-            eprintln!("[>] error initializing scripts: {}", e);
-            std::process::exit(1);
+    // The target list goes to stdout alone, to be diffed against the scope.
+    if opts.list_targets {
+        opts.quiet = true;
+    }
+
+    if opts.tries == 0 {
+        if !opts.quiet {
+            etagln!(
+                Warning,
+                "--tries 0 would never probe the ports, using a single try."
+            );
         }
-    };
+        opts.tries = 1;
+    }
+
+    debug!("main() `opts` arguments are {:?}", opts);
+
+    if !opts.script_tags.is_empty() && opts.scripts != ScriptsRequired::Custom && !opts.quiet {
+        etagln!(
+            Warning,
+            "--script-tags only picks custom scripts, it goes along with --scripts custom."
+        );
+    }
+
+    let scripts_to_run: Vec<ScriptFile> =
+        match init_scripts(opts.scripts, &opts.scripts_dir, &opts.script_tags) {
+            Ok(scripts_to_run) => scripts_to_run,
+            Err(e) => {
+                etagln!(Output, "error initializing scripts: {e}");
+                Exit::UsageError.exit();
+            }
+        };
 
     debug!("scripts initialized {:?}", &scripts_to_run);
 
-    let ips: Vec<IpAddr> = parse_addresses(&opts);
+    let (targets, unresolved) = resolve_targets(&opts);
+    if opts.require_all_resolve && !unresolved.is_empty() {
+        etagln!(
+            Output,
+            "{} target(s) could not be resolved, aborting scan: {}",
+            unresolved.len(),
+            unresolved.join(", ")
+        );
+        Exit::UsageError.exit();
+    }
+    let ips: Vec<IpAddr> = unique_ips(&targets);
+    let hostnames = if opts.show_hostnames {
+        hostnames(&targets)
+    } else {
+        HashMap::new()
+    };
 
     if ips.is_empty() {
-        eprintln!("[>] no IPs could be resolved, aborting scan.");
-        std::process::exit(1);
+        etagln!(Output, "no IPs could be resolved, aborting scan.");
+        Exit::UsageError.exit();
+    }
+
+    if opts.list_targets {
+        for ip in &ips {
+            println!("{ip}");
+        }
+        return;
+    }
+
+    if !opts.allow_public && !opts.dry_run {
+        confirm_public_targets(&targets);
     }
 
     #[cfg(unix)]
     let batch_size: u16 = infer_batch_size(&opts, adjust_ulimit_size(&opts));
 
     #[cfg(not(unix))]
-    let batch_size: u16 = AVERAGE_BATCH_SIZE;
+    let batch_size: u16 = ulimit::AVERAGE_BATCH_SIZE;
+
+    if let Some(path) = &opts.ports_file {
+        let file_ports = read_ports_file(path).unwrap_or_else(|e| {
+            etagln!(Output, "{e}");
+            Exit::UsageError.exit();
+        });
+        let listed = match opts.top_ports.take() {
+            Some(n) => TOP_PORTS.iter().take(n).copied().collect(),
+            None => opts.ports.take().unwrap_or_default(),
+        };
+        opts.ports = Some(union_ports(listed, file_ports));
+    }
+
+    if opts.only_scripts && opts.ports.is_none() {
+        etagln!(
+            Output,
+            "--only-scripts needs the open ports, given with --ports or --ports-file."
+        );
+        Exit::UsageError.exit();
+    }
+
+    let default_ports = opts.ports_given();
+    let port_strategy = match opts.top_ports {
+        Some(n) => PortStrategy::pick_top(n, opts.scan_order, opts.seed),
+        None => PortStrategy::pick(&opts.range, opts.ports, opts.scan_order, opts.seed),
+    };
+
+    let (scan_order, seed) = (opts.scan_order, opts.seed);
+    let protocol_ports: Vec<(Protocol, PortStrategy)> = vec![
+        (Protocol::Tcp, opts.tcp_ports.take()),
+        (Protocol::Udp, opts.udp_ports.take()),
+    ]
+    .into_iter()
+    .filter_map(|(protocol, ports)| {
+        let port_strategy = PortStrategy::pick(&None, Some(ports?), scan_order, seed);
+        Some((protocol, port_strategy))
+    })
+    .collect();
+    let protocols = scan_protocols(
+        opts.protocols.as_deref(),
+        opts.udp,
+        default_ports,
+        &protocol_ports,
+    );
+
+    if opts.dry_run {
+        let exclude_ports = opts.exclude_ports.unwrap_or_default();
+        for line in dry_run_plan(
+            &ips,
+            batch_size,
+            &port_strategy,
+            (&protocols, &protocol_ports),
+            &exclude_ports,
+            &scripts_to_run,
+        ) {
+            tagln!(Detail, "{line}");
+        }
+        return;
+    }
+
+    let proxy_auth = opts.proxy_auth.clone();
+    let proxy = match opts
+        .proxy
+        .as_deref()
+        .map(|url| Proxy::parse(url, proxy_auth))
+    {
+        Some(Ok(proxy)) => Some(proxy),
+        Some(Err(e)) => {
+            etagln!(Output, "{e}");
+            Exit::UsageError.exit();
+        }
+        None => None,
+    };
+
+    if let Some(source) = opts.source_address {
+        if let Err(e) = source_address::check(source) {
+            etagln!(
+                Output,
+                "can't send probes from {source}, it isn't a local address: {e}"
+            );
+            Exit::UsageError.exit();
+        }
+    }
+
+    if let Some(mark) = opts.fwmark {
+        if let Err(e) = source_address::check_mark(mark) {
+            etagln!(Output, "can't mark the probe sockets with {mark}: {e}");
+            Exit::UsageError.exit();
+        }
+    }
+
+    let interface = match opts.interface.as_deref().map(source_address::interface) {
+        Some(Ok(interface)) => Some(interface),
+        Some(Err(e)) => {
+            etagln!(Output, "can't send probes through the interface: {e}");
+            Exit::UsageError.exit();
+        }
+        None => None,
+    };
+    // Without the permission to bind sockets to the interface, its
+    // addresses still get the probes routed through it in most setups.
+    let bind_device = match &interface {
+        Some(interface) => match source_address::check_device(&interface.name) {
+            Ok(()) => true,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::Unsupported && !opts.quiet {
+                    etagln!(
+                        Warning,
+                        "can't bind sockets to {}, only sending from its addresses: {e}",
+                        interface.name
+                    );
+                }
+                false
+            }
+        },
+        None => false,
+    };
+
+    if let (Some(min), Some(max)) = (opts.min_timeout, opts.max_timeout) {
+        if min > max {
+            etagln!(Output, "--min-timeout {min} is above --max-timeout {max}.");
+            Exit::UsageError.exit();
+        }
+    }
+
+    let syslog = if opts.syslog {
+        match SyslogSink::connect(opts.syslog_facility, opts.syslog_severity) {
+            Ok(sink) => Some(sink),
+            Err(e) => {
+                etagln!(Output, "{e}");
+                Exit::UsageError.exit();
+            }
+        }
+    } else {
+        None
+    };
+
+    let resume_from = match opts.resume.as_deref().map(Checkpoint::read) {
+        Some(Ok(checkpoint)) => checkpoint,
+        Some(Err(e)) => {
+            etagln!(Output, "failed to read the checkpoint to resume: {e}");
+            Exit::UsageError.exit();
+        }
+        None => Checkpoint::default(),
+    };
+
+    // The first Ctrl-C lets the probes in flight finish so what was found
+    // can still be reported, a second one quits right away.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        etagln!(
+            Warning,
+            "interrupted, waiting for the probes in flight. Press Ctrl-C again to quit now."
+        );
+    }) {
+        debug!("failed to set the Ctrl-C handler {}", e);
+    }
+
+    let host_batch_sizes = host_batch_sizes(opts.host_tuning.as_deref().unwrap_or_default())
+        .unwrap_or_else(|e| {
+            etagln!(Output, "{e}");
+            Exit::UsageError.exit();
+        });
+
+    // Only the scan itself is shuffled, results are still listed in the
+    // input order.
+    let mut scan_ips = ips.clone();
+    if opts.randomize_hosts {
+        shuffle_ips(&mut scan_ips, opts.seed);
+    } else if opts.interleave {
+        scan_ips = interleave_ips(&scan_ips);
+    }
+
+    // Scanning both protocols keeps the UDP ports apart, the open ports
+    // listed as usual are the TCP ones then.
+    let scans_tcp = protocols.contains(&Protocol::Tcp);
+    let ports_protocol = if scans_tcp { "tcp" } else { "udp" };
 
     let scanner = Scanner::new(
-        &ips,
+        &scan_ips,
         batch_size,
         Duration::from_millis(opts.timeout.into()),
         opts.tries,
-        opts.greppable,
-        PortStrategy::pick(&opts.range, opts.ports, opts.scan_order),
+        // The open ports found are not listed with --count, not even as they come in.
+        opts.greppable || opts.count,
+        port_strategy,
         opts.accessible,
         opts.exclude_ports.unwrap_or_default(),
-    );
+        opts.udp,
+    )
+    .with_protocols(&protocols)
+    .with_tcp_timeout(opts.tcp_timeout.map(|ms| Duration::from_millis(ms.into())))
+    .with_udp_timeout(opts.udp_timeout.map(|ms| Duration::from_millis(ms.into())))
+    .with_timeout_bounds(
+        opts.min_timeout.map(|ms| Duration::from_millis(ms.into())),
+        opts.max_timeout.map(|ms| Duration::from_millis(ms.into())),
+    )
+    .with_max_rate(opts.max_rate)
+    .with_banner(opts.banner)
+    .with_tls_probe(opts.tls_probe)
+    .with_max_hosts(opts.max_hosts)
+    .with_host_batch_sizes(host_batch_sizes)
+    .with_retry_backoff(opts.retry_backoff)
+    .with_retry_jitter(opts.retry_jitter)
+    .with_local_error_backoff(opts.local_error_backoff)
+    .with_proxy(proxy)
+    .with_source_address(opts.source_address)
+    .with_fwmark(opts.fwmark)
+    .with_interface(interface, bind_device)
+    .with_local_port_range(opts.local_port_range.as_ref())
+    .with_output_file(opts.output_file.clone())
+    .with_ping_first(opts.ping_first)
+    .with_knock(opts.knock.clone().unwrap_or_default())
+    .with_port_rules(opts.port_rules.clone())
+    .with_jsonl(opts.output_format == OutputFormat::Jsonl)
+    .with_quiet(opts.quiet)
+    .with_checkpoint(opts.resume.clone().or(opts.checkpoint.clone()), resume_from)
+    .with_interrupt(interrupted)
+    .with_max_time(opts.max_time)
+    .with_max_findings(opts.max_findings, opts.max_findings_per_host)
+    .with_adaptive_batch(opts.adaptive)
+    .with_progress(opts.progress && !opts.greppable && !opts.accessible);
+    let scanner = match syslog {
+        Some(sink) => scanner.with_extra_sink(Arc::new(sink)),
+        None => scanner,
+    };
+    let scanner = match opts.metrics_file.clone() {
+        Some(path) => scanner.with_extra_sink(Arc::new(MetricsSink::new(
+            path,
+            scan_ips.clone(),
+            protocols.clone(),
+        ))),
+        None => scanner,
+    };
+    let scanner = protocol_ports
+        .into_iter()
+        .fold(scanner, |scanner, (protocol, port_strategy)| {
+            scanner.with_protocol_ports(protocol, port_strategy)
+        });
     debug!("scanner finished building: {:?}", scanner);
 
+    if !opts.only_scripts {
+        block_on(scanner.warmup(opts.warmup));
+    }
     let mut portscan_bench = NamedTimer::start("Portscan");
-    let scan_result = block_on(scanner.run());
+    let scan_result = if opts.only_scripts {
+        scanner.assume_open()
+    } else {
+        block_on(scanner.run())
+    };
     portscan_bench.end();
+    let partial =
+        scanner.is_interrupted() || scanner.is_timed_out() || scanner.is_max_findings_reached();
+    let portscan_duration = portscan_bench.duration().unwrap_or_default();
     benchmarks.push(portscan_bench);
 
     let mut ports_per_ip = HashMap::new();
+    let mut udp_ports_per_ip: HashMap<IpAddr, Vec<u16>> = HashMap::new();
+    let mut open_filtered_per_ip: HashMap<IpAddr, usize> = HashMap::new();
+    let mut filtered_per_ip: HashMap<IpAddr, usize> = HashMap::new();
+    let mut banners_per_ip: HashMap<IpAddr, BTreeMap<u16, String>> = HashMap::new();
+    let mut tls_per_ip: HashMap<IpAddr, BTreeMap<u16, TlsInfo>> = HashMap::new();
 
-    for socket in scan_result {
-        ports_per_ip
-            .entry(socket.ip())
-            .or_insert_with(Vec::new)
-            .push(socket.port());
+    // Keyed by IP and whether the ports are the UDP ones of a TCP and UDP
+    // scan, like the lines they end up on.
+    let mut states_per_ip: HashMap<(IpAddr, bool), Vec<(u16, PortState)>> = HashMap::new();
+
+    for result in scan_result {
+        if opts.port_states {
+            let udp = result.protocol == Protocol::Udp && scans_tcp;
+            states_per_ip
+                .entry((result.ip, udp))
+                .or_default()
+                .push((result.port, result.state));
+        }
+        if result.state == PortState::Open && result.protocol == Protocol::Udp && scans_tcp {
+            udp_ports_per_ip
+                .entry(result.ip)
+                .or_default()
+                .push(result.port);
+        } else if result.state == PortState::Open {
+            ports_per_ip
+                .entry(result.ip)
+                .or_insert_with(Vec::new)
+                .push(result.port);
+            if let Some(banner) = result.banner {
+                banners_per_ip
+                    .entry(result.ip)
+                    .or_default()
+                    .insert(result.port, banner);
+            }
+            if let Some(tls) = result.tls {
+                tls_per_ip
+                    .entry(result.ip)
+                    .or_default()
+                    .insert(result.port, tls);
+            }
+        } else if result.state == PortState::Filtered {
+            *filtered_per_ip.entry(result.ip).or_insert(0) += 1;
+        } else {
+            *open_filtered_per_ip.entry(result.ip).or_insert(0) += 1;
+        }
     }
 
-    for ip in ips {
-        if ports_per_ip.contains_key(&ip) {
-            continue;
+    // Every open port whatever its protocol, for what only counts them.
+    let mut open_per_ip = ports_per_ip.clone();
+    for (ip, ports) in &udp_ports_per_ip {
+        open_per_ip.entry(*ip).or_default().extend(ports);
+    }
+
+    let ptrs = if opts.resolve_ptr {
+        let open_ips: Vec<IpAddr> = open_per_ip.keys().copied().collect();
+        reverse_lookups(&open_ips, &opts.resolver, opts.dns_server)
+    } else {
+        HashMap::new()
+    };
+
+    // UDP ports that never answered can't be told apart from filtered ones,
+    // and TCP ports that timed out were most likely dropped by a firewall,
+    // so only their count per host is reported.
+    if !opts.greppable {
+        for (ip, count) in &open_filtered_per_ip {
+            tagln!(
+                Output,
+                "{ip} -> {count} port(s) open|filtered (no UDP response)"
+            );
+        }
+        for (ip, count) in &filtered_per_ip {
+            tagln!(
+                Output,
+                "{ip} -> {count} port(s) filtered (connection timed out)"
+            );
+        }
+    }
+
+    if opts.count && opts.output_format == OutputFormat::Text {
+        for line in count_lines(&ips, &open_per_ip, &hostnames, &ptrs, opts.open_only) {
+            println!("{line}");
         }
+    }
 
-        let x = format!("looks like i didn't find any open ports for {:?}. this is usually caused by a high batch size.
-        \n*i used {} batch size, consider lowering it with {} or a comfortable number for your system.
-        \n alternatively, increase the timeout if your ping is high. rustscan -t 2000 for 2000 milliseconds (2s) timeout.\n",
-        ip,
-        opts.batch_size,
-        "'rustscan -b <batch_size> -a <ip address>'");
-        eprintln!("[>] {}", x);
+    let advice = match opts.advice {
+        Advice::Short if opts.verbose => Advice::Verbose,
+        advice => advice,
+    };
+    for ip in &ips {
+        if opts.open_only || opts.count || opts.quiet || open_per_ip.contains_key(ip) {
+            continue;
+        }
+        if let Some(x) = no_open_ports_advice(*ip, advice, opts.batch_size) {
+            etagln!(Output, "{x}");
+        }
     }
 
     let mut script_bench = NamedTimer::start("Scripts");
-    for (ip, ports) in &ports_per_ip {
+    let mut script_jobs: Vec<(IpAddr, Vec<Script>)> = Vec::new();
+    // --count leaves out the port lists and scripts.
+    let count_only = opts.count;
+    let lists_ports = opts.greppable || opts.scripts == ScriptsRequired::None;
+    for (ip, ports) in ports_per_ip.iter().filter(|_| !count_only) {
         let vec_str_ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
 
         // nmap port style is 80,443. Comma separated with no spaces.
         let ports_str = vec_str_ports.join(",");
 
         // if option scripts is none, no script will be spawned
-        if opts.greppable || opts.scripts == ScriptsRequired::None {
-            println!("[>] {} -> [{}]", &ip, ports_str);
+        if lists_ports {
+            if opts.output_format == OutputFormat::Text && !opts.port_states {
+                tagln!(
+                    Output,
+                    "{} -> [{}]",
+                    host_label(*ip, &hostnames, &ptrs),
+                    ports_str
+                );
+            }
             continue;
         }
         debug!("starting script(s)");
 
-        // run all the scripts we found and parsed based on the script config file tags field.
+        // build all the scripts we found and parsed based on the script config file tags field.
+        let mut scripts = Vec::with_capacity(scripts_to_run.len());
         for mut script_f in scripts_to_run.clone() {
+            if !script_f.is_triggered_by(ports) {
+                debug!(
+                    "skipping script {:?}, none of its trigger ports are open",
+                    script_f.path
+                );
+                continue;
+            }
+
             // this part allows us to add commandline arguments to the script call_format, appending them to the end of the command.
             if !opts.command.is_empty() {
                 let user_extra_args = &opts.command.join(" ");
@@ -130,7 +549,9 @@ fn main() {
                     let mut call_f = script_f.call_format.unwrap();
                     call_f.push(' ');
                     call_f.push_str(user_extra_args);
-                    println!("[>] running script {:?} on ip {}\ndepending on the complexity of the script, results may take some time to appear.", call_f, &ip);
+                    if !opts.quiet {
+                        tagln!(Output, "running script {:?} on ip {}\ndepending on the complexity of the script, results may take some time to appear.", call_f, &ip);
+                    }
                     debug!("call format {}", call_f);
                     script_f.call_format = Some(call_f);
                 }
@@ -145,137 +566,855 @@ fn main() {
                 script_f.ports_separator,
                 script_f.tags,
                 script_f.call_format,
+            )
+            .with_timeout(
+                opts.script_timeout
+                    .or(script_f.timeout)
+                    .map(Duration::from_secs),
+            )
+            .with_ports_format(script_f.ports_format)
+            .with_streaming(opts.stream_scripts);
+            scripts.push(script);
+        }
+        script_jobs.push((*ip, scripts));
+    }
+
+    if opts.output_format == OutputFormat::Text && !count_only && !opts.port_states {
+        for (ip, ports) in &mut udp_ports_per_ip {
+            ports.sort_unstable();
+            let ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+            tagln!(
+                Output,
+                "{} -> [{}] udp",
+                host_label(*ip, &hostnames, &ptrs),
+                ports.join(",")
             );
-            match script.run() {
+        }
+    }
+
+    if opts.port_states && lists_ports && opts.output_format == OutputFormat::Text && !count_only {
+        for line in port_state_lines(&ips, &mut states_per_ip, &hostnames, &ptrs) {
+            tagln!(Output, "{line}");
+        }
+    }
+
+    // Each IP's output is buffered and only printed once all of its scripts
+    // are done, so results of concurrently running IPs don't interleave.
+    // Streamed output was printed as it came in already.
+    let stream_scripts = opts.stream_scripts;
+    run_scripts(script_jobs, opts.script_parallelism, |_, outputs| {
+        for output in outputs {
+            match output {
+                Ok(_) if stream_scripts => {}
                 Ok(script_result) => {
-                    println!("[>] {}", script_result);
+                    tagln!(Output, "{script_result}");
                 }
                 Err(e) => {
-                    eprintln!("[>] error running script: {}", e);
+                    etagln!(Output, "error running script: {e}");
                 }
             }
         }
-    }
+    });
 
     script_bench.end();
     benchmarks.push(script_bench);
+
+    let timing_ms = u64::try_from(portscan_duration.as_millis()).unwrap_or(u64::MAX);
+    let open_only = opts.open_only;
+    let results: Vec<HostResult> = ips
+        .iter()
+        .filter(|ip| !open_only || open_per_ip.contains_key(ip))
+        .map(|ip| {
+            let mut open_ports = ports_per_ip.get(ip).cloned().unwrap_or_default();
+            open_ports.sort_unstable();
+            HostResult {
+                ip: *ip,
+                hostname: hostnames.get(ip).cloned(),
+                ptr: ptrs.get(ip).cloned().flatten(),
+                open_ports,
+                open_udp_ports: udp_ports_per_ip.get(ip).cloned().unwrap_or_default(),
+                timing_ms,
+                banners: banners_per_ip.remove(ip).unwrap_or_default(),
+                tls: tls_per_ip.remove(ip).unwrap_or_default(),
+                partial,
+            }
+        })
+        .collect();
+
+    if opts.summary {
+        for line in target_summaries(&targets, &open_per_ip) {
+            if opts.greppable {
+                etagln!(Output, "{line}");
+            } else {
+                tagln!(Output, "{line}");
+            }
+        }
+    }
+
+    if opts.port_summary {
+        // Without TCP the UDP ports are the ones in ports_per_ip.
+        let lines = if scans_tcp {
+            port_summary_lines(&ports_per_ip, &udp_ports_per_ip)
+        } else {
+            port_summary_lines(&HashMap::new(), &ports_per_ip)
+        };
+        for line in lines {
+            if opts.greppable {
+                etagln!(Output, "{line}");
+            } else {
+                tagln!(Output, "{line}");
+            }
+        }
+    }
+
+    if opts.stats {
+        for line in stats_lines(&scanner.stats(), batch_size, opts.max_rate) {
+            if opts.greppable {
+                etagln!(Detail, "{line}");
+            } else {
+                tagln!(Detail, "{line}");
+            }
+        }
+    }
+
+    if opts.output_format == OutputFormat::Json {
+        match json::to_string(&results) {
+            Ok(document) => println!("{document}"),
+            Err(e) => etagln!(Output, "error serializing results: {e}"),
+        }
+    }
+
+    if opts.output_format == OutputFormat::Csv {
+        print!("{}", csv::to_string(&results, ports_protocol));
+    }
+
+    if opts.output_format == OutputFormat::NmapGrepable {
+        print!("{}", gnmap::to_string(&results, ports_protocol));
+    }
+
+    if let Some(path) = &opts.output_xml {
+        if let Err(e) = fs::write(path, xml::to_string(&results, ports_protocol)) {
+            etagln!(
+                Output,
+                "error writing XML output to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
     rustscan_bench.end();
     benchmarks.push(rustscan_bench);
     debug!("benchmarks raw {:?}", benchmarks);
-    println!("[>] {}", benchmarks.summary());
+    if let Some(path) = &opts.benchmark_json {
+        match benchmarks.to_json() {
+            Ok(report) => {
+                if let Err(e) = fs::write(path, report) {
+                    etagln!(
+                        Output,
+                        "error writing benchmark report to {}: {e}",
+                        path.display()
+                    );
+                }
+            }
+            Err(e) => etagln!(Output, "error serializing benchmarks: {e}"),
+        }
+    } else if !opts.quiet {
+        if opts.output_format == OutputFormat::Text {
+            tagln!(Output, "{}", benchmarks.summary());
+        } else {
+            etagln!(Output, "{}", benchmarks.summary());
+        }
+    }
+
+    Exit::of_scan(!open_per_ip.is_empty(), partial).exit();
 }
 
-#[cfg(unix)]
-fn adjust_ulimit_size(opts: &Opts) -> u64 {
-    use rlimit::Resource;
+/// Parses the CIDRs of the `[[host_tuning]]` config tables, single IPs
+/// being taken as a CIDR holding only that IP.
+fn host_batch_sizes(host_tuning: &[HostTuning]) -> Result<Vec<(IpCidr, usize)>, String> {
+    host_tuning
+        .iter()
+        .map(|tuning| {
+            let cidr = IpCidr::from_str(&tuning.cidr)
+                .map_err(|_| format!("host_tuning CIDR {:?} is not valid", tuning.cidr))?;
+            if tuning.batch_size == 0 {
+                return Err(format!(
+                    "host_tuning batch_size for {} has to be at least 1",
+                    tuning.cidr
+                ));
+            }
+            Ok((cidr, usize::from(tuning.batch_size)))
+        })
+        .collect()
+}
 
-    if let Some(limit) = opts.ulimit {
-        if Resource::NOFILE.set(limit, limit).is_ok() {
-            println!("[>] automatically increasing ulimit value to {}", limit);
-        } else {
-            eprintln!("[>] failed to set ulimit value.");
+/// The ports of both lists, in the order they are first listed.
+fn union_ports(first: Vec<u16>, second: Vec<u16>) -> Vec<u16> {
+    let mut seen = HashSet::new();
+    first
+        .into_iter()
+        .chain(second)
+        .filter(|port| seen.insert(*port))
+        .collect()
+}
+
+/// The `--stats` block, comparing the rate achieved to the limits set.
+fn stats_lines(stats: &ScanStats, batch_size: u16, max_rate: Option<u32>) -> Vec<String> {
+    let limits = match max_rate.filter(|&rate| rate > 0) {
+        Some(rate) => format!("batch size {batch_size}, max rate {rate}/s"),
+        None => format!("batch size {batch_size}, no max rate"),
+    };
+    let mut lines = vec![
+        format!(
+            "{} connection attempt(s), {} of them retries",
+            stats.attempts, stats.retries
+        ),
+        format!(
+            "{} open, {} closed, {} filtered, {} error(s)",
+            stats.open, stats.closed, stats.filtered, stats.errors
+        ),
+        format!(
+            "{:.1} probes/s over {:.3}s ({limits})",
+            stats.probes_per_second(),
+            stats.duration.as_secs_f64()
+        ),
+    ];
+    if stats.local_errors > 0 {
+        lines.push(format!(
+            "{} local resource error(s) backed off from",
+            stats.local_errors
+        ));
+    }
+    lines
+}
+
+/// What is printed for a host where no open port was found, at the `--advice`
+/// level.
+fn no_open_ports_advice(ip: IpAddr, advice: Advice, batch_size: u16) -> Option<String> {
+    match advice {
+        Advice::Off => None,
+        Advice::Short => Some(format!(
+            "no open ports found on {ip}, run with --verbose for hints"
+        )),
+        Advice::Verbose => Some(format!("looks like i didn't find any open ports for {:?}. this is usually caused by a high batch size.
+        \n*i used {} batch size, consider lowering it with {} or a comfortable number for your system.
+        \n alternatively, increase the timeout if your ping is high. rustscan -t 2000 for 2000 milliseconds (2s) timeout.\n",
+        ip,
+        batch_size,
+        "'rustscan -b <batch_size> -a <ip address>'")),
+    }
+}
+
+/// The `--count` lines, `ip: N open` for every host in the input order.
+/// Hosts without open ports are left out when `open_only` is set.
+fn count_lines(
+    ips: &[IpAddr],
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+    hostnames: &HashMap<IpAddr, String>,
+    ptrs: &HashMap<IpAddr, Option<String>>,
+    open_only: bool,
+) -> Vec<String> {
+    ips.iter()
+        .map(|ip| (ip, ports_per_ip.get(ip).map_or(0, Vec::len)))
+        .filter(|(_, open)| !open_only || *open > 0)
+        .map(|(ip, open)| format!("{}: {open} open", host_label(*ip, hostnames, ptrs)))
+        .collect()
+}
+
+/// Warns that targets have public IPs. When run from a terminal the scan
+/// only goes ahead once the user confirms it, otherwise the warning is all
+/// there is.
+#[cfg(not(tarpaulin_include))]
+fn confirm_public_targets(targets: &[Target]) {
+    let public = public_targets(targets);
+    if public.is_empty() {
+        return;
+    }
+    let listed: Vec<String> = public
+        .iter()
+        .map(|(name, ips)| format!("{name} ({ips})"))
+        .collect();
+    etagln!(
+        Warning,
+        "targets include public IPs: {}. Pass --allow-public if that is intended.",
+        listed.join(", ")
+    );
+
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return;
+    }
+    eprint!(
+        "{} scan them anyway? [y/N] ",
+        tui::tag(Tag::Question, Stream::Stderr)
+    );
+    let mut answer = String::new();
+    let confirmed = std::io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+    if !confirmed {
+        etagln!(Output, "aborting scan.");
+        Exit::UsageError.exit();
+    }
+}
+
+/// The `--port-states` lines, `ip -> [80:open,53:filtered]` for every host
+/// with ports that weren't found closed, in the input order. The UDP ports
+/// of a TCP and UDP scan follow the TCP ones on the same line, e.g.
+/// `53/udp:open|filtered`.
+fn port_state_lines(
+    ips: &[IpAddr],
+    states_per_ip: &mut HashMap<(IpAddr, bool), Vec<(u16, PortState)>>,
+    hostnames: &HashMap<IpAddr, String>,
+    ptrs: &HashMap<IpAddr, Option<String>>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for ip in ips {
+        let mut ports: Vec<String> = Vec::new();
+        for udp in [false, true] {
+            let Some(states) = states_per_ip.get_mut(&(*ip, udp)) else {
+                continue;
+            };
+            states.sort_unstable_by_key(|(port, _)| *port);
+            ports.extend(states.iter().map(|(port, state)| {
+                if udp {
+                    format!("{port}/udp:{state}")
+                } else {
+                    format!("{port}:{state}")
+                }
+            }));
+        }
+        if !ports.is_empty() {
+            lines.push(format!(
+                "{} -> [{}]",
+                host_label(*ip, hostnames, ptrs),
+                ports.join(",")
+            ));
         }
     }
+    lines
+}
 
-    let (soft, _) = Resource::NOFILE.get().unwrap();
-    soft
+/// The IP as shown in the results, preceded by the host name it was resolved
+/// from when there is one, and followed by its PTR record when it was looked
+/// up.
+fn host_label(
+    ip: IpAddr,
+    hostnames: &HashMap<IpAddr, String>,
+    ptrs: &HashMap<IpAddr, Option<String>>,
+) -> String {
+    let label = match hostnames.get(&ip) {
+        Some(hostname) => format!("{hostname} ({ip})"),
+        None => ip.to_string(),
+    };
+    match ptrs.get(&ip) {
+        Some(Some(ptr)) => format!("{label} (PTR {ptr})"),
+        Some(None) => format!("{label} (no PTR)"),
+        None => label,
+    }
 }
 
-#[cfg(unix)]
-fn infer_batch_size(opts: &Opts, ulimit: u64) -> u16 {
-    use std::convert::TryInto;
-
-    let mut batch_size: u64 = opts.batch_size.into();
-
-    // adjust the batch size when the ulimit value is lower than the desired batch size
-    if ulimit < batch_size {
-        eprintln!("[>] file limit is lower than default batch size. consider upping with --ulimit. may cause harm to sensitive servers");
-
-        // when the os supports high file limits like 8000, but the user
-        // selected a batch size higher than this we should reduce it to
-        // a lower number.
-        if ulimit < AVERAGE_BATCH_SIZE.into() {
-            // ulimit is smaller than aveage batch size
-            // user must have very small ulimit
-            // decrease batch size to half of ulimit
-            eprintln!("[>] your file limit is very small, which negatively impacts rustscan's speed. use the docker image, or up the ulimit with '--ulimit 5000'. ");
-            println!("[>] halving batch_size because ulimit is smaller than average batch size");
-            batch_size = ulimit / 2;
-        } else if ulimit > DEFAULT_FILE_DESCRIPTORS_LIMIT {
-            println!("[>] batch size is now average batch size");
-            batch_size = AVERAGE_BATCH_SIZE.into();
+/// One line per input target, counting its hosts with open ports and the
+/// open ports across all of its hosts.
+fn target_summaries(targets: &[Target], ports_per_ip: &HashMap<IpAddr, Vec<u16>>) -> Vec<String> {
+    targets
+        .iter()
+        .map(|target| {
+            let open_ports: Vec<usize> = target
+                .ips
+                .iter()
+                .filter_map(|ip| ports_per_ip.get(ip).map(Vec::len))
+                .collect();
+            format!(
+                "{}: {}/{} host(s) with open ports, {} open port(s)",
+                target.name,
+                open_ports.len(),
+                target.ips.len(),
+                open_ports.iter().sum::<usize>()
+            )
+        })
+        .collect()
+}
+
+/// One line per open port, with how many hosts it is open on, the ports on
+/// the most hosts first. UDP ports are told apart from TCP ones with /udp.
+fn port_summary_lines(
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+    udp_ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<String> {
+    let mut hosts_per_port: HashMap<(u16, &str), usize> = HashMap::new();
+    for (ports, suffix) in [(ports_per_ip, ""), (udp_ports_per_ip, "/udp")] {
+        for port in ports
+            .values()
+            .flat_map(|ports| ports.iter().collect::<HashSet<_>>())
+        {
+            *hosts_per_port.entry((*port, suffix)).or_insert(0) += 1;
+        }
+    }
+    let mut hosts_per_port: Vec<_> = hosts_per_port.into_iter().collect();
+    hosts_per_port
+        .sort_unstable_by_key(|&((port, suffix), hosts)| (std::cmp::Reverse(hosts), port, suffix));
+    hosts_per_port
+        .into_iter()
+        .map(|((port, suffix), hosts)| format!("port {port}{suffix} open on {hosts} host(s)"))
+        .collect()
+}
+
+/// What a scan with these settings would do, one line per item: the
+/// targets, batch size, ports and sockets, and the scripts that could run.
+/// The protocols to scan, the ones of `--protocols` or `--udp` and those
+/// given their own ports. Without either of the first two, TCP is only
+/// scanned if it has ports of its own or `default_ports` were given, so that
+/// `--udp-ports 53` alone doesn't also scan every TCP port.
+fn scan_protocols(
+    selected: Option<&[Protocol]>,
+    udp: bool,
+    default_ports: bool,
+    protocol_ports: &[(Protocol, PortStrategy)],
+) -> Vec<Protocol> {
+    let mut protocols = match (selected, udp) {
+        (Some(protocols), _) => protocols.to_vec(),
+        (None, true) => vec![Protocol::Udp],
+        (None, false) if default_ports || protocol_ports.is_empty() => vec![Protocol::Tcp],
+        (None, false) => Vec::new(),
+    };
+    for (protocol, _) in protocol_ports {
+        if !protocols.contains(protocol) {
+            protocols.push(*protocol);
+        }
+    }
+    protocols
+}
+
+fn dry_run_plan(
+    ips: &[IpAddr],
+    batch_size: u16,
+    port_strategy: &PortStrategy,
+    (protocols, protocol_ports): (&[Protocol], &[(Protocol, PortStrategy)]),
+    exclude_ports: &[u16],
+    scripts: &[ScriptFile],
+) -> Vec<String> {
+    let exclude_ports: HashSet<&u16> = exclude_ports.iter().collect();
+    let count_ports = |port_strategy: &PortStrategy| {
+        port_strategy
+            .order()
+            .iter()
+            .filter(|port| !exclude_ports.contains(port))
+            .count()
+    };
+    let ports = count_ports(port_strategy);
+    let mut plan = vec![
+        format!("{} target IP(s)", ips.len()),
+        format!("batch size {batch_size}"),
+    ];
+    // Only when some protocol has no ports of its own to scan instead
+    if protocols
+        .iter()
+        .any(|protocol| protocol_ports.iter().all(|(known, _)| known != protocol))
+    {
+        plan.push(format!(
+            "port strategy {port_strategy}, {ports} port(s) after exclusions"
+        ));
+    }
+    let mut sockets = 0;
+    for protocol in protocols {
+        match protocol_ports.iter().find(|(known, _)| known == protocol) {
+            Some((_, port_strategy)) => {
+                let ports = count_ports(port_strategy);
+                plan.push(format!(
+                    "{protocol} ports {port_strategy}, {ports} port(s) after exclusions"
+                ));
+                sockets += ips.len() * ports;
+            }
+            None => sockets += ips.len() * ports,
+        }
+    }
+    plan.push(format!("{sockets} socket(s) to scan"));
+    if scripts.is_empty() {
+        plan.push("no scripts would run".to_owned());
+    }
+    for script in scripts {
+        let name = match (&script.path, &script.call_format) {
+            (Some(path), _) => path.display().to_string(),
+            (None, Some(call_format)) => call_format.clone(),
+            (None, None) => "unnamed script".to_owned(),
+        };
+        plan.push(format!("script {name}"));
+    }
+    plan
+}
+
+/// Prints the outcome of `--doctor`, telling whether every check passed.
+fn print_doctor(checks: &[Check], accessible: bool) -> bool {
+    for check in checks {
+        if check.passed {
+            detail!(check.summary, false, accessible);
         } else {
-            batch_size = ulimit - 100;
+            warning!(check.summary, false, accessible);
+        }
+        if let Some(advice) = &check.advice {
+            for line in advice.lines() {
+                println!("    {line}");
+            }
         }
     }
-    // when the ulimit is higher than the batch size let the user know that the
-    // batch size can be increased unless they specified the ulimit themselves.
-    else if ulimit + 2 > batch_size && (opts.ulimit.is_none()) {
-        println!("[>] file limit higher than batch size. can increase speed by increasing batch size '-b {}'.", ulimit - 100);
+    checks.iter().all(|check| check.passed)
+}
+
+#[cfg(unix)]
+fn adjust_ulimit_size(opts: &Opts) -> u64 {
+    if let Some(limit) = opts.ulimit {
+        match ulimit::set_file_limit(limit) {
+            Ok(()) if !opts.quiet => {
+                etagln!(Output, "automatically increasing ulimit value to {limit}");
+            }
+            Ok(()) => {}
+            Err(e) => etagln!(Output, "failed to set ulimit value to {limit}: {e}"),
+        }
     }
 
-    batch_size
-        .try_into()
-        .expect("couldn't fit the batch size into a u16.")
+    ulimit::file_limit().unwrap()
+}
+
+#[cfg(unix)]
+fn infer_batch_size(opts: &Opts, file_limit: u64) -> u16 {
+    if !opts.quiet {
+        for note in ulimit::batch_size_notes(file_limit, opts) {
+            etagln!(Output, "{note}");
+        }
+    }
+    ulimit::recommended_batch_size(file_limit, opts)
 }
 
 #[cfg(test)]
 mod tests {
     #[cfg(unix)]
-    use super::{adjust_ulimit_size, infer_batch_size};
-    use super::{Opts};
+    use super::adjust_ulimit_size;
+    use super::{
+        count_lines, dry_run_plan, host_batch_sizes, host_label, no_open_ports_advice,
+        port_state_lines, port_summary_lines, scan_protocols, stats_lines, target_summaries,
+        union_ports, Opts, Target,
+    };
+    use rustscan::input::{Advice, HostTuning, PortRange, Protocol, ScanOrder};
+    use rustscan::port_strategy::PortStrategy;
+    use rustscan::scanner::{PortState, ScanStats};
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::time::Duration;
 
     #[test]
     #[cfg(unix)]
-    fn batch_size_lowered() {
-        let mut opts = Opts::default();
-        opts.batch_size = 50_000;
-        let batch_size = infer_batch_size(&opts, 120);
+    fn batch_size_adjusted_2000() {
+        // ulimit == batch_size
+        let opts = Opts {
+            batch_size: 50_000,
+            ulimit: Some(2_000),
+            ..Default::default()
+        };
+        let batch_size = adjust_ulimit_size(&opts);
 
-        assert!(batch_size < opts.batch_size);
+        assert_eq!(batch_size, 2_000);
     }
 
     #[test]
-    #[cfg(unix)]
-    fn batch_size_lowered_average_size() {
-        let mut opts = Opts::default();
-        opts.batch_size = 50_000;
-        let batch_size = infer_batch_size(&opts, 9_000);
+    fn summary_per_target() {
+        let targets = vec![
+            Target {
+                name: "192.168.0.0/30".to_owned(),
+                ips: (0..4)
+                    .map(|i| format!("192.168.0.{i}").parse().unwrap())
+                    .collect(),
+            },
+            Target {
+                name: "10.0.0.1".to_owned(),
+                ips: vec!["10.0.0.1".parse().unwrap()],
+            },
+        ];
+        let ports_per_ip: HashMap<_, _> = vec![
+            ("192.168.0.1".parse().unwrap(), vec![22, 80]),
+            ("192.168.0.3".parse().unwrap(), vec![443]),
+        ]
+        .into_iter()
+        .collect();
 
-        assert_eq!(batch_size, 3_000);
+        assert_eq!(
+            target_summaries(&targets, &ports_per_ip),
+            [
+                "192.168.0.0/30: 2/4 host(s) with open ports, 3 open port(s)",
+                "10.0.0.1: 0/1 host(s) with open ports, 0 open port(s)",
+            ]
+        );
     }
+
     #[test]
-    #[cfg(unix)]
-    fn batch_size_equals_ulimit_lowered() {
-        // because ulimit and batch size are same size, batch size is lowered
-        // to ULIMIT - 100
-        let mut opts = Opts::default();
-        opts.batch_size = 50_000;
-        let batch_size = infer_batch_size(&opts, 5_000);
+    fn port_summary_by_host_count() {
+        let ip = |last: u8| IpAddr::from([10, 0, 0, last]);
+        let ports_per_ip: HashMap<_, _> = vec![
+            (ip(1), vec![22, 443]),
+            (ip(2), vec![443, 8080]),
+            (ip(3), vec![443, 22]),
+        ]
+        .into_iter()
+        .collect();
+        let udp_ports_per_ip: HashMap<_, _> = vec![(ip(1), vec![53])].into_iter().collect();
 
-        assert_eq!(batch_size, 4_900);
+        assert_eq!(
+            port_summary_lines(&ports_per_ip, &udp_ports_per_ip),
+            [
+                "port 443 open on 3 host(s)",
+                "port 22 open on 2 host(s)",
+                "port 53/udp open on 1 host(s)",
+                "port 8080 open on 1 host(s)",
+            ]
+        );
     }
+
     #[test]
-    #[cfg(unix)]
-    fn batch_size_adjusted_2000() {
-        // ulimit == batch_size
-        let mut opts = Opts::default();
-        opts.batch_size = 50_000;
-        opts.ulimit = Some(2_000);
-        let batch_size = adjust_ulimit_size(&opts);
+    fn dry_run_plan_counts_sockets() {
+        let ips = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let range = PortRange { start: 1, end: 100 };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
 
-        assert_eq!(batch_size, 2_000);
+        assert_eq!(
+            dry_run_plan(
+                &ips,
+                4500,
+                &strategy,
+                (&[Protocol::Tcp], &[]),
+                &[22, 80, 9000],
+                &[]
+            ),
+            vec![
+                "2 target IP(s)",
+                "batch size 4500",
+                "port strategy serial 1-100, 98 port(s) after exclusions",
+                "196 socket(s) to scan",
+                "no scripts would run",
+            ]
+        );
     }
 
     #[test]
-    #[cfg(unix)]
-    fn test_high_ulimit_no_greppable_mode() {
-        let mut opts = Opts::default();
-        opts.batch_size = 10;
-        opts.greppable = false;
+    fn dry_run_plan_counts_ports_per_protocol() {
+        let ips = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let range = PortRange { start: 1, end: 100 };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
+        let udp_ports =
+            PortStrategy::pick(&None, Some(vec![53, 123, 161]), ScanOrder::Serial, None);
+
+        let plan = dry_run_plan(
+            &ips,
+            4500,
+            &strategy,
+            (
+                &[Protocol::Tcp, Protocol::Udp],
+                &[(Protocol::Udp, udp_ports)],
+            ),
+            &[123],
+            &[],
+        );
+        assert_eq!(
+            plan[3],
+            "udp ports 3 listed port(s), 2 port(s) after exclusions"
+        );
+        assert_eq!(plan[4], "204 socket(s) to scan");
+
+        let tcp_ports = PortStrategy::pick(&None, Some(vec![22, 80]), ScanOrder::Serial, None);
+        let udp_ports = PortStrategy::pick(&None, Some(vec![53]), ScanOrder::Serial, None);
+        assert_eq!(
+            dry_run_plan(
+                &ips,
+                4500,
+                &strategy,
+                (
+                    &[Protocol::Tcp, Protocol::Udp],
+                    &[(Protocol::Tcp, tcp_ports), (Protocol::Udp, udp_ports)],
+                ),
+                &[],
+                &[],
+            ),
+            vec![
+                "2 target IP(s)",
+                "batch size 4500",
+                "tcp ports 2 listed port(s), 2 port(s) after exclusions",
+                "udp ports 1 listed port(s), 1 port(s) after exclusions",
+                "6 socket(s) to scan",
+                "no scripts would run",
+            ]
+        );
+    }
+
+    #[test]
+    fn udp_ports_alone_scan_only_udp() {
+        let udp_ports = || {
+            vec![(
+                Protocol::Udp,
+                PortStrategy::pick(&None, Some(vec![53]), ScanOrder::Serial, None),
+            )]
+        };
+
+        assert_eq!(
+            scan_protocols(None, false, false, &udp_ports()),
+            vec![Protocol::Udp]
+        );
+        assert_eq!(
+            scan_protocols(None, false, true, &udp_ports()),
+            vec![Protocol::Tcp, Protocol::Udp]
+        );
+        assert_eq!(
+            scan_protocols(Some(&[Protocol::Tcp]), false, false, &udp_ports()),
+            vec![Protocol::Tcp, Protocol::Udp]
+        );
+        assert_eq!(
+            scan_protocols(None, true, false, &udp_ports()),
+            vec![Protocol::Udp]
+        );
+        assert_eq!(scan_protocols(None, false, false, &[]), vec![Protocol::Tcp]);
+    }
+
+    #[test]
+    fn port_state_lines_per_host() {
+        let ips: Vec<IpAddr> = vec!["10.0.0.2".parse().unwrap(), "10.0.0.1".parse().unwrap()];
+        let mut states_per_ip = vec![
+            (
+                (ips[1], false),
+                vec![(443, PortState::Open), (53, PortState::Filtered)],
+            ),
+            ((ips[1], true), vec![(161, PortState::OpenFiltered)]),
+            ((ips[0], false), vec![(80, PortState::Open)]),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            port_state_lines(&ips, &mut states_per_ip, &HashMap::new(), &HashMap::new()),
+            vec![
+                "10.0.0.2 -> [80:open]",
+                "10.0.0.1 -> [53:filtered,443:open,161/udp:open|filtered]",
+            ]
+        );
+    }
+
+    #[test]
+    fn host_label_with_hostname() {
+        let ip = "93.184.216.34".parse().unwrap();
+        let mut hostnames = HashMap::new();
+        let mut ptrs = HashMap::new();
+
+        assert_eq!(host_label(ip, &hostnames, &ptrs), "93.184.216.34");
+        hostnames.insert(ip, "example.com".to_owned());
+        assert_eq!(
+            host_label(ip, &hostnames, &ptrs),
+            "example.com (93.184.216.34)"
+        );
+        ptrs.insert(ip, Some("edge.example.net".to_owned()));
+        assert_eq!(
+            host_label(ip, &hostnames, &ptrs),
+            "example.com (93.184.216.34) (PTR edge.example.net)"
+        );
+        ptrs.insert(ip, None);
+        assert_eq!(
+            host_label(ip, &HashMap::new(), &ptrs),
+            "93.184.216.34 (no PTR)"
+        );
+    }
 
-        let batch_size = infer_batch_size(&opts, 1_000_000);
+    #[test]
+    fn stats_lines_show_limits() {
+        let stats = ScanStats {
+            attempts: 1_200,
+            retries: 200,
+            open: 3,
+            closed: 990,
+            filtered: 5,
+            errors: 2,
+            local_errors: 0,
+            duration: Duration::from_secs(4),
+        };
+
+        assert_eq!(
+            stats_lines(&stats, 4500, Some(500)),
+            vec![
+                "1200 connection attempt(s), 200 of them retries",
+                "3 open, 990 closed, 5 filtered, 2 error(s)",
+                "300.0 probes/s over 4.000s (batch size 4500, max rate 500/s)",
+            ]
+        );
+        assert!(stats_lines(&stats, 10, None)[2].ends_with("(batch size 10, no max rate)"));
+
+        let stats = ScanStats {
+            local_errors: 7,
+            ..stats
+        };
+        assert_eq!(
+            stats_lines(&stats, 4500, None)[3],
+            "7 local resource error(s) backed off from"
+        );
+    }
+
+    #[test]
+    fn union_keeps_first_listing() {
+        assert_eq!(
+            union_ports(vec![443, 22, 80], vec![22, 8080, 443, 21]),
+            vec![443, 22, 80, 8080, 21]
+        );
+    }
+
+    #[test]
+    fn host_tuning_cidrs() {
+        let tuning = |cidr: &str, batch_size| HostTuning {
+            cidr: cidr.to_owned(),
+            batch_size,
+        };
+        let parsed = host_batch_sizes(&[tuning("10.0.0.0/24", 500), tuning("::1", 5)]).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].0.contains(&"10.0.0.9".parse().unwrap()));
+        assert_eq!(parsed[1].1, 5);
+
+        assert_eq!(
+            host_batch_sizes(&[tuning("10.0.0.0/33", 500)]).unwrap_err(),
+            "host_tuning CIDR \"10.0.0.0/33\" is not valid"
+        );
+        assert_eq!(
+            host_batch_sizes(&[tuning("10.0.0.0/24", 0)]).unwrap_err(),
+            "host_tuning batch_size for 10.0.0.0/24 has to be at least 1"
+        );
+    }
+
+    #[test]
+    fn no_open_ports_advice_levels() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(no_open_ports_advice(ip, Advice::Off, 4500), None);
+        assert_eq!(
+            no_open_ports_advice(ip, Advice::Short, 4500).as_deref(),
+            Some("no open ports found on 10.0.0.1, run with --verbose for hints")
+        );
+        let verbose = no_open_ports_advice(ip, Advice::Verbose, 4500).unwrap();
+        assert!(verbose.contains("i used 4500 batch size"));
+    }
+
+    #[test]
+    fn count_lines_follow_input_order() {
+        let ips: Vec<IpAddr> = vec![
+            "10.0.0.2".parse().unwrap(),
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.3".parse().unwrap(),
+        ];
+        let ports_per_ip: HashMap<IpAddr, Vec<u16>> =
+            vec![(ips[0], vec![22, 80, 443]), (ips[2], vec![8080])]
+                .into_iter()
+                .collect();
+        let hostnames = vec![(ips[2], "web".to_owned())].into_iter().collect();
 
-        assert_eq!(batch_size, opts.batch_size);
+        assert_eq!(
+            count_lines(&ips, &ports_per_ip, &hostnames, &HashMap::new(), false),
+            vec![
+                "10.0.0.2: 3 open",
+                "10.0.0.1: 0 open",
+                "web (10.0.0.3): 1 open"
+            ]
+        );
+        assert_eq!(
+            count_lines(&ips, &ports_per_ip, &hostnames, &HashMap::new(), true),
+            vec!["10.0.0.2: 3 open", "web (10.0.0.3): 1 open"]
+        );
     }
 }
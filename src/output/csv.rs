@@ -0,0 +1,118 @@
+//! CSV output, one `ip,port,state,banner,protocol` row per open port.
+//!
+//! Fields are quoted as RFC 4180 describes, so banners with commas, quotes
+//! or line breaks in them stay in their column.
+use super::HostResult;
+
+const HEADER: &str = "ip,port,state,banner,protocol";
+
+/// Renders the results of a scan as CSV, starting with a header line. Hosts
+/// without open ports get no row. `protocol` is the protocol of the
+/// open_ports, like for the XML output.
+///
+/// ```rust
+/// # use rustscan::output::{csv, HostResult};
+/// let results = vec![HostResult {
+///     ip: "127.0.0.1".parse().unwrap(),
+///     hostname: None,
+///     ptr: None,
+///     open_ports: vec![22, 80],
+///     open_udp_ports: vec![],
+///     timing_ms: 1500,
+///     banners: Default::default(),
+///     tls: Default::default(),
+///     partial: false,
+/// }];
+///
+/// let document = csv::to_string(&results, "tcp");
+/// ```
+pub fn to_string(results: &[HostResult], protocol: &str) -> String {
+    let mut document = String::from(HEADER);
+    document.push('\n');
+
+    for host in results {
+        let ports = host.open_ports.iter().map(|port| (protocol, port));
+        let udp_ports = host.open_udp_ports.iter().map(|port| ("udp", port));
+        for (protocol, port) in ports.chain(udp_ports) {
+            // Banners are only grabbed over TCP.
+            let banner = match protocol {
+                "udp" => "",
+                _ => host.banners.get(port).map_or("", String::as_str),
+            };
+            let row = [
+                host.ip.to_string(),
+                port.to_string(),
+                "open".to_owned(),
+                quote(banner),
+                protocol.to_owned(),
+            ];
+            document.push_str(&row.join(","));
+            document.push('\n');
+        }
+    }
+    document
+}
+
+/// Quotes a field when it holds a separator, a quote or a line break,
+/// doubling the quotes inside it.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote, to_string, HostResult};
+
+    #[test]
+    fn renders_open_ports() {
+        let mut results = vec![
+            HostResult {
+                ip: "127.0.0.1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![22, 80],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: vec![(22, "SSH-2.0-OpenSSH_9.6".to_owned())]
+                    .into_iter()
+                    .collect(),
+                tls: Default::default(),
+                partial: false,
+            },
+            HostResult {
+                ip: "::1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+        ];
+
+        assert_eq!(
+            to_string(&results, "tcp"),
+            "ip,port,state,banner,protocol\n127.0.0.1,22,open,SSH-2.0-OpenSSH_9.6,tcp\n127.0.0.1,80,open,,tcp\n"
+        );
+
+        results[0].open_udp_ports = vec![22, 53];
+        assert_eq!(
+            to_string(&results, "tcp"),
+            "ip,port,state,banner,protocol\n127.0.0.1,22,open,SSH-2.0-OpenSSH_9.6,tcp\n127.0.0.1,80,open,,tcp\n127.0.0.1,22,open,,udp\n127.0.0.1,53,open,,udp\n"
+        );
+    }
+
+    #[test]
+    fn quotes_special_fields() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a,b"), "\"a,b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,118 @@
+//! Nmap's grepable (`-oG`) output, for the tools and scripts that parse it.
+//!
+//! Every scanned IP gets a `Status` line, and the ones with open ports a
+//! `Ports` line listing them as `port/state/protocol/owner/service/rpc/version/`,
+//! e.g. `22/open/tcp//ssh///`, the service coming from the well-known ports.
+use super::HostResult;
+use crate::port_strategy::service_name;
+use std::fmt::Write;
+
+/// Renders the results of a scan as grepable nmap output. `protocol` is the
+/// protocol of the open_ports, like for the XML output.
+///
+/// ```rust
+/// # use rustscan::output::{gnmap, HostResult};
+/// let results = vec![HostResult {
+///     ip: "127.0.0.1".parse().unwrap(),
+///     hostname: None,
+///     ptr: None,
+///     open_ports: vec![22, 80],
+///     open_udp_ports: vec![],
+///     timing_ms: 1500,
+///     banners: Default::default(),
+///     tls: Default::default(),
+///     partial: false,
+/// }];
+///
+/// let document = gnmap::to_string(&results, "tcp");
+/// ```
+pub fn to_string(results: &[HostResult], protocol: &str) -> String {
+    let mut document = format!("# RustScan {} scan\n", env!("CARGO_PKG_VERSION"));
+
+    for host in results {
+        // nmap puts the PTR record in the parentheses.
+        let name = host
+            .ptr
+            .as_deref()
+            .or(host.hostname.as_deref())
+            .unwrap_or_default();
+        let up = !host.open_ports.is_empty() || !host.open_udp_ports.is_empty();
+        let status = if up { "Up" } else { "Down" };
+        let _ = writeln!(document, "Host: {} ({name})\tStatus: {status}", host.ip);
+        if !up {
+            continue;
+        }
+
+        let ports = host.open_ports.iter().map(|port| (protocol, *port));
+        let udp_ports = host.open_udp_ports.iter().map(|port| ("udp", *port));
+        let ports: Vec<String> = ports
+            .chain(udp_ports)
+            .map(|(protocol, port)| {
+                let service = service_name(port).unwrap_or_default();
+                format!("{port}/open/{protocol}//{service}///")
+            })
+            .collect();
+        let _ = writeln!(
+            document,
+            "Host: {} ({name})\tPorts: {}",
+            host.ip,
+            ports.join(", ")
+        );
+    }
+
+    let up = results
+        .iter()
+        .filter(|h| !h.open_ports.is_empty() || !h.open_udp_ports.is_empty())
+        .count();
+    let _ = writeln!(
+        document,
+        "# RustScan done -- {} IP address{} ({up} host{} up)",
+        results.len(),
+        if results.len() == 1 { "" } else { "es" },
+        if up == 1 { "" } else { "s" }
+    );
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_string, HostResult};
+
+    #[test]
+    fn renders_hosts_and_ports() {
+        let results = vec![
+            HostResult {
+                ip: "127.0.0.1".parse().unwrap(),
+                hostname: None,
+                ptr: Some("localhost".to_owned()),
+                open_ports: vec![22, 8081],
+                open_udp_ports: vec![53],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+            HostResult {
+                ip: "::1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+        ];
+        let document = to_string(&results, "tcp");
+
+        assert!(document.starts_with("# RustScan "));
+        assert!(document.ends_with(
+            "\nHost: 127.0.0.1 (localhost)\tStatus: Up
+Host: 127.0.0.1 (localhost)\tPorts: 22/open/tcp//ssh///, 8081/open/tcp/////, 53/open/udp//domain///
+Host: ::1 ()\tStatus: Down
+# RustScan done -- 2 IP addresses (1 host up)
+"
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+//! JSON output, a single object holding the [`SCHEMA_VERSION`] and the
+//! array of [`HostResult`] objects, e.g.
+//! `{"version":1,"hosts":[{"ip":"127.0.0.1","open_ports":[22],"timing_ms":1500}]}`.
+use super::{HostResult, SCHEMA_VERSION};
+use serde_derive::Serialize;
+
+/// The document `to_string` serializes.
+#[derive(Debug, Serialize)]
+struct Document<'r> {
+    version: u32,
+    hosts: &'r [HostResult],
+}
+
+/// Serializes the results of a scan into a JSON document.
+///
+/// ```rust
+/// # use rustscan::output::{json, HostResult};
+/// let results = vec![HostResult {
+///     ip: "127.0.0.1".parse().unwrap(),
+///     hostname: None,
+///     ptr: None,
+///     open_ports: vec![22, 80],
+///     open_udp_ports: vec![],
+///     timing_ms: 1500,
+///     banners: Default::default(),
+///     tls: Default::default(),
+///     partial: false,
+/// }];
+///
+/// let document = json::to_string(&results).unwrap();
+/// ```
+pub fn to_string(results: &[HostResult]) -> serde_json::Result<String> {
+    serde_json::to_string(&Document {
+        version: SCHEMA_VERSION,
+        hosts: results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_string, HostResult};
+    use crate::scanner::tls::TlsInfo;
+
+    #[test]
+    fn serializes_hosts() {
+        let results = vec![
+            HostResult {
+                ip: "127.0.0.1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![22, 80],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+            HostResult {
+                ip: "::1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+        ];
+
+        assert_eq!(
+            to_string(&results).unwrap(),
+            r#"{"version":1,"hosts":[{"ip":"127.0.0.1","open_ports":[22,80],"timing_ms":1500},{"ip":"::1","open_ports":[],"timing_ms":1500}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_banners() {
+        let results = vec![HostResult {
+            ip: "127.0.0.1".parse().unwrap(),
+            hostname: None,
+            ptr: None,
+            open_ports: vec![22, 80],
+            open_udp_ports: vec![],
+            timing_ms: 1500,
+            banners: vec![(22, "SSH-2.0-OpenSSH_9.6".to_owned()), (80, String::new())]
+                .into_iter()
+                .collect(),
+            tls: Default::default(),
+            partial: false,
+        }];
+
+        assert_eq!(
+            to_string(&results).unwrap(),
+            r#"{"version":1,"hosts":[{"ip":"127.0.0.1","open_ports":[22,80],"timing_ms":1500,"banners":{"22":"SSH-2.0-OpenSSH_9.6","80":""}}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_partial_hosts() {
+        let results = vec![HostResult {
+            ip: "127.0.0.1".parse().unwrap(),
+            hostname: None,
+            ptr: None,
+            open_ports: vec![22],
+            open_udp_ports: vec![],
+            timing_ms: 1500,
+            banners: Default::default(),
+            tls: Default::default(),
+            partial: true,
+        }];
+
+        assert_eq!(
+            to_string(&results).unwrap(),
+            r#"{"version":1,"hosts":[{"ip":"127.0.0.1","open_ports":[22],"timing_ms":1500,"partial":true}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_tls() {
+        let https = TlsInfo {
+            version: "TLSv1.3".to_owned(),
+            alpn: Some("h2".to_owned()),
+        };
+        let results = vec![HostResult {
+            ip: "127.0.0.1".parse().unwrap(),
+            hostname: None,
+            ptr: None,
+            open_ports: vec![443],
+            open_udp_ports: vec![],
+            timing_ms: 1500,
+            banners: Default::default(),
+            tls: vec![(443, https)].into_iter().collect(),
+            partial: false,
+        }];
+
+        assert_eq!(
+            to_string(&results).unwrap(),
+            r#"{"version":1,"hosts":[{"ip":"127.0.0.1","open_ports":[443],"timing_ms":1500,"tls":{"443":{"version":"TLSv1.3","alpn":"h2"}}}]}"#
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! JSON lines output, one object per open port printed as soon as the port
+//! is found. Meant for log shippers tailing stdout. Every line carries the
+//! [`SCHEMA_VERSION`](super::SCHEMA_VERSION).
+use super::SCHEMA_VERSION;
+use crate::input::Protocol;
+use crate::scanner::{tls::TlsInfo, ScanResult};
+use serde_derive::Serialize;
+use std::net::IpAddr;
+
+/// A port found open, as streamed on its own line.
+#[derive(Debug, Serialize)]
+struct OpenPort<'r> {
+    version: u32,
+    ip: IpAddr,
+    port: u16,
+    protocol: Protocol,
+    state: &'static str,
+    /// How long the connection that found the port open took.
+    rtt_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    banner: Option<&'r str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<&'r TlsInfo>,
+}
+
+/// Serializes an open port into a single line of JSON, without the line
+/// break.
+///
+/// ```rust
+/// # use rustscan::output::jsonl;
+/// # use rustscan::scanner::ScanResult;
+/// fn stream(result: &ScanResult) {
+///     println!("{}", jsonl::to_line(result).unwrap());
+/// }
+/// ```
+pub fn to_line(result: &ScanResult) -> serde_json::Result<String> {
+    serde_json::to_string(&OpenPort {
+        version: SCHEMA_VERSION,
+        ip: result.ip,
+        port: result.port,
+        protocol: result.protocol,
+        state: "open",
+        rtt_ms: result.rtt.as_millis(),
+        banner: result.banner.as_deref(),
+        tls: result.tls.as_ref(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_line;
+    use crate::input::Protocol;
+    use crate::scanner::{tls::TlsInfo, PortState, ScanResult};
+    use std::time::Duration;
+
+    #[test]
+    fn serializes_open_port() {
+        let mut result = ScanResult {
+            ip: "127.0.0.1".parse().unwrap(),
+            port: 443,
+            protocol: Protocol::Tcp,
+            state: PortState::Open,
+            rtt: Duration::from_millis(12),
+            banner: None,
+            tls: None,
+        };
+        assert_eq!(
+            to_line(&result).unwrap(),
+            r#"{"version":1,"ip":"127.0.0.1","port":443,"protocol":"tcp","state":"open","rtt_ms":12}"#
+        );
+
+        result.banner = Some("hello".to_owned());
+        result.tls = Some(TlsInfo {
+            version: "TLSv1.3".to_owned(),
+            alpn: None,
+        });
+        assert_eq!(
+            to_line(&result).unwrap(),
+            r#"{"version":1,"ip":"127.0.0.1","port":443,"protocol":"tcp","state":"open","rtt_ms":12,"banner":"hello","tls":{"version":"TLSv1.3"}}"#
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Provides machine readable representations of scan results.
+//!
+//! The text and JSON lines output are printed as results come in, through
+//! the scanner's [`sink::OutputSink`]. Every other format is built from the
+//! [`HostResult`]s gathered once the scan is done.
+use crate::scanner::tls::TlsInfo;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+/// The version of the shape of the JSON and JSON lines output, the
+/// `version` key of the document and of every line. It goes up whenever a
+/// key is removed, renamed or changes type. New keys alone don't change it,
+/// parsers should ignore the keys they don't know.
+///
+/// Versions:
+///   1. the JSON output became an object holding the version and the array
+///      of hosts under `hosts`, JSON lines got the `version` key.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub mod csv;
+pub mod gnmap;
+pub mod json;
+pub mod jsonl;
+pub mod prometheus;
+pub mod sink;
+pub mod syslog;
+pub mod xml;
+
+/// Scan results for a single scanned IP address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostResult {
+    pub ip: IpAddr,
+    /// The host name the IP was resolved from, only set with
+    /// `--show-hostnames`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The PTR record of the IP, only set with `--resolve-ptr` and when the
+    /// lookup found one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ptr: Option<String>,
+    pub open_ports: Vec<u16>,
+    /// The UDP ports found open when both TCP and UDP were scanned, in which
+    /// case open_ports only holds the TCP ones.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub open_udp_ports: Vec<u16>,
+    /// Time spent in the port scan phase, in milliseconds.
+    pub timing_ms: u64,
+    /// Banners grabbed from the open ports, keyed by port. Left out of the
+    /// output when banner grabbing is off.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub banners: BTreeMap<u16, String>,
+    /// What the open ports that speak TLS negotiated, keyed by port. Left out
+    /// of the output when TLS probing is off.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tls: BTreeMap<u16, TlsInfo>,
+    /// Set when the scan was interrupted before every port of the host got
+    /// probed, so the open ports may be incomplete.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
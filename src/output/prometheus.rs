@@ -0,0 +1,215 @@
+//! The results of a scan as Prometheus metrics, written to a file for the
+//! textfile collector of node_exporter, so that scans run from cron show
+//! exposure drifting over time.
+//!
+//! ```text
+//! # HELP rustscan_open_ports Ports found open on the host.
+//! # TYPE rustscan_open_ports gauge
+//! rustscan_open_ports{ip="10.0.0.1",protocol="tcp"} 2
+//! ```
+use super::sink::{OutputSink, Summary};
+use crate::input::Protocol;
+use crate::scanner::{PortState, ScanResult};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes the metrics of a scan to `path` once it's done. Nothing is
+/// reported per port.
+#[derive(Debug, Clone)]
+pub struct MetricsSink {
+    path: PathBuf,
+    ips: Vec<IpAddr>,
+    protocols: Vec<Protocol>,
+}
+
+impl MetricsSink {
+    /// A sink for the scan of `ips` over `protocols`, which get a count of
+    /// open ports each, even the ones that have none.
+    pub fn new(path: PathBuf, ips: Vec<IpAddr>, protocols: Vec<Protocol>) -> Self {
+        Self {
+            path,
+            ips,
+            protocols,
+        }
+    }
+}
+
+impl OutputSink for MetricsSink {
+    fn on_open_port(&self, _result: &ScanResult) {}
+
+    /// Writes the metrics next to the file first and renames it into place,
+    /// as the collector may read the file at any time.
+    fn on_complete(&self, summary: &Summary<'_>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let content = metrics(summary, &self.ips, &self.protocols, timestamp);
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        if let Err(e) =
+            fs::write(&temporary, content).and_then(|()| fs::rename(&temporary, &self.path))
+        {
+            crate::etagln!(
+                Warning,
+                "can't write the metrics to {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// The metrics in the Prometheus text format.
+fn metrics(
+    summary: &Summary<'_>,
+    ips: &[IpAddr],
+    protocols: &[Protocol],
+    timestamp: u64,
+) -> String {
+    let mut open: HashMap<(IpAddr, Protocol), usize> = HashMap::new();
+    for result in summary.results {
+        if result.state == PortState::Open {
+            *open.entry((result.ip, result.protocol)).or_default() += 1;
+        }
+    }
+    let hosts_up = ips
+        .iter()
+        .filter(|ip| {
+            protocols
+                .iter()
+                .any(|protocol| open.contains_key(&(**ip, *protocol)))
+        })
+        .count();
+
+    let mut text = String::from(
+        "# HELP rustscan_open_ports Ports found open on the host.\n\
+         # TYPE rustscan_open_ports gauge\n",
+    );
+    for ip in ips {
+        for protocol in protocols {
+            let count = open.get(&(*ip, *protocol)).copied().unwrap_or_default();
+            let _ = writeln!(
+                text,
+                "rustscan_open_ports{{ip=\"{ip}\",protocol=\"{protocol}\"}} {count}"
+            );
+        }
+    }
+
+    let totals = [
+        (
+            "rustscan_hosts_scanned",
+            "Hosts the scan went through.",
+            ips.len().to_string(),
+        ),
+        (
+            "rustscan_hosts_up",
+            "Hosts with at least one port found open.",
+            hosts_up.to_string(),
+        ),
+        (
+            "rustscan_scan_duration_seconds",
+            "How long the port scan took.",
+            format!("{:.3}", summary.stats.duration.as_secs_f64()),
+        ),
+        (
+            "rustscan_scan_partial",
+            "1 when the scan was interrupted or hit --max-time or --max-findings.",
+            u8::from(summary.partial).to_string(),
+        ),
+        (
+            "rustscan_last_run_timestamp_seconds",
+            "When the scan finished, in seconds since the Unix epoch.",
+            timestamp.to_string(),
+        ),
+    ];
+    for (name, help, value) in totals {
+        let _ = writeln!(
+            text,
+            "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}"
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{metrics, MetricsSink};
+    use crate::input::Protocol;
+    use crate::output::sink::{OutputSink, Summary};
+    use crate::scanner::{PortState, ScanResult, ScanStats};
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    fn summary(results: &[ScanResult]) -> Summary<'_> {
+        Summary {
+            results,
+            partial: false,
+            timed_out: false,
+            max_findings_reached: false,
+            stats: ScanStats {
+                duration: Duration::from_millis(1_250),
+                ..ScanStats::default()
+            },
+        }
+    }
+
+    #[test]
+    fn metrics_per_host() {
+        let ips: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let open = |port, state| ScanResult {
+            ip: ips[0],
+            port,
+            protocol: Protocol::Tcp,
+            state,
+            rtt: Duration::ZERO,
+            banner: None,
+            tls: None,
+        };
+        let results = vec![
+            open(22, PortState::Open),
+            open(80, PortState::Open),
+            open(443, PortState::Filtered),
+        ];
+
+        assert_eq!(
+            metrics(&summary(&results), &ips, &[Protocol::Tcp], 1_700_000_000),
+            "# HELP rustscan_open_ports Ports found open on the host.
+# TYPE rustscan_open_ports gauge
+rustscan_open_ports{ip=\"10.0.0.1\",protocol=\"tcp\"} 2
+rustscan_open_ports{ip=\"10.0.0.2\",protocol=\"tcp\"} 0
+# HELP rustscan_hosts_scanned Hosts the scan went through.
+# TYPE rustscan_hosts_scanned gauge
+rustscan_hosts_scanned 2
+# HELP rustscan_hosts_up Hosts with at least one port found open.
+# TYPE rustscan_hosts_up gauge
+rustscan_hosts_up 1
+# HELP rustscan_scan_duration_seconds How long the port scan took.
+# TYPE rustscan_scan_duration_seconds gauge
+rustscan_scan_duration_seconds 1.250
+# HELP rustscan_scan_partial 1 when the scan was interrupted or hit --max-time or --max-findings.
+# TYPE rustscan_scan_partial gauge
+rustscan_scan_partial 0
+# HELP rustscan_last_run_timestamp_seconds When the scan finished, in seconds since the Unix epoch.
+# TYPE rustscan_last_run_timestamp_seconds gauge
+rustscan_last_run_timestamp_seconds 1700000000
+"
+        );
+    }
+
+    #[test]
+    fn metrics_file_written() {
+        let path = std::env::temp_dir().join(format!("rustscan-{}.prom", std::process::id()));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let sink = MetricsSink::new(path.clone(), vec![ip], vec![Protocol::Tcp, Protocol::Udp]);
+
+        sink.on_complete(&summary(&[]));
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains("rustscan_open_ports{ip=\"10.0.0.1\",protocol=\"udp\"} 0\n"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Where the scanner reports results as they come in, so that embedding
+//! RustScan doesn't mean scraping stdout.
+//!
+//! ```rust
+//! # use rustscan::output::sink::{OutputSink, Summary};
+//! # use rustscan::scanner::ScanResult;
+//! # use std::sync::Mutex;
+//! #[derive(Default)]
+//! struct Collect(Mutex<Vec<ScanResult>>);
+//!
+//! impl OutputSink for Collect {
+//!     fn on_open_port(&self, result: &ScanResult) {
+//!         self.0.lock().unwrap().push(result.clone());
+//!     }
+//! }
+//! ```
+//!
+//! Registered with `Scanner::with_sink`, a sink replaces [`CliSink`], which
+//! prints what the command line shows.
+use crate::input::Protocol;
+use crate::output::jsonl;
+use crate::scanner::{ScanResult, ScanStats};
+use crate::tui::{styled, Stream};
+use ansi_term::Colour;
+use indicatif::ProgressBar;
+use log::debug;
+use std::fmt;
+use std::io::Write;
+
+/// What a finished scan found, see `OutputSink::on_complete`.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary<'s> {
+    /// Every socket that wasn't found closed, the same as `Scanner::run`
+    /// returns.
+    pub results: &'s [ScanResult],
+    /// Set when the scan stopped before every socket got probed.
+    pub partial: bool,
+    /// Set when it was the max time that stopped the scan, rather than an
+    /// interrupt.
+    pub timed_out: bool,
+    /// Set when it was --max-findings that stopped the scan.
+    pub max_findings_reached: bool,
+    pub stats: ScanStats,
+}
+
+/// Receives the results of a scan. Probes run concurrently, so the calls may
+/// come from several tasks.
+pub trait OutputSink: Send + Sync {
+    /// Called for every open port as soon as it is found, and for the ones
+    /// a resumed checkpoint found open already.
+    fn on_open_port(&self, result: &ScanResult);
+
+    /// Called once the scan is done, whether it completed or not.
+    fn on_complete(&self, _summary: &Summary<'_>) {}
+}
+
+impl fmt::Debug for dyn OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutputSink")
+    }
+}
+
+/// The output of the command line: a line per open port as it is found,
+/// colored unless accessible, or JSON lines. Nothing is printed per port in
+/// greppable mode.
+#[derive(Debug, Clone, Default)]
+pub struct CliSink {
+    pub greppable: bool,
+    pub accessible: bool,
+    pub jsonl: bool,
+    /// Keeps stdout to the results, see `Opts::quiet`.
+    pub quiet: bool,
+    /// The live progress bar, which lines are printed above of.
+    pub progress: Option<ProgressBar>,
+}
+
+impl OutputSink for CliSink {
+    fn on_open_port(&self, result: &ScanResult) {
+        if self.jsonl {
+            match jsonl::to_line(result) {
+                Ok(line) => {
+                    let mut stdout = std::io::stdout().lock();
+                    if let Err(e) = writeln!(stdout, "{line}").and_then(|()| stdout.flush()) {
+                        debug!("Failed to stream {} {}", result.socket(), e);
+                    }
+                }
+                Err(e) => debug!("Failed to serialize {} {}", result.socket(), e),
+            }
+        } else if !self.greppable {
+            // TCP is the default, only UDP ports get their protocol written out.
+            let socket = match result.protocol {
+                Protocol::Tcp => result.socket().to_string(),
+                Protocol::Udp => format!("{}/udp", result.socket()),
+            };
+            let tls = match &result.tls {
+                Some(tls) => format!(" [{tls}]"),
+                None => String::new(),
+            };
+            let banner = match &result.banner {
+                Some(banner) if !banner.is_empty() => format!(" {}", banner.escape_debug()),
+                _ => String::new(),
+            };
+            let line = if self.accessible {
+                format!("Open {socket}{tls}{banner}")
+            } else {
+                format!(
+                    "Open {}{tls}{banner}",
+                    styled(&socket, Colour::Purple.normal(), Stream::Stdout)
+                )
+            };
+            // Printing over a live progress bar would leave half drawn bars behind.
+            match &self.progress {
+                Some(bar) => bar.suspend(|| println!("{line}")),
+                None => println!("{line}"),
+            }
+        }
+    }
+
+    /// Warns that the results are partial, on stderr in greppable or quiet
+    /// mode to keep stdout parseable.
+    fn on_complete(&self, summary: &Summary<'_>) {
+        if !summary.partial {
+            return;
+        }
+        let message = if summary.timed_out {
+            "the scan reached --max-time, results are partial."
+        } else if summary.max_findings_reached {
+            "the scan reached --max-findings, results are partial."
+        } else {
+            "the scan was interrupted, results are partial."
+        };
+        if self.greppable || self.jsonl || self.quiet {
+            crate::etagln!(Warning, "{message}");
+        } else {
+            crate::tagln!(Warning, "{message}");
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Open ports sent to the local syslog daemon as they are found, for setups
+//! where tool output reaches a SIEM through syslog.
+use super::sink::OutputSink;
+use crate::input::SyslogSeverity;
+use crate::scanner::ScanResult;
+use ::syslog::{Facility, Formatter3164, LogFormat, Logger, LoggerBackend, Severity};
+use log::debug;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Logs a line per open port, see `message`. The CLI output is left to the
+/// other sinks.
+pub struct SyslogSink {
+    logger: Mutex<Logger<LoggerBackend, Formatter3164>>,
+    severity: SyslogSeverity,
+}
+
+impl SyslogSink {
+    /// Connects to the local syslog daemon, through `/dev/log` or the other
+    /// usual sockets.
+    pub fn connect(facility: Facility, severity: SyslogSeverity) -> Result<Self, String> {
+        let logger = ::syslog::unix(formatter(facility))
+            .map_err(|e| format!("can't connect to syslog: {e}"))?;
+        Ok(Self {
+            logger: Mutex::new(logger),
+            severity,
+        })
+    }
+}
+
+/// The RFC 3164 header the lines get, naming this process.
+fn formatter(facility: Facility) -> Formatter3164 {
+    Formatter3164 {
+        facility,
+        hostname: None,
+        process: "rustscan".to_owned(),
+        pid: std::process::id(),
+    }
+}
+
+impl fmt::Debug for SyslogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyslogSink")
+            .field("severity", &self.severity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OutputSink for SyslogSink {
+    fn on_open_port(&self, result: &ScanResult) {
+        let severity = match self.severity {
+            SyslogSeverity::Emerg => Severity::LOG_EMERG,
+            SyslogSeverity::Alert => Severity::LOG_ALERT,
+            SyslogSeverity::Crit => Severity::LOG_CRIT,
+            SyslogSeverity::Err => Severity::LOG_ERR,
+            SyslogSeverity::Warning => Severity::LOG_WARNING,
+            SyslogSeverity::Notice => Severity::LOG_NOTICE,
+            SyslogSeverity::Info => Severity::LOG_INFO,
+            SyslogSeverity::Debug => Severity::LOG_DEBUG,
+        };
+        let mut guard = self.logger.lock().unwrap_or_else(|e| e.into_inner());
+        let logger = &mut *guard;
+        if let Err(e) = logger
+            .formatter
+            .format(&mut logger.backend, severity, message(result))
+        {
+            debug!("Failed to log {} to syslog {}", result.socket(), e);
+        }
+    }
+}
+
+/// The line logged for an open port: key=value pairs, with the banner and
+/// TLS details quoted when there are any.
+fn message(result: &ScanResult) -> String {
+    let mut message = format!(
+        "open ip={} port={} protocol={} rtt_ms={}",
+        result.ip,
+        result.port,
+        result.protocol,
+        result.rtt.as_millis()
+    );
+    if let Some(tls) = &result.tls {
+        message.push_str(&format!(" tls={:?}", tls.to_string()));
+    }
+    if let Some(banner) = result.banner.as_deref().filter(|banner| !banner.is_empty()) {
+        message.push_str(&format!(" banner={banner:?}"));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{formatter, message, SyslogSink};
+    use crate::input::{Protocol, SyslogSeverity};
+    use crate::output::sink::OutputSink;
+    use crate::scanner::{tls::TlsInfo, PortState, ScanResult};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn open_port() -> ScanResult {
+        ScanResult {
+            ip: "127.0.0.1".parse().unwrap(),
+            port: 443,
+            protocol: Protocol::Tcp,
+            state: PortState::Open,
+            rtt: Duration::from_millis(12),
+            banner: None,
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn messages_are_key_values() {
+        let mut result = open_port();
+        assert_eq!(
+            message(&result),
+            "open ip=127.0.0.1 port=443 protocol=tcp rtt_ms=12"
+        );
+
+        result.banner = Some("SSH-2.0 \"x\"\r\n".to_owned());
+        result.tls = Some(TlsInfo {
+            version: "TLSv1.3".to_owned(),
+            alpn: Some("h2".to_owned()),
+        });
+        assert_eq!(
+            message(&result),
+            r#"open ip=127.0.0.1 port=443 protocol=tcp rtt_ms=12 tls="TLSv1.3 h2" banner="SSH-2.0 \"x\"\r\n""#
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn logs_open_ports() {
+        let path = std::env::temp_dir().join(format!("rustscan-syslog-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let daemon = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let logger =
+            ::syslog::unix_custom(formatter(::syslog::Facility::LOG_LOCAL3), &path).unwrap();
+        let sink = SyslogSink {
+            logger: Mutex::new(logger),
+            severity: SyslogSeverity::Warning,
+        };
+        sink.on_open_port(&open_port());
+
+        let mut buf = [0u8; 512];
+        let len = daemon.recv(&mut buf).unwrap();
+        let line = String::from_utf8_lossy(&buf[..len]);
+        // local3 is facility 19, warning severity 4: 19 * 8 + 4.
+        assert!(line.starts_with("<156>"), "{}", line);
+        let expected = format!(
+            "rustscan[{}]: open ip=127.0.0.1 port=443 protocol=tcp rtt_ms=12",
+            std::process::id()
+        );
+        assert!(line.ends_with(&expected), "{}", line);
+        let _ = std::fs::remove_file(&path);
+    }
+}
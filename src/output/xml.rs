@@ -0,0 +1,181 @@
+//! Nmap compatible XML output.
+//!
+//! Only the subset of the nmaprun format that importers such as Metasploit's
+//! `db_import` need is written: one `<host>` per scanned IP with its status,
+//! address and open ports.
+use super::HostResult;
+use std::fmt::Write;
+use std::net::IpAddr;
+
+/// Renders the results of a scan as an nmaprun XML document. `protocol` is
+/// written as the protocol of the open_ports, "tcp" or "udp", the
+/// open_udp_ports are always "udp".
+///
+/// ```rust
+/// # use rustscan::output::{xml, HostResult};
+/// let results = vec![HostResult {
+///     ip: "127.0.0.1".parse().unwrap(),
+///     hostname: None,
+///     ptr: None,
+///     open_ports: vec![22, 80],
+///     open_udp_ports: vec![],
+///     timing_ms: 1500,
+///     banners: Default::default(),
+///     tls: Default::default(),
+///     partial: false,
+/// }];
+///
+/// let document = xml::to_string(&results, "tcp");
+/// ```
+pub fn to_string(results: &[HostResult], protocol: &str) -> String {
+    let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    document.push_str("<!DOCTYPE nmaprun>\n");
+    let _ = writeln!(
+        document,
+        "<nmaprun scanner=\"rustscan\" version=\"{}\" xmloutputversion=\"1.05\">",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    for host in results {
+        let state = if host.open_ports.is_empty() && host.open_udp_ports.is_empty() {
+            "down"
+        } else {
+            "up"
+        };
+        let addrtype = match host.ip {
+            IpAddr::V4(_) => "ipv4",
+            IpAddr::V6(_) => "ipv6",
+        };
+
+        document.push_str("<host>");
+        let _ = write!(document, "<status state=\"{state}\" reason=\"user-set\"/>");
+        let _ = write!(
+            document,
+            "<address addr=\"{}\" addrtype=\"{addrtype}\"/>",
+            host.ip
+        );
+        if host.hostname.is_some() || host.ptr.is_some() {
+            document.push_str("<hostnames>");
+            if let Some(hostname) = &host.hostname {
+                let _ = write!(
+                    document,
+                    "<hostname name=\"{}\" type=\"user\"/>",
+                    escape(hostname)
+                );
+            }
+            if let Some(ptr) = &host.ptr {
+                let _ = write!(
+                    document,
+                    "<hostname name=\"{}\" type=\"PTR\"/>",
+                    escape(ptr)
+                );
+            }
+            document.push_str("</hostnames>");
+        }
+        document.push_str("<ports>");
+        let ports = host.open_ports.iter().map(|port| (protocol, port));
+        let udp_ports = host.open_udp_ports.iter().map(|port| ("udp", port));
+        for (protocol, port) in ports.chain(udp_ports) {
+            let reason = if protocol == "udp" {
+                "udp-response"
+            } else {
+                "syn-ack"
+            };
+            let _ = write!(
+                document,
+                "<port protocol=\"{protocol}\" portid=\"{port}\"><state state=\"open\" reason=\"{reason}\"/></port>"
+            );
+        }
+        document.push_str("</ports></host>\n");
+    }
+
+    let up = results
+        .iter()
+        .filter(|h| !h.open_ports.is_empty() || !h.open_udp_ports.is_empty())
+        .count();
+    let _ = writeln!(
+        document,
+        "<runstats><hosts up=\"{up}\" down=\"{}\" total=\"{}\"/></runstats>",
+        results.len() - up,
+        results.len()
+    );
+    document.push_str("</nmaprun>\n");
+    document
+}
+
+/// Escapes the characters XML gives a meaning to, so names found in the DNS
+/// can't break out of the attribute they are written in.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_string, HostResult};
+
+    #[test]
+    fn renders_hosts_and_ports() {
+        let results = vec![
+            HostResult {
+                ip: "127.0.0.1".parse().unwrap(),
+                hostname: None,
+                ptr: None,
+                open_ports: vec![22, 80],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+            HostResult {
+                ip: "::1".parse().unwrap(),
+                hostname: Some("localhost".to_owned()),
+                ptr: Some("localhost".to_owned()),
+                open_ports: vec![],
+                open_udp_ports: vec![],
+                timing_ms: 1500,
+                banners: Default::default(),
+                tls: Default::default(),
+                partial: false,
+            },
+        ];
+        let document = to_string(&results, "tcp");
+
+        assert!(document.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n<nmaprun scanner=\"rustscan\""));
+        assert!(document.contains("<host><status state=\"up\" reason=\"user-set\"/><address addr=\"127.0.0.1\" addrtype=\"ipv4\"/><ports><port protocol=\"tcp\" portid=\"22\"><state state=\"open\" reason=\"syn-ack\"/></port><port protocol=\"tcp\" portid=\"80\"><state state=\"open\" reason=\"syn-ack\"/></port></ports></host>\n"));
+        assert!(document.contains("<host><status state=\"down\" reason=\"user-set\"/><address addr=\"::1\" addrtype=\"ipv6\"/><hostnames><hostname name=\"localhost\" type=\"user\"/><hostname name=\"localhost\" type=\"PTR\"/></hostnames><ports></ports></host>\n"));
+        assert!(document.ends_with(
+            "<runstats><hosts up=\"1\" down=\"1\" total=\"2\"/></runstats>\n</nmaprun>\n"
+        ));
+    }
+
+    #[test]
+    fn escapes_names_and_tells_udp_apart() {
+        let results = vec![HostResult {
+            ip: "10.0.0.1".parse().unwrap(),
+            hostname: Some("a&b".to_owned()),
+            ptr: Some("\"/><x y='1'>".to_owned()),
+            open_ports: vec![22],
+            open_udp_ports: vec![53],
+            timing_ms: 1500,
+            banners: Default::default(),
+            tls: Default::default(),
+            partial: false,
+        }];
+        let document = to_string(&results, "tcp");
+
+        assert!(document.contains("<hostnames><hostname name=\"a&amp;b\" type=\"user\"/><hostname name=\"&quot;/&gt;&lt;x y=&apos;1&apos;&gt;\" type=\"PTR\"/></hostnames>"));
+        assert!(document.contains("<port protocol=\"tcp\" portid=\"22\"><state state=\"open\" reason=\"syn-ack\"/></port><port protocol=\"udp\" portid=\"53\"><state state=\"open\" reason=\"udp-response\"/></port>"));
+    }
+}
@@ -1,9 +1,16 @@
 //! Provides a means to hold configuration options specifically for port scanning.
 mod range_iterator;
+mod services;
+mod top_ports;
 use crate::input::{PortRange, ScanOrder};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{Rng, SeedableRng};
 use range_iterator::RangeIterator;
+pub use services::{service_name, service_port, SERVICES};
+use std::collections::HashMap;
+use std::fmt;
+pub use top_ports::TOP_PORTS;
 
 /// Represents options of port scanning.
 ///
@@ -14,10 +21,31 @@ pub enum PortStrategy {
     Manual(Vec<u16>),
     Serial(SerialRange),
     Random(RandomRange),
+    Reverse(ReverseRange),
+    WeightedRandom(RandomRange),
+    Top(usize),
 }
 
 impl PortStrategy {
-    pub fn pick(range: &Option<PortRange>, ports: Option<Vec<u16>>, order: ScanOrder) -> Self {
+    /// Picks the strategy for the given range or ports. `seed` only matters
+    /// for a random order, where the same seed always yields the same order.
+    /// A single port, listed or as a one port range, is the same in any
+    /// order and skips the shuffling.
+    pub fn pick(
+        range: &Option<PortRange>,
+        ports: Option<Vec<u16>>,
+        order: ScanOrder,
+        seed: Option<u64>,
+    ) -> Self {
+        let single_port = match (&ports, range) {
+            (Some(ports), _) if ports.len() == 1 => Some(ports[0]),
+            (None, Some(range)) if range.start == range.end => Some(range.start),
+            _ => None,
+        };
+        if let Some(port) = single_port {
+            return PortStrategy::Manual(vec![port]);
+        }
+
         match order {
             ScanOrder::Serial if ports.is_none() => {
                 let range = range.as_ref().unwrap();
@@ -31,15 +59,52 @@ impl PortStrategy {
                 PortStrategy::Random(RandomRange {
                     start: range.start,
                     end: range.end,
+                    seed,
+                })
+            }
+            ScanOrder::Reverse if ports.is_none() => {
+                let range = range.as_ref().unwrap();
+                PortStrategy::Reverse(ReverseRange {
+                    start: range.start,
+                    end: range.end,
+                })
+            }
+            ScanOrder::WeightedRandom if ports.is_none() => {
+                let range = range.as_ref().unwrap();
+                PortStrategy::WeightedRandom(RandomRange {
+                    start: range.start,
+                    end: range.end,
+                    seed,
                 })
             }
             ScanOrder::Serial => PortStrategy::Manual(ports.unwrap()),
+            ScanOrder::Reverse => {
+                let mut ports = ports.unwrap();
+                ports.sort_unstable_by(|a, b| b.cmp(a));
+                PortStrategy::Manual(ports)
+            }
             ScanOrder::Random => {
-                let mut rng = thread_rng();
+                let mut rng = seeded_rng(seed);
                 let mut ports = ports.unwrap();
                 ports.shuffle(&mut rng);
                 PortStrategy::Manual(ports)
             }
+            ScanOrder::WeightedRandom => {
+                let mut rng = seeded_rng(seed);
+                PortStrategy::Manual(weighted_shuffle(ports.unwrap(), &mut rng))
+            }
+        }
+    }
+
+    /// Picks the `n` most common ports. With a serial order they are
+    /// scanned from most to least common, a random order shuffles them and
+    /// a reverse order scans them from the highest port down.
+    pub fn pick_top(n: usize, order: ScanOrder, seed: Option<u64>) -> Self {
+        match order {
+            ScanOrder::Serial => PortStrategy::Top(n),
+            ScanOrder::Random | ScanOrder::Reverse | ScanOrder::WeightedRandom => {
+                PortStrategy::pick(&None, Some(top_ports(n)), order, seed)
+            }
         }
     }
 
@@ -48,10 +113,84 @@ impl PortStrategy {
             PortStrategy::Manual(ports) => ports.clone(),
             PortStrategy::Serial(range) => range.generate(),
             PortStrategy::Random(range) => range.generate(),
+            PortStrategy::Reverse(range) => range.generate(),
+            PortStrategy::WeightedRandom(range) => {
+                let mut rng = seeded_rng(range.seed);
+                weighted_shuffle((range.start..=range.end).collect(), &mut rng)
+            }
+            PortStrategy::Top(n) => top_ports(*n),
+        }
+    }
+}
+
+impl fmt::Display for PortStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortStrategy::Manual(ports) => write!(f, "{} listed port(s)", ports.len()),
+            PortStrategy::Serial(range) => write!(f, "serial {}-{}", range.start, range.end),
+            PortStrategy::Random(range) => match range.seed {
+                Some(seed) => write!(f, "random {}-{} (seed {seed})", range.start, range.end),
+                None => write!(f, "random {}-{}", range.start, range.end),
+            },
+            PortStrategy::Reverse(range) => write!(f, "reverse {}-{}", range.start, range.end),
+            PortStrategy::WeightedRandom(range) => match range.seed {
+                Some(seed) => write!(
+                    f,
+                    "weighted random {}-{} (seed {seed})",
+                    range.start, range.end
+                ),
+                None => write!(f, "weighted random {}-{}", range.start, range.end),
+            },
+            PortStrategy::Top(n) => write!(f, "top {n} ports"),
         }
     }
 }
 
+/// The `n` most common ports in descending frequency order. Asking for more
+/// than the table holds returns the whole table.
+fn top_ports(n: usize) -> Vec<u16> {
+    TOP_PORTS.iter().take(n).copied().collect()
+}
+
+/// Shuffles the ports so that every port has a chance to come first
+/// proportional to its `port_weight`, the common ports tending to come
+/// early. Each port draws an exponential key scaled down by its weight and
+/// the ports are sorted by key (Efraimidis and Spirakis).
+fn weighted_shuffle(ports: Vec<u16>, rng: &mut StdRng) -> Vec<u16> {
+    let ranks: HashMap<u16, usize> = TOP_PORTS
+        .iter()
+        .enumerate()
+        .map(|(rank, port)| (*port, rank))
+        .collect();
+    let mut keyed: Vec<(f64, u16)> = ports
+        .into_iter()
+        .map(|port| {
+            // gen yields [0, 1), the key needs (0, 1] to stay finite.
+            let draw: f64 = 1.0 - rng.gen::<f64>();
+            (-draw.ln() / port_weight(ranks.get(&port).copied()), port)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    keyed.into_iter().map(|(_, port)| port).collect()
+}
+
+/// The weight of a port from its rank in TOP_PORTS. The first 100 ranks
+/// follow nmap's frequencies and get decreasing weights, the other 900
+/// share a weight since their order carries no meaning, and ports outside
+/// the table get the lowest one.
+fn port_weight(rank: Option<usize>) -> f64 {
+    match rank {
+        Some(rank) if rank < 100 => 1_000.0 - 9.0 * rank as f64,
+        Some(_) => 100.0,
+        None => 1.0,
+    }
+}
+
+/// The RNG behind random orders, seeded from the OS unless a seed is given.
+fn seeded_rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)
+}
+
 /// Trait associated with a port strategy. Each PortStrategy must be able
 /// to generate an order for future port scanning.
 trait RangeOrder {
@@ -72,12 +211,27 @@ impl RangeOrder for SerialRange {
     }
 }
 
+/// As the name implies ReverseRange will always generate a vector in
+/// descending order.
+#[derive(Debug)]
+pub struct ReverseRange {
+    start: u16,
+    end: u16,
+}
+
+impl RangeOrder for ReverseRange {
+    fn generate(&self) -> Vec<u16> {
+        (self.start..=self.end).rev().collect()
+    }
+}
+
 /// As the name implies RandomRange will always generate a vector with
 /// a random order. This vector is built following the LCG algorithm.
 #[derive(Debug)]
 pub struct RandomRange {
     start: u16,
     end: u16,
+    seed: Option<u64>,
 }
 
 impl RangeOrder for RandomRange {
@@ -91,7 +245,8 @@ impl RangeOrder for RandomRange {
     // port numbers close to each other are pretty slim due to the way the
     // algorithm works.
     fn generate(&self) -> Vec<u16> {
-        RangeIterator::new(self.start.into(), self.end.into()).collect()
+        let mut rng = seeded_rng(self.seed);
+        RangeIterator::new(self.start.into(), self.end.into(), &mut rng).collect()
     }
 }
 
@@ -103,38 +258,193 @@ mod tests {
     #[test]
     fn serial_strategy_with_range() {
         let range = PortRange { start: 1, end: 100 };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial);
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
         let result = strategy.order();
-        let expected_range = (1..=100).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=100).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
     }
     #[test]
     fn random_strategy_with_range() {
         let range = PortRange { start: 1, end: 100 };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
         let mut result = strategy.order();
-        let expected_range = (1..=100).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=100).collect::<Vec<u16>>();
         assert_ne!(expected_range, result);
 
         result.sort_unstable();
         assert_eq!(expected_range, result);
     }
 
+    #[test]
+    fn single_port_in_any_order() {
+        let orders = vec![
+            ScanOrder::Serial,
+            ScanOrder::Random,
+            ScanOrder::Reverse,
+            ScanOrder::WeightedRandom,
+        ];
+        for order in orders {
+            let listed = PortStrategy::pick(&None, Some(vec![443]), order, Some(7));
+            assert_eq!(listed.order(), vec![443]);
+            assert_eq!(listed.to_string(), "1 listed port(s)");
+
+            let range = PortRange {
+                start: 443,
+                end: 443,
+            };
+            let ranged = PortStrategy::pick(&Some(range), None, order, Some(7));
+            assert_eq!(ranged.order(), vec![443]);
+        }
+
+        let lowest = PortStrategy::pick(
+            &Some(PortRange { start: 1, end: 1 }),
+            None,
+            ScanOrder::Random,
+            None,
+        );
+        assert_eq!(lowest.order(), vec![1]);
+        let highest = PortStrategy::pick(
+            &Some(PortRange {
+                start: 65_535,
+                end: 65_535,
+            }),
+            None,
+            ScanOrder::Reverse,
+            None,
+        );
+        assert_eq!(highest.order(), vec![65_535]);
+    }
+
     #[test]
     fn serial_strategy_with_ports() {
-        let strategy = PortStrategy::pick(&None, Some(vec![80, 443]), ScanOrder::Serial);
+        let strategy = PortStrategy::pick(&None, Some(vec![80, 443]), ScanOrder::Serial, None);
         let result = strategy.order();
         assert_eq!(vec![80, 443], result);
     }
 
+    #[test]
+    fn reverse_strategy_with_range() {
+        let range = PortRange { start: 1, end: 100 };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Reverse, None);
+        let result = strategy.order();
+        let expected_range = (1..=100).rev().collect::<Vec<u16>>();
+        assert_eq!(expected_range, result);
+    }
+
+    #[test]
+    fn reverse_strategy_with_ports() {
+        let strategy =
+            PortStrategy::pick(&None, Some(vec![80, 8080, 443]), ScanOrder::Reverse, None);
+        assert_eq!(vec![8080, 443, 80], strategy.order());
+
+        let strategy = PortStrategy::pick_top(3, ScanOrder::Reverse, None);
+        assert_eq!(vec![443, 80, 23], strategy.order());
+    }
+
+    #[test]
+    fn top_strategy_in_frequency_order() {
+        let strategy = PortStrategy::pick_top(5, ScanOrder::Serial, None);
+        assert_eq!(vec![80, 23, 443, 21, 22], strategy.order());
+
+        let strategy = PortStrategy::pick_top(5_000, ScanOrder::Serial, None);
+        let mut result = strategy.order();
+        assert_eq!(result.len(), 1_000);
+        result.sort_unstable();
+        result.dedup();
+        assert_eq!(result.len(), 1_000);
+    }
+
+    #[test]
+    fn random_top_strategy() {
+        let strategy = PortStrategy::pick_top(100, ScanOrder::Random, None);
+        let mut result = strategy.order();
+        let mut expected = super::TOP_PORTS[..100].to_vec();
+        assert_ne!(expected, result);
+
+        result.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(expected, result);
+    }
+
     #[test]
     fn random_strategy_with_ports() {
-        let strategy = PortStrategy::pick(&None, Some((1..10).collect()), ScanOrder::Random);
+        let strategy = PortStrategy::pick(&None, Some((1..10).collect()), ScanOrder::Random, None);
         let mut result = strategy.order();
-        let expected_range = (1..10).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..10).collect::<Vec<u16>>();
         assert_ne!(expected_range, result);
 
         result.sort_unstable();
         assert_eq!(expected_range, result);
     }
+
+    #[test]
+    fn seeded_random_strategy_is_reproducible() {
+        let pick = |seed| {
+            let range = PortRange {
+                start: 1,
+                end: 1_000,
+            };
+            PortStrategy::pick(&Some(range), None, ScanOrder::Random, seed).order()
+        };
+        assert_eq!(pick(Some(42)), pick(Some(42)));
+        assert_ne!(pick(Some(42)), pick(Some(43)));
+
+        let pick_ports = |seed| {
+            PortStrategy::pick(&None, Some((1..100).collect()), ScanOrder::Random, seed).order()
+        };
+        assert_eq!(pick_ports(Some(7)), pick_ports(Some(7)));
+
+        let pick_top = |seed| PortStrategy::pick_top(100, ScanOrder::Random, seed).order();
+        assert_eq!(pick_top(Some(7)), pick_top(Some(7)));
+    }
+
+    #[test]
+    fn weighted_random_strategy_favours_common_ports() {
+        let pick = || {
+            let range = PortRange {
+                start: 1,
+                end: 10_000,
+            };
+            PortStrategy::pick(&Some(range), None, ScanOrder::WeightedRandom, Some(3))
+        };
+        let strategy = pick();
+        let mut result = strategy.order();
+        assert_eq!(result, pick().order());
+
+        // Out of 10_000 ports, most of the 100 most common should be among
+        // the first 1_000 probed.
+        let common = super::TOP_PORTS[..100]
+            .iter()
+            .filter(|port| result[..1_000].contains(port))
+            .count();
+        assert!(common > 80, "only {} common ports came early", common);
+
+        result.sort_unstable();
+        assert_eq!(result, (1..=10_000).collect::<Vec<u16>>());
+        assert_eq!(strategy.to_string(), "weighted random 1-10000 (seed 3)");
+
+        let mut top = PortStrategy::pick_top(100, ScanOrder::WeightedRandom, None).order();
+        assert_ne!(top, super::TOP_PORTS[..100].to_vec());
+        top.sort_unstable();
+        let mut expected = super::TOP_PORTS[..100].to_vec();
+        expected.sort_unstable();
+        assert_eq!(top, expected);
+    }
+
+    #[test]
+    fn strategy_display() {
+        let range = PortRange { start: 1, end: 100 };
+        let serial = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
+        let range = PortRange { start: 1, end: 100 };
+        let random = PortStrategy::pick(&Some(range), None, ScanOrder::Random, Some(7));
+        let manual = PortStrategy::pick(&None, Some(vec![80, 443]), ScanOrder::Serial, None);
+
+        assert_eq!(serial.to_string(), "serial 1-100");
+        assert_eq!(random.to_string(), "random 1-100 (seed 7)");
+        assert_eq!(manual.to_string(), "2 listed port(s)");
+        assert_eq!(
+            PortStrategy::pick_top(10, ScanOrder::Serial, None).to_string(),
+            "top 10 ports"
+        );
+    }
 }
@@ -21,13 +21,15 @@ impl RangeIterator {
     ///
     /// For example, the range `1000-2500` will be normalized to `0-1500`
     /// before going through the algorithm.
-    pub fn new(start: u32, end: u32) -> Self {
+    ///
+    /// All the randomness comes from `rng`, so a seeded one always gives
+    /// the same order.
+    pub fn new<R: Rng>(start: u32, end: u32, rng: &mut R) -> Self {
         let normalized_end = end - start + 1;
-        let step = pick_random_coprime(normalized_end);
+        let step = pick_random_coprime(normalized_end, rng);
 
         // Randomly choose a number within the range to be the first
         // and assign it as a pick.
-        let mut rng = rand::thread_rng();
         let normalized_first_pick = rng.gen_range(0..normalized_end);
 
         Self {
@@ -79,11 +81,10 @@ impl Iterator for RangeIterator {
 /// the boundaries, which in these case are the "start" and "end" arguments
 /// would also provide non-ideal randomization as discussed on the paragraph
 /// above.
-fn pick_random_coprime(end: u32) -> u32 {
+fn pick_random_coprime<R: Rng>(end: u32, rng: &mut R) -> u32 {
     let range_boundary = end / 4;
     let lower_range = range_boundary;
     let upper_range = end - range_boundary;
-    let mut rng = rand::thread_rng();
     let mut candidate = rng.gen_range(lower_range..upper_range);
 
     for _ in 0..10 {
@@ -103,28 +104,28 @@ mod tests {
     #[test]
     fn range_iterator_iterates_through_the_entire_range() {
         let result = generate_sorted_range(1, 10);
-        let expected_range = (1..=10).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=10).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
 
         let result = generate_sorted_range(1, 100);
-        let expected_range = (1..=100).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=100).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
 
         let result = generate_sorted_range(1, 1000);
-        let expected_range = (1..=1000).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=1000).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
 
         let result = generate_sorted_range(1, 65_535);
-        let expected_range = (1..=65_535).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1..=65_535).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
 
         let result = generate_sorted_range(1000, 2000);
-        let expected_range = (1000..=2000).into_iter().collect::<Vec<u16>>();
+        let expected_range = (1000..=2000).collect::<Vec<u16>>();
         assert_eq!(expected_range, result);
     }
 
     fn generate_sorted_range(start: u32, end: u32) -> Vec<u16> {
-        let range = RangeIterator::new(start, end);
+        let range = RangeIterator::new(start, end, &mut rand::thread_rng());
         let mut result = range.into_iter().collect::<Vec<u16>>();
         result.sort_unstable();
 
@@ -0,0 +1,116 @@
+/// Well-known service names and the TCP port they listen on, to be written
+/// in place of port numbers, e.g. `-p http,https,ssh`.
+///
+/// The names are nmap-services' with a few common aliases, e.g. `dns` next
+/// to `domain`, sorted by port.
+pub const SERVICES: [(&str, u16); 92] = [
+    ("ftp-data", 20),
+    ("ftp", 21),
+    ("ssh", 22),
+    ("telnet", 23),
+    ("smtp", 25),
+    ("time", 37),
+    ("whois", 43),
+    ("tacacs", 49),
+    ("domain", 53),
+    ("dns", 53),
+    ("gopher", 70),
+    ("finger", 79),
+    ("http", 80),
+    ("www", 80),
+    ("kerberos", 88),
+    ("kerberos-sec", 88),
+    ("pop3", 110),
+    ("rpcbind", 111),
+    ("sunrpc", 111),
+    ("ident", 113),
+    ("auth", 113),
+    ("nntp", 119),
+    ("ntp", 123),
+    ("msrpc", 135),
+    ("netbios-ns", 137),
+    ("netbios-dgm", 138),
+    ("netbios-ssn", 139),
+    ("imap", 143),
+    ("snmp", 161),
+    ("snmptrap", 162),
+    ("bgp", 179),
+    ("irc", 194),
+    ("ldap", 389),
+    ("https", 443),
+    ("microsoft-ds", 445),
+    ("smb", 445),
+    ("kpasswd", 464),
+    ("smtps", 465),
+    ("isakmp", 500),
+    ("rexec", 512),
+    ("rlogin", 513),
+    ("syslog", 514),
+    ("printer", 515),
+    ("rtsp", 554),
+    ("submission", 587),
+    ("ipp", 631),
+    ("ldaps", 636),
+    ("rsync", 873),
+    ("ftps", 990),
+    ("imaps", 993),
+    ("pop3s", 995),
+    ("socks", 1080),
+    ("openvpn", 1194),
+    ("ms-sql-s", 1433),
+    ("mssql", 1433),
+    ("ms-sql-m", 1434),
+    ("oracle", 1521),
+    ("pptp", 1723),
+    ("radius", 1812),
+    ("mqtt", 1883),
+    ("nfs", 2049),
+    ("zookeeper", 2181),
+    ("docker", 2375),
+    ("etcd", 2379),
+    ("ldap-gc", 3268),
+    ("mysql", 3306),
+    ("ms-wbt-server", 3389),
+    ("rdp", 3389),
+    ("svn", 3690),
+    ("epmd", 4369),
+    ("sip", 5060),
+    ("sips", 5061),
+    ("xmpp-client", 5222),
+    ("xmpp-server", 5269),
+    ("postgresql", 5432),
+    ("postgres", 5432),
+    ("amqp", 5672),
+    ("vnc", 5900),
+    ("couchdb", 5984),
+    ("winrm", 5985),
+    ("x11", 6000),
+    ("redis", 6379),
+    ("kubernetes", 6443),
+    ("irc-ssl", 6697),
+    ("http-proxy", 8080),
+    ("http-alt", 8080),
+    ("https-alt", 8443),
+    ("prometheus", 9090),
+    ("kafka", 9092),
+    ("elasticsearch", 9200),
+    ("memcached", 11211),
+    ("mongodb", 27017),
+];
+
+/// The name nmap gives the service listening on `port`, the first one listed
+/// for it.
+pub fn service_name(port: u16) -> Option<&'static str> {
+    SERVICES
+        .iter()
+        .find(|&&(_, service_port)| service_port == port)
+        .map(|&(service, _)| service)
+}
+
+/// The port of the service called `name`, ignoring case.
+pub fn service_port(name: &str) -> Option<u16> {
+    SERVICES
+        .iter()
+        .find(|(service, _)| service.eq_ignore_ascii_case(name))
+        .map(|&(_, port)| port)
+}
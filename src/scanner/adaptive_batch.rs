@@ -0,0 +1,107 @@
+/// Adapts how many probes are in flight to the errors they run into.
+///
+/// Results are looked at in windows as large as the current batch. When
+/// more than half of a window timed out or failed for another reason than
+/// a refused connection, the target or the local network stack is likely
+/// overwhelmed and the batch is halved. A window with less than a tenth of
+/// errors grows it back by a tenth, up to the configured batch size.
+///
+/// Hosts that drop every probe look the same as overwhelmed ones, so a
+/// scan of firewalled hosts will run at the smallest batch.
+#[derive(Debug)]
+pub struct AdaptiveBatch {
+    size: usize,
+    max: usize,
+    min: usize,
+    results: usize,
+    errors: usize,
+}
+
+/// The batch never shrinks below this, unless the configured one is smaller.
+const MIN_BATCH_SIZE: usize = 16;
+const SHRINK_ERROR_RATIO: f64 = 0.5;
+const GROW_ERROR_RATIO: f64 = 0.1;
+
+impl AdaptiveBatch {
+    pub fn new(max: usize) -> Self {
+        Self {
+            size: max,
+            max,
+            min: MIN_BATCH_SIZE.min(max),
+            results: 0,
+            errors: 0,
+        }
+    }
+
+    /// How many probes should currently be in flight.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records the outcome of a probe, resizing the batch at the end of
+    /// every window.
+    pub fn record(&mut self, error: bool) {
+        self.results += 1;
+        if error {
+            self.errors += 1;
+        }
+        if self.results < self.size {
+            return;
+        }
+
+        let error_ratio = self.errors as f64 / self.results as f64;
+        if error_ratio > SHRINK_ERROR_RATIO {
+            self.size = (self.size / 2).max(self.min);
+        } else if error_ratio < GROW_ERROR_RATIO {
+            self.size = (self.size + self.size / 10 + 1).min(self.max);
+        }
+        self.results = 0;
+        self.errors = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AdaptiveBatch;
+
+    fn record_window(batch: &mut AdaptiveBatch, errors: usize) {
+        let size = batch.size();
+        for result in 0..size {
+            batch.record(result < errors);
+        }
+    }
+
+    #[test]
+    fn shrinks_on_error_spikes_and_grows_back() {
+        let mut batch = AdaptiveBatch::new(1_000);
+
+        record_window(&mut batch, 900);
+        assert_eq!(batch.size(), 500);
+        record_window(&mut batch, 500);
+        assert_eq!(batch.size(), 250);
+
+        // Some errors are tolerated without any change.
+        record_window(&mut batch, 50);
+        assert_eq!(batch.size(), 250);
+
+        record_window(&mut batch, 0);
+        assert_eq!(batch.size(), 276);
+        for _ in 0..50 {
+            record_window(&mut batch, 0);
+        }
+        assert_eq!(batch.size(), 1_000);
+    }
+
+    #[test]
+    fn stays_within_bounds() {
+        let mut batch = AdaptiveBatch::new(1_000);
+        for _ in 0..20 {
+            record_window(&mut batch, 1_000);
+        }
+        assert_eq!(batch.size(), 16);
+
+        let mut batch = AdaptiveBatch::new(4);
+        record_window(&mut batch, 4);
+        assert_eq!(batch.size(), 4);
+    }
+}
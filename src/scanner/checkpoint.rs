@@ -0,0 +1,262 @@
+//! Checkpoints recording which sockets a scan already went through, so an
+//! interrupted scan can be resumed.
+//!
+//! The file has one line per host, its scanned ports as ranges followed by
+//! the open ones:
+//!
+//! ```text
+//! # rustscan checkpoint
+//! 127.0.0.1 1-1000,8080 open:22,80
+//! 192.168.0.1 1-500
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+const HEADER: &str = "# rustscan checkpoint";
+
+/// The sockets scanned so far, and which of them were open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    hosts: BTreeMap<IpAddr, HostProgress>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct HostProgress {
+    scanned: PortRanges,
+    open: BTreeSet<u16>,
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint file. A file that doesn't exist yet is an empty
+    /// checkpoint, so the first run and its resumes can share a command.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => content
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the checkpoint to a temporary file first and renames it over
+    /// `path`, so a crash mid-write leaves the previous checkpoint intact.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_string())?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn is_scanned(&self, socket: SocketAddr) -> bool {
+        self.hosts
+            .get(&socket.ip())
+            .is_some_and(|host| host.scanned.contains(socket.port()))
+    }
+
+    /// Marks the socket as scanned, and as open if it was.
+    pub fn record(&mut self, socket: SocketAddr, open: bool) {
+        let host = self.hosts.entry(socket.ip()).or_default();
+        host.scanned.insert(socket.port());
+        if open {
+            host.open.insert(socket.port());
+        }
+    }
+
+    /// Every socket found open so far.
+    pub fn open_sockets(&self) -> Vec<SocketAddr> {
+        self.hosts
+            .iter()
+            .flat_map(|(ip, host)| {
+                host.open
+                    .iter()
+                    .map(move |port| SocketAddr::new(*ip, *port))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for (ip, host) in &self.hosts {
+            write!(f, "{ip} {}", host.scanned)?;
+            if !host.open.is_empty() {
+                let open: Vec<String> = host.open.iter().map(ToString::to_string).collect();
+                write!(f, " open:{}", open.join(","))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Checkpoint {
+    type Err = String;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut checkpoint = Self::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("invalid checkpoint line {}: {line:?}", index + 1);
+
+            let mut fields = line.split_whitespace();
+            let ip: IpAddr = fields
+                .next()
+                .and_then(|ip| ip.parse().ok())
+                .ok_or_else(invalid)?;
+            let scanned = fields
+                .next()
+                .and_then(PortRanges::parse)
+                .ok_or_else(invalid)?;
+            let open = match fields.next() {
+                Some(open) => open
+                    .strip_prefix("open:")
+                    .and_then(|ports| ports.split(',').map(|port| port.parse().ok()).collect())
+                    .ok_or_else(invalid)?,
+                None => BTreeSet::new(),
+            };
+            if fields.next().is_some() {
+                return Err(invalid());
+            }
+            checkpoint.hosts.insert(ip, HostProgress { scanned, open });
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// A set of ports stored as inclusive ranges, keyed by their start. Ranges
+/// next to each other are merged as ports get inserted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PortRanges(BTreeMap<u16, u16>);
+
+impl PortRanges {
+    fn contains(&self, port: u16) -> bool {
+        self.0
+            .range(..=port)
+            .next_back()
+            .is_some_and(|(_, &end)| port <= end)
+    }
+
+    fn insert(&mut self, port: u16) {
+        if self.contains(port) {
+            return;
+        }
+        let mut start = port;
+        let mut end = port;
+        if let Some((&previous_start, &previous_end)) = self.0.range(..port).next_back() {
+            if previous_end.checked_add(1) == Some(port) {
+                start = previous_start;
+            }
+        }
+        if let Some(next_start) = port.checked_add(1) {
+            if let Some(next_end) = self.0.remove(&next_start) {
+                end = next_end;
+            }
+        }
+        self.0.insert(start, end);
+    }
+
+    /// Parses ranges written by Display, e.g. `1-1000,8080`.
+    fn parse(ranges: &str) -> Option<Self> {
+        let mut parsed = BTreeMap::new();
+        for range in ranges.split(',') {
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                None => {
+                    let port = range.parse().ok()?;
+                    (port, port)
+                }
+            };
+            if start > end {
+                return None;
+            }
+            parsed.insert(start, end);
+        }
+        Some(Self(parsed))
+    }
+}
+
+impl fmt::Display for PortRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ranges = String::new();
+        for (start, end) in &self.0 {
+            if !ranges.is_empty() {
+                ranges.push(',');
+            }
+            if start == end {
+                let _ = write!(ranges, "{start}");
+            } else {
+                let _ = write!(ranges, "{start}-{end}");
+            }
+        }
+        f.write_str(&ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, PortRanges};
+    use std::net::{IpAddr, SocketAddr};
+
+    #[test]
+    fn port_ranges_merge() {
+        let mut ranges = PortRanges::default();
+        for port in [5, 1, 3, 2, 65535, 4, 7] {
+            ranges.insert(port);
+        }
+
+        assert_eq!(ranges.to_string(), "1-5,7,65535");
+        assert!(ranges.contains(4));
+        assert!(!ranges.contains(6));
+        assert_eq!(PortRanges::parse("1-5,7,65535"), Some(ranges));
+        assert_eq!(PortRanges::parse("5-1"), None);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut checkpoint = Checkpoint::default();
+        for port in 1..=100 {
+            checkpoint.record(SocketAddr::new(ip, port), port == 22 || port == 80);
+        }
+        checkpoint.record(SocketAddr::new("::1".parse().unwrap(), 443), false);
+
+        let content = checkpoint.to_string();
+        assert_eq!(
+            content,
+            "# rustscan checkpoint\n127.0.0.1 1-100 open:22,80\n::1 443\n"
+        );
+        let parsed: Checkpoint = content.parse().unwrap();
+        assert_eq!(parsed, checkpoint);
+        assert!(parsed.is_scanned(SocketAddr::new(ip, 50)));
+        assert!(!parsed.is_scanned(SocketAddr::new(ip, 101)));
+        assert_eq!(
+            parsed.open_sockets(),
+            vec![SocketAddr::new(ip, 22), SocketAddr::new(ip, 80)]
+        );
+    }
+
+    #[test]
+    fn checkpoint_invalid_line() {
+        assert_eq!(
+            "# rustscan checkpoint\n127.0.0.1 1-10 closed:3\n"
+                .parse::<Checkpoint>()
+                .unwrap_err(),
+            "invalid checkpoint line 2: \"127.0.0.1 1-10 closed:3\""
+        );
+    }
+
+    #[test]
+    fn missing_checkpoint_is_empty() {
+        let path = std::env::temp_dir().join("rustscan-missing-checkpoint");
+        assert_eq!(Checkpoint::read(&path).unwrap(), Checkpoint::default());
+    }
+}
@@ -0,0 +1,111 @@
+//! Where the scanner gets the time from and sleeps with, so that tests can
+//! swap the wall clock for a [`ManualClock`] and check retry delays, backoffs
+//! and max times without waiting for them.
+//!
+//! ```rust
+//! # use rustscan::scanner::clock::{Clock, ManualClock};
+//! # use std::time::Duration;
+//! let clock = ManualClock::default();
+//! let start = clock.now();
+//! futures::executor::block_on(clock.sleep(Duration::from_secs(60)));
+//! assert_eq!(clock.now() - start, Duration::from_secs(60));
+//! ```
+//!
+//! Probe timeouts are left to the socket operations they bound, which run
+//! on the wall clock whatever the scanner's clock is.
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A future a `Clock` sleeps with.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A source of time for `Scanner::with_clock`.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Completes once `duration` passed on this clock.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+/// The wall clock, what scans run on unless told otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// A clock that only moves when told to. Sleeping on it moves it forward by
+/// the duration and completes right away, so the time it shows is the time
+/// the scan would have spent waiting.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl ManualClock {
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// How far the clock moved since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock};
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    #[test]
+    fn manual_clock_moves_when_told() {
+        let clock = ManualClock::default();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_millis(250));
+        block_on(clock.sleep(Duration::from_secs(2)));
+        assert_eq!(clock.elapsed(), Duration::from_millis(2_250));
+        assert_eq!(clock.now() - start, Duration::from_millis(2_250));
+    }
+}
@@ -0,0 +1,124 @@
+use cidr_utils::cidr::IpCidr;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+
+/// Caps the probes in flight to single IPs, for the IPs a batch size
+/// override applies to. Sockets of an IP at its cap are held back until one
+/// of its probes finishes. IPs without an override are let through as is,
+/// leaving them to the scan wide batch size.
+#[derive(Debug, Default)]
+pub struct HostLimits {
+    limits: HashMap<IpAddr, usize>,
+    in_flight: HashMap<IpAddr, usize>,
+    deferred: HashMap<IpAddr, VecDeque<SocketAddr>>,
+    // Held back sockets whose IP got below its cap, to be probed first.
+    ready: VecDeque<SocketAddr>,
+}
+
+impl HostLimits {
+    /// Looks up the cap of every IP, the first override whose CIDR holds the
+    /// IP winning.
+    pub fn new(overrides: &[(IpCidr, usize)], ips: &[IpAddr]) -> Self {
+        let limits = ips
+            .iter()
+            .filter_map(|ip| {
+                overrides
+                    .iter()
+                    .find(|(cidr, _)| cidr.contains(ip))
+                    .map(|(_, limit)| (*ip, (*limit).max(1)))
+            })
+            .collect();
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Takes a socket to probe, handing it back if it can start right away.
+    /// Otherwise it's held back until a probe of its IP finishes.
+    pub fn admit(&mut self, socket: SocketAddr) -> Option<SocketAddr> {
+        let ip = socket.ip();
+        let Some(&limit) = self.limits.get(&ip) else {
+            return Some(socket);
+        };
+        let in_flight = self.in_flight.entry(ip).or_default();
+        if *in_flight < limit {
+            *in_flight += 1;
+            Some(socket)
+        } else {
+            self.deferred.entry(ip).or_default().push_back(socket);
+            None
+        }
+    }
+
+    /// Records that a probe of `ip` is done, which lets a held back socket
+    /// of the same IP start.
+    pub fn finished(&mut self, ip: IpAddr) {
+        let Some(in_flight) = self.in_flight.get_mut(&ip) else {
+            return;
+        };
+        match self.deferred.get_mut(&ip).and_then(VecDeque::pop_front) {
+            Some(socket) => self.ready.push_back(socket),
+            None => *in_flight = in_flight.saturating_sub(1),
+        }
+    }
+
+    /// A held back socket that can start now, if there is one.
+    pub fn next_ready(&mut self) -> Option<SocketAddr> {
+        self.ready.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HostLimits;
+    use cidr_utils::cidr::IpCidr;
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+
+    #[test]
+    fn holds_back_capped_hosts() {
+        let fragile: IpAddr = "10.0.0.7".parse().unwrap();
+        let sturdy: IpAddr = "192.168.0.1".parse().unwrap();
+        let overrides = vec![(IpCidr::from_str("10.0.0.0/24").unwrap(), 1)];
+        let mut limits = HostLimits::new(&overrides, &[fragile, sturdy]);
+
+        let first = SocketAddr::new(fragile, 1);
+        let second = SocketAddr::new(fragile, 2);
+        assert_eq!(limits.admit(first), Some(first));
+        assert_eq!(limits.admit(second), None);
+        for port in 1..=3 {
+            let socket = SocketAddr::new(sturdy, port);
+            assert_eq!(limits.admit(socket), Some(socket));
+        }
+        assert_eq!(limits.next_ready(), None);
+
+        limits.finished(sturdy);
+        assert_eq!(limits.next_ready(), None);
+        limits.finished(fragile);
+        assert_eq!(limits.next_ready(), Some(second));
+        assert_eq!(limits.next_ready(), None);
+
+        // The second probe is in flight now, a third has to wait for it
+        let third = SocketAddr::new(fragile, 3);
+        assert_eq!(limits.admit(third), None);
+        limits.finished(fragile);
+        assert_eq!(limits.next_ready(), Some(third));
+        limits.finished(fragile);
+        assert_eq!(limits.admit(first), Some(first));
+    }
+
+    #[test]
+    fn first_override_wins() {
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+        let overrides = vec![
+            (IpCidr::from_str("10.0.0.0/24").unwrap(), 2),
+            (IpCidr::from_str("10.0.0.0/8").unwrap(), 1),
+        ];
+        let mut limits = HostLimits::new(&overrides, &[ip]);
+
+        assert!(limits.admit(SocketAddr::new(ip, 1)).is_some());
+        assert!(limits.admit(SocketAddr::new(ip, 2)).is_some());
+        assert!(limits.admit(SocketAddr::new(ip, 3)).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+
+/// Hands out the sockets of at most `max_hosts` IPs at a time. Within the
+/// window it goes through the active IPs like SocketIterator does, one port
+/// of each IP before moving on to the next port. An IP leaves the window
+/// once all of its ports were handed out and every probe to it finished,
+/// making room for the next one.
+pub struct HostWindow<'s> {
+    ports: &'s [u16],
+    pending: std::slice::Iter<'s, IpAddr>,
+    active: Vec<ActiveHost>,
+    max_hosts: usize,
+    // Index of the active host the next socket is taken from.
+    cursor: usize,
+}
+
+struct ActiveHost {
+    ip: IpAddr,
+    // Index of the next port to hand out for this host.
+    next_port: usize,
+    in_flight: usize,
+}
+
+impl<'s> HostWindow<'s> {
+    pub fn new(ips: &'s [IpAddr], ports: &'s [u16], max_hosts: NonZeroUsize) -> Self {
+        Self {
+            ports,
+            pending: ips.iter(),
+            active: Vec::with_capacity(max_hosts.get()),
+            max_hosts: max_hosts.get(),
+            cursor: 0,
+        }
+    }
+
+    /// Records that a probe handed out for `ip` is done.
+    pub fn finished(&mut self, ip: IpAddr) {
+        let Some(index) = self.active.iter().position(|host| host.ip == ip) else {
+            return;
+        };
+        let host = &mut self.active[index];
+        host.in_flight = host.in_flight.saturating_sub(1);
+        if host.in_flight == 0 && host.next_port >= self.ports.len() {
+            self.active.remove(index);
+            if index < self.cursor {
+                self.cursor -= 1;
+            }
+        }
+    }
+}
+
+impl<'s> Iterator for HostWindow<'s> {
+    type Item = SocketAddr;
+
+    /// Returns the next socket to probe. None either means every socket was
+    /// handed out, or that the window is full of IPs that are only waiting
+    /// for their last probes, in which case it's worth asking again after
+    /// calling `finished`.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.active.len() < self.max_hosts {
+            let Some(ip) = self.pending.next() else {
+                break;
+            };
+            self.active.push(ActiveHost {
+                ip: *ip,
+                next_port: 0,
+                in_flight: 0,
+            });
+        }
+
+        for _ in 0..self.active.len() {
+            if self.cursor >= self.active.len() {
+                self.cursor = 0;
+            }
+            let host = &mut self.active[self.cursor];
+            self.cursor += 1;
+            if let Some(port) = self.ports.get(host.next_port) {
+                host.next_port += 1;
+                host.in_flight += 1;
+                return Some(SocketAddr::new(host.ip, *port));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HostWindow;
+    use std::net::{IpAddr, SocketAddr};
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn interleaves_hosts_within_window() {
+        let addrs: Vec<IpAddr> = vec![
+            "127.0.0.1".parse().unwrap(),
+            "127.0.0.2".parse().unwrap(),
+            "127.0.0.3".parse().unwrap(),
+        ];
+        let ports = vec![22, 80];
+        let mut window = HostWindow::new(&addrs, &ports, NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[0], 22)));
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[1], 22)));
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[0], 80)));
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[1], 80)));
+        // Both hosts are still waiting for their probes, the third can't start
+        assert_eq!(window.next(), None);
+
+        window.finished(addrs[0]);
+        assert_eq!(window.next(), None);
+        window.finished(addrs[0]);
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[2], 22)));
+        assert_eq!(window.next(), Some(SocketAddr::new(addrs[2], 80)));
+        assert_eq!(window.next(), None);
+    }
+
+    #[test]
+    fn hands_out_every_socket() {
+        let addrs: Vec<IpAddr> = (1..=5)
+            .map(|i| format!("10.0.0.{i}").parse().unwrap())
+            .collect();
+        let ports = vec![1, 2, 3];
+        let mut window = HostWindow::new(&addrs, &ports, NonZeroUsize::new(2).unwrap());
+
+        let mut sockets = Vec::new();
+        while let Some(socket) = window.next() {
+            sockets.push(socket);
+            window.finished(socket.ip());
+        }
+
+        assert_eq!(sockets.len(), 15);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::input::PortRange;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Hands out the local ports of a `--local-port-range` to the probes, going
+/// round the range. A port is only picked again once every other port of the
+/// range was, by which time its last connection is usually done.
+#[derive(Debug)]
+pub struct LocalPorts {
+    start: u16,
+    len: u32,
+    next: AtomicU32,
+}
+
+impl LocalPorts {
+    pub fn new(range: &PortRange) -> Self {
+        Self {
+            start: range.start,
+            len: u32::from(range.end.saturating_sub(range.start)) + 1,
+            next: AtomicU32::new(0),
+        }
+    }
+
+    /// The local port for the next probe. One still in use fails to bind
+    /// with AddrInUse, which gets the probe retried later on the next port.
+    pub fn next(&self) -> u16 {
+        let offset = self.next.fetch_add(1, Ordering::Relaxed) % self.len;
+        // The offset is below len, so it stays within the range.
+        self.start + offset as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalPorts;
+    use crate::input::PortRange;
+
+    #[test]
+    fn goes_round_the_range() {
+        let ports = LocalPorts::new(&PortRange {
+            start: 40000,
+            end: 40002,
+        });
+        let picked: Vec<u16> = (0..7).map(|_| ports.next()).collect();
+        assert_eq!(picked, [40000, 40001, 40002, 40000, 40001, 40002, 40000]);
+
+        let single = LocalPorts::new(&PortRange {
+            start: 65535,
+            end: 65535,
+        });
+        assert_eq!([single.next(), single.next()], [65535, 65535]);
+    }
+}
@@ -1,20 +1,56 @@
 //! Core functionality for actual scanning behaviour.
+use crate::input::{PortRange, PortRule, Protocol};
+use crate::output::sink::{CliSink, OutputSink, Summary};
 use crate::port_strategy::PortStrategy;
+use crate::{etagln, tagln};
 use log::debug;
+use rand::Rng;
 
+mod adaptive_batch;
+pub mod checkpoint;
+pub mod clock;
+mod host_limits;
+mod host_window;
+pub mod http_proxy;
+mod local_ports;
+mod rate_limiter;
 mod socket_iterator;
+pub mod socks5;
+pub mod source_address;
+mod stats;
+pub mod tls;
+use adaptive_batch::AdaptiveBatch;
+use checkpoint::Checkpoint;
+use clock::{Clock, SystemClock};
+use host_limits::HostLimits;
+use host_window::HostWindow;
+use local_ports::LocalPorts;
+use rate_limiter::RateLimiter;
 use socket_iterator::SocketIterator;
+use source_address::Interface;
+use stats::ScanCounters;
+pub use stats::ScanStats;
 
 use async_std::io;
-use async_std::net::TcpStream;
+use async_std::net::{TcpStream, UdpSocket};
 use async_std::prelude::*;
-use colored::Colorize;
+use cidr_utils::cidr::IpCidr;
 use futures::stream::FuturesUnordered;
+use futures_rustls::rustls::ClientConfig;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::{
-    collections::HashSet,
-    net::{IpAddr, Shutdown, SocketAddr},
-    num::NonZeroU8,
-    time::Duration,
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{LineWriter, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr},
+    num::{NonZeroU32, NonZeroU8, NonZeroUsize},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 /// The class for the scanner
@@ -22,20 +58,222 @@ use std::{
 /// start & end is where the port scan starts and ends
 /// batch_size is how many ports at a time should be scanned
 /// Timeout is the time RustScan should wait before declaring a port closed. As datatype Duration.
+/// tcp_timeout and udp_timeout, when set, replace it for the probes of their protocol.
+/// min_timeout and max_timeout, when set, bound the timeout of every try.
 /// greppable is whether or not RustScan should print things, or wait until the end to print only the ip and open ports.
 /// Added by wasuaje - 01/26/2024:
 ///     exclude_ports  is an exclusion port list
+/// protocols are scanned one after the other, over TCP with a connect and
+/// over UDP with a datagram exchange.
+/// protocol_ports, when set for a protocol, are the ports it scans instead of
+/// the ones of port_strategy.
+/// port_rules hold the ports only scanned on hosts where a prerequisite port
+/// was found open, in waves following the scan of the other ports.
+/// max_rate caps how many probes are started per second, on top of batch_size
+/// which only caps how many are in flight.
+/// banner_bytes, when set, is how much of the first data an open TCP port
+/// sends back gets captured.
+/// retry_backoff multiplies the timeout after every failed try, up to
+/// MAX_BACKOFF_TIMEOUT.
+/// retry_jitter is the most a try waits, picked at random, after a failed
+/// one, so retries of probes that timed out together don't go out together.
+/// local_error_backoff is how long to wait before running a try again that
+/// failed on a local resource, see `is_local_error`.
+/// proxy, when set, is a SOCKS5 or HTTP proxy every TCP connection goes through.
+/// source_address, when set, is the local address every probe is sent from.
+/// interface, when set, is the network interface probes are sent from the
+/// addresses of, device being its name when sockets are bound to it too.
+/// fwmark, when set, is the firewall mark (SO_MARK) of every probe socket,
+/// for policy routing on Linux.
+/// local_ports, when set, are the local ports probes are sent from in turn.
+/// output_file, when set, gets every open socket appended as soon as it is found.
+/// ping_first runs a TCP ping sweep over PING_PORTS first, and only port scans
+/// the hosts that answered it.
+/// knock is a sequence of ports every host is connected to before it's
+/// pinged or scanned.
+/// interrupted is a flag that, once raised, stops new probes from being
+/// started. The ones in flight are still waited for.
+/// max_time, when set, gives the deadline once run starts, which has the same
+/// effect as interrupted once it passed, and timed_out records that it
+/// stopped the scan.
+/// max_findings, when set, stops new probes once that many open ports were
+/// found, and max_host_findings skips the rest of a host once it has that
+/// many. findings counts the open ports per IP, and max_findings_reached
+/// records that either left sockets unprobed.
+/// adaptive shrinks the batch while probes run into errors and grows it back
+/// up to batch_size once they stop.
+/// progress, when set, is a bar drawn on stderr counting scanned sockets.
+/// max_hosts, when set, caps how many IPs have probes in flight at once.
+/// host_batch_sizes caps the probes in flight to the IPs of each CIDR.
+/// stats counts the probes and their outcomes across runs, see `stats`.
+/// jsonl prints every open port as a line of JSON instead of the text output.
+/// quiet leaves out the lines on stdout that aren't results.
+/// sink, when set, gets the results instead of the CliSink built from
+/// greppable, accessible, jsonl, quiet and progress. extra_sinks get them
+/// as well, whichever of the two it is.
+/// tls_config, when set, is used for a TLS handshake with every open TCP port.
+/// checkpoint_file, when set, periodically gets the progress of the scan
+/// written to it, and checkpoint holds the progress of the run being resumed.
+/// clock is where the scan gets the time from and sleeps with.
 #[cfg(not(tarpaulin_include))]
 #[derive(Debug)]
 pub struct Scanner {
     ips: Vec<IpAddr>,
     batch_size: u16,
     timeout: Duration,
+    tcp_timeout: Option<Duration>,
+    udp_timeout: Option<Duration>,
+    min_timeout: Option<Duration>,
+    max_timeout: Option<Duration>,
     tries: NonZeroU8,
     greppable: bool,
     port_strategy: PortStrategy,
     accessible: bool,
     exclude_ports: Vec<u16>,
+    protocols: Vec<Protocol>,
+    protocol_ports: Vec<(Protocol, PortStrategy)>,
+    port_rules: Vec<PortRule>,
+    max_rate: Option<NonZeroU32>,
+    banner_bytes: Option<usize>,
+    retry_backoff: f64,
+    retry_jitter: Duration,
+    local_error_backoff: Duration,
+    proxy: Option<Proxy>,
+    source_address: Option<IpAddr>,
+    interface: Option<Interface>,
+    device: Option<String>,
+    fwmark: Option<u32>,
+    local_ports: Option<LocalPorts>,
+    output_file: Option<PathBuf>,
+    ping_first: bool,
+    knock: Vec<u16>,
+    interrupted: Arc<AtomicBool>,
+    max_time: Option<Duration>,
+    deadline: Mutex<Option<Instant>>,
+    timed_out: AtomicBool,
+    max_findings: Option<NonZeroUsize>,
+    max_host_findings: Option<NonZeroUsize>,
+    findings: Mutex<HashMap<IpAddr, usize>>,
+    max_findings_reached: AtomicBool,
+    adaptive: bool,
+    progress: Option<ProgressBar>,
+    tls_config: Option<Arc<ClientConfig>>,
+    max_hosts: Option<NonZeroUsize>,
+    host_batch_sizes: Vec<(IpCidr, usize)>,
+    stats: ScanCounters,
+    checkpoint_file: Option<PathBuf>,
+    checkpoint: Checkpoint,
+    jsonl: bool,
+    quiet: bool,
+    sink: Option<Arc<dyn OutputSink>>,
+    extra_sinks: Vec<Arc<dyn OutputSink>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// How long to wait for an open port to send its banner. Kept short as many
+/// services, HTTP for one, say nothing until they get a request.
+const BANNER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The longest a retry backoff can grow the timeout to. A base timeout that
+/// is already longer is left as is.
+const MAX_BACKOFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ports probed by the ping sweep. A host is up as soon as one of them either
+/// accepts or refuses the connection, as both mean something answered.
+pub const PING_PORTS: [u16; 3] = [80, 443, 22];
+
+/// How long the TLS handshake with an open port may take. Services that
+/// don't speak TLS usually wait for more input instead of closing, so this is
+/// what keeps them from stalling the scan.
+const TLS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How long to wait before trying a connection again that failed for lack of
+/// local resources, unless set with `with_local_error_backoff`.
+const LOCAL_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How many times a single try may hit a local resource error and be run
+/// again before the error is taken as its outcome.
+const MAX_LOCAL_ERROR_RETRIES: u8 = 10;
+
+/// How often the checkpoint file gets rewritten while a scan runs.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a knock waits for its connection, and how long to wait before
+/// the next one. Knock daemons only need to see the SYN, the delay keeps the
+/// knocks from arriving out of order.
+const KNOCK_DELAY: Duration = Duration::from_millis(100);
+
+/// The state a scanned port was found in.
+///   - Open means the port answered: a TCP handshake completed or a UDP
+///     datagram came back.
+///   - OpenFiltered is only used for UDP, where silence can either mean a
+///     service that ignored our probe or a firewall dropping it.
+///   - Filtered is only used for TCP, when the connection attempt timed out
+///     which usually means a firewall silently dropped it.
+///   - Closed means the host actively refused the probe, either with a TCP
+///     RST or an ICMP port unreachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    Open,
+    OpenFiltered,
+    Filtered,
+    Closed,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortState::Open => write!(f, "open"),
+            PortState::OpenFiltered => write!(f, "open|filtered"),
+            PortState::Filtered => write!(f, "filtered"),
+            PortState::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// What the scan found out about a single socket, over protocol.
+/// rtt is how long the try that settled the state took, the full timeout
+/// for filtered and open|filtered ports.
+/// banner is only set for open TCP ports when banner grabbing is enabled.
+/// tls is only set for open TCP ports that completed a TLS handshake when
+/// TLS probing is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub protocol: Protocol,
+    pub state: PortState,
+    pub rtt: Duration,
+    pub banner: Option<String>,
+    pub tls: Option<tls::TlsInfo>,
+}
+
+impl ScanResult {
+    fn new(socket: SocketAddr, state: PortState, rtt: Duration) -> Self {
+        Self {
+            ip: socket.ip(),
+            port: socket.port(),
+            protocol: Protocol::Tcp,
+            state,
+            rtt,
+            banner: None,
+            tls: None,
+        }
+    }
+
+    pub fn socket(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+
+    /// Collapses scan results to the open sockets among them, in the same
+    /// order.
+    pub fn open_sockets(results: &[ScanResult]) -> Vec<SocketAddr> {
+        results
+            .iter()
+            .filter(|result| result.state == PortState::Open)
+            .map(ScanResult::socket)
+            .collect()
+    }
 }
 
 // Allowing too many arguments for clippy.
@@ -50,266 +288,3044 @@ impl Scanner {
         port_strategy: PortStrategy,
         accessible: bool,
         exclude_ports: Vec<u16>,
+        udp: bool,
     ) -> Self {
         Self {
             batch_size,
             timeout,
+            tcp_timeout: None,
+            udp_timeout: None,
+            min_timeout: None,
+            max_timeout: None,
+            // 0 tries would never probe, it's taken as a single try.
             tries: NonZeroU8::new(std::cmp::max(tries, 1)).unwrap(),
             greppable,
             port_strategy,
             ips: ips.iter().map(ToOwned::to_owned).collect(),
             accessible,
             exclude_ports,
+            protocols: vec![if udp { Protocol::Udp } else { Protocol::Tcp }],
+            protocol_ports: Vec::new(),
+            port_rules: Vec::new(),
+            max_rate: None,
+            banner_bytes: None,
+            retry_backoff: 1.0,
+            retry_jitter: Duration::ZERO,
+            local_error_backoff: LOCAL_ERROR_BACKOFF,
+            proxy: None,
+            source_address: None,
+            interface: None,
+            device: None,
+            fwmark: None,
+            local_ports: None,
+            output_file: None,
+            ping_first: false,
+            knock: Vec::new(),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            max_time: None,
+            deadline: Mutex::new(None),
+            timed_out: AtomicBool::new(false),
+            max_findings: None,
+            max_host_findings: None,
+            findings: Mutex::new(HashMap::new()),
+            max_findings_reached: AtomicBool::new(false),
+            adaptive: false,
+            progress: None,
+            tls_config: None,
+            max_hosts: None,
+            host_batch_sizes: Vec::new(),
+            stats: ScanCounters::default(),
+            checkpoint_file: None,
+            checkpoint: Checkpoint::default(),
+            jsonl: false,
+            quiet: false,
+            sink: None,
+            extra_sinks: Vec::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Limits the scan to at most `max_rate` probes started per second.
+    /// `None` or 0 leaves the scan unthrottled.
+    pub fn with_max_rate(mut self, max_rate: Option<u32>) -> Self {
+        self.max_rate = max_rate.and_then(NonZeroU32::new);
+        self
+    }
+
+    /// Reads up to `banner_bytes` from every open TCP port before closing
+    /// the connection. Ports that stay silent get an empty banner.
+    pub fn with_banner(mut self, banner_bytes: Option<usize>) -> Self {
+        self.banner_bytes = banner_bytes.filter(|&bytes| bytes > 0);
+        self
+    }
+
+    /// Waits `tcp_timeout` rather than the timeout for TCP connections,
+    /// ping sweep and knocks included. `None` keeps the timeout.
+    pub fn with_tcp_timeout(mut self, tcp_timeout: Option<Duration>) -> Self {
+        self.tcp_timeout = tcp_timeout;
+        self
+    }
+
+    /// Waits `udp_timeout` rather than the timeout for a reply to a UDP
+    /// probe, which often needs longer. `None` keeps the timeout.
+    pub fn with_udp_timeout(mut self, udp_timeout: Option<Duration>) -> Self {
+        self.udp_timeout = udp_timeout;
+        self
+    }
+
+    /// Keeps the timeout of every try within `min_timeout` and `max_timeout`,
+    /// whatever the protocol timeouts and the retry backoff make of it.
+    /// `None` leaves that side unbounded.
+    pub fn with_timeout_bounds(
+        mut self,
+        min_timeout: Option<Duration>,
+        max_timeout: Option<Duration>,
+    ) -> Self {
+        self.min_timeout = min_timeout;
+        self.max_timeout = max_timeout;
+        self
+    }
+
+    /// Multiplies the timeout by `retry_backoff` after each failed try, so
+    /// the n-th try waits `timeout * retry_backoff^(n - 1)`. `None` or a
+    /// multiplier below 1 keeps the same timeout for every try.
+    pub fn with_retry_backoff(mut self, retry_backoff: Option<f64>) -> Self {
+        self.retry_backoff = retry_backoff
+            .filter(|backoff| backoff.is_finite() && *backoff >= 1.0)
+            .unwrap_or(1.0);
+        self
+    }
+
+    /// Waits a random delay of up to `retry_jitter` milliseconds before
+    /// every retry. `None` or 0 retries right away.
+    pub fn with_retry_jitter(mut self, retry_jitter: Option<u64>) -> Self {
+        self.retry_jitter = retry_jitter.map_or(Duration::ZERO, Duration::from_millis);
+        self
+    }
+
+    /// Waits `local_error_backoff` milliseconds before running a try again
+    /// that failed for lack of local resources, like free ephemeral ports.
+    /// Such tries don't count against `tries`. `None` keeps
+    /// LOCAL_ERROR_BACKOFF.
+    pub fn with_local_error_backoff(mut self, local_error_backoff: Option<u64>) -> Self {
+        self.local_error_backoff =
+            local_error_backoff.map_or(LOCAL_ERROR_BACKOFF, Duration::from_millis);
+        self
+    }
+
+    /// Routes every TCP connection through `proxy`. The timeout covers the
+    /// whole proxied connection.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Sends every probe from `source_address`, which has to be an address
+    /// of this host, see `source_address::check`. Targets of the other IP
+    /// family can't be reached from it.
+    pub fn with_source_address(mut self, source_address: Option<IpAddr>) -> Self {
+        self.source_address = source_address;
+        self
+    }
+
+    /// Sends every probe from the address of `interface` of the target's IP
+    /// family. With `bind_device` the sockets are bound to the interface
+    /// itself as well, see `source_address::check_device`.
+    pub fn with_interface(mut self, interface: Option<Interface>, bind_device: bool) -> Self {
+        self.device = interface
+            .as_ref()
+            .filter(|_| bind_device)
+            .map(|interface| interface.name.clone());
+        self.interface = interface;
+        self
+    }
+
+    /// Marks every probe socket with `fwmark`, so policy routing rules can
+    /// send the scan through a given table. Linux only, see
+    /// `source_address::check_mark`. Ignored for connections through a proxy.
+    pub fn with_fwmark(mut self, fwmark: Option<u32>) -> Self {
+        self.fwmark = fwmark;
+        self
+    }
+
+    /// Sends the probes from the ports of `range` in turn, rather than from
+    /// ephemeral ports picked by the OS, for firewalls that only let some
+    /// source ports through. Ignored for connections through a proxy.
+    pub fn with_local_port_range(mut self, range: Option<&PortRange>) -> Self {
+        self.local_ports = range.map(LocalPorts::new);
+        self
+    }
+
+    /// Appends every open socket to `output_file`, one `ip:port` per line,
+    /// as soon as it is found. Lines are flushed right away so the file can
+    /// be read mid-scan and survives the scan being interrupted.
+    pub fn with_output_file(mut self, output_file: Option<PathBuf>) -> Self {
+        self.output_file = output_file;
+        self
+    }
+
+    /// Skips the hosts that don't answer a TCP ping on any of PING_PORTS.
+    /// Raw sockets would be needed for an ICMP echo, so it isn't used.
+    pub fn with_ping_first(mut self, ping_first: bool) -> Self {
+        self.ping_first = ping_first;
+        self
+    }
+
+    /// Connects to these ports of every host, one after the other, before
+    /// the host gets pinged or scanned. Whether the knocks connect doesn't
+    /// matter.
+    pub fn with_knock(mut self, knock: Vec<u16>) -> Self {
+        self.knock = knock;
+        self
+    }
+
+    /// Shares a flag with the caller, typically raised by a SIGINT handler.
+    /// Once it is set the scan stops starting new probes and returns what
+    /// was found so far, see `is_interrupted`.
+    pub fn with_interrupt(mut self, interrupted: Arc<AtomicBool>) -> Self {
+        self.interrupted = interrupted;
+        self
+    }
+
+    /// Stops starting new probes once `max_time` has passed since the scan
+    /// was run, waiting for the ones in flight like an interrupt does, see
+    /// `is_timed_out`. A warmup doesn't use up any of it.
+    pub fn with_max_time(mut self, max_time: Option<Duration>) -> Self {
+        self.max_time = max_time;
+        self
+    }
+
+    /// Stops starting new probes once `total` open ports were found, and
+    /// skips the sockets left of a host once `per_host` of its ports were
+    /// found open. The probes in flight are still waited for, so a few more
+    /// can turn up. `None` or 0 doesn't limit, see `is_max_findings_reached`.
+    pub fn with_max_findings(mut self, total: Option<usize>, per_host: Option<usize>) -> Self {
+        self.max_findings = total.and_then(NonZeroUsize::new);
+        self.max_host_findings = per_host.and_then(NonZeroUsize::new);
+        self
+    }
+
+    /// Reads the time from and sleeps with `clock` instead of the wall
+    /// clock, see `clock::ManualClock`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Lets the number of probes in flight follow the error rate instead of
+    /// always being batch_size, see `AdaptiveBatch`.
+    pub fn with_adaptive_batch(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Whether the scan was interrupted, meaning its results are partial.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Whether the scan ran past its max_time with sockets left to probe,
+    /// meaning its results are partial.
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::SeqCst)
+    }
+
+    /// Whether max_findings or max_host_findings left sockets unprobed,
+    /// meaning the results are partial.
+    pub fn is_max_findings_reached(&self) -> bool {
+        self.max_findings_reached.load(Ordering::SeqCst)
+    }
+
+    /// Counts an open port of `ip` towards max_findings.
+    fn record_finding(&self, ip: IpAddr) {
+        *self.findings.lock().unwrap().entry(ip).or_default() += 1;
+    }
+
+    /// Whether `ip` has max_host_findings open ports already.
+    fn host_findings_reached(&self, ip: IpAddr) -> bool {
+        self.max_host_findings.is_some_and(|max| {
+            self.findings
+                .lock()
+                .unwrap()
+                .get(&ip)
+                .copied()
+                .unwrap_or_default()
+                >= max.get()
+        })
+    }
+
+    /// Whether max_findings open ports were found across all hosts.
+    fn total_findings_reached(&self) -> bool {
+        self.max_findings
+            .is_some_and(|max| self.findings.lock().unwrap().values().sum::<usize>() >= max.get())
+    }
+
+    /// The next socket to probe, none once the scan got interrupted, ran
+    /// past its max_time or found max_findings open ports.
+    fn next_socket(&self, sockets: &mut impl Iterator<Item = SocketAddr>) -> Option<SocketAddr> {
+        if self.is_interrupted() || self.is_timed_out() {
+            return None;
+        }
+        self.before_deadline(sockets.next()?)
+    }
+
+    /// Hands the socket back, unless the deadline passed or max_findings
+    /// open ports were found, which leaves it and every socket after it
+    /// unprobed.
+    fn before_deadline(&self, socket: SocketAddr) -> Option<SocketAddr> {
+        if self.deadline_passed() {
+            self.timed_out.store(true, Ordering::SeqCst);
+            return None;
+        }
+        if self.total_findings_reached() {
+            self.max_findings_reached.store(true, Ordering::SeqCst);
+            return None;
+        }
+        Some(socket)
+    }
+
+    /// Starts counting max_time from now.
+    fn start_deadline(&self) {
+        *self.deadline.lock().unwrap() = self.max_time.map(|max_time| self.clock.now() + max_time);
+    }
+
+    /// Whether the deadline started by `start_deadline`, if any, passed.
+    fn deadline_passed(&self) -> bool {
+        self.deadline
+            .lock()
+            .unwrap()
+            .is_some_and(|deadline| self.clock.now() >= deadline)
+    }
+
+    /// Same as `next_socket`, skipping the sockets the checkpoint says were
+    /// already scanned and the ones of hosts at their max_host_findings.
+    fn next_pending_socket(
+        &self,
+        sockets: &mut Sockets<'_>,
+        checkpoint: &Checkpoint,
+    ) -> Option<SocketAddr> {
+        while let Some(socket) = self.next_socket(sockets) {
+            if self.host_findings_reached(socket.ip()) {
+                self.max_findings_reached.store(true, Ordering::SeqCst);
+            } else if !checkpoint.is_scanned(socket) {
+                return Some(socket);
+            }
+            sockets.finished(socket.ip());
+            if let Some(bar) = &self.progress {
+                bar.inc(1);
+            }
+        }
+        None
+    }
+
+    /// Same as `next_pending_socket`, holding back the sockets of IPs that
+    /// are at their batch size until one of their probes finishes. The held
+    /// back sockets that can start are handed out first.
+    fn next_admitted_socket(
+        &self,
+        sockets: &mut Sockets<'_>,
+        checkpoint: &Checkpoint,
+        host_limits: &mut HostLimits,
+    ) -> Option<SocketAddr> {
+        if self.is_interrupted() || self.is_timed_out() {
+            return None;
+        }
+        if let Some(socket) = host_limits.next_ready() {
+            return self.before_deadline(socket);
+        }
+        while let Some(socket) = self.next_pending_socket(sockets, checkpoint) {
+            if let Some(socket) = host_limits.admit(socket) {
+                return Some(socket);
+            }
+        }
+        None
+    }
+
+    /// Writes the checkpoint, if there is a file to write it to. Failing to
+    /// do so is reported but doesn't stop the scan.
+    fn write_checkpoint(&self, checkpoint: &Checkpoint) {
+        let Some(path) = &self.checkpoint_file else {
+            return;
+        };
+        if let Err(e) = checkpoint.write(path) {
+            etagln!(
+                Warning,
+                "failed to write checkpoint {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    /// The timeout of the probes over `protocol`.
+    fn protocol_timeout(&self, protocol: Protocol) -> Duration {
+        let timeout = match protocol {
+            Protocol::Tcp => self.tcp_timeout,
+            Protocol::Udp => self.udp_timeout,
+        };
+        self.bounded(timeout.unwrap_or(self.timeout))
+    }
+
+    /// The timeout within min_timeout and max_timeout.
+    fn bounded(&self, timeout: Duration) -> Duration {
+        let timeout = self.min_timeout.map_or(timeout, |min| timeout.max(min));
+        self.max_timeout.map_or(timeout, |max| timeout.min(max))
+    }
+
+    /// The timeout of the given try over `protocol`, counting from 1.
+    fn try_timeout(&self, nr_try: u8, protocol: Protocol) -> Duration {
+        let base = self.protocol_timeout(protocol);
+        if self.retry_backoff <= 1.0 || base >= MAX_BACKOFF_TIMEOUT {
+            return base;
+        }
+        let factor = self.retry_backoff.powi(i32::from(nr_try) - 1);
+        let timeout = base.as_secs_f64() * factor;
+        self.bounded(Duration::from_secs_f64(
+            timeout.min(MAX_BACKOFF_TIMEOUT.as_secs_f64()),
+        ))
+    }
+
+    /// How long to wait before the given try, counting from 1: nothing
+    /// before the first, and a random part of retry_jitter before the others.
+    fn try_delay(&self, nr_try: u8) -> Duration {
+        if nr_try == 1 || self.retry_jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=self.retry_jitter)
+    }
+
+    /// Draws a live count of scanned sockets and open ports on stderr, so
+    /// greppable output on stdout stays untouched.
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress.then(|| {
+            let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "[{elapsed_precise}] {bar:40} {pos}/{len} sockets, {msg}",
+                )
+                .unwrap(),
+            );
+            bar
+        });
+        self
+    }
+
+    /// Opens a second connection to every open TCP port and attempts a TLS
+    /// handshake on it, recording the negotiated version and ALPN protocol.
+    /// The certificate is not verified.
+    pub fn with_tls_probe(mut self, tls_probe: bool) -> Self {
+        self.tls_config = tls_probe.then(tls::client_config);
+        self
+    }
+
+    /// Only probes up to `max_hosts` IPs at a time, moving on to the next IP
+    /// once every port of one is done. batch_size still caps the probes in
+    /// flight across all of them. `None` or 0 scans every IP at once.
+    pub fn with_max_hosts(mut self, max_hosts: Option<usize>) -> Self {
+        self.max_hosts = max_hosts.and_then(NonZeroUsize::new);
+        self
+    }
+
+    /// Writes which sockets were scanned, and which of them were open, to
+    /// `checkpoint_file` every CHECKPOINT_INTERVAL and once the scan ends.
+    /// The sockets already in `resume_from` are not probed again, its open
+    /// ones are reported as if they were just found.
+    pub fn with_checkpoint(
+        mut self,
+        checkpoint_file: Option<PathBuf>,
+        resume_from: Checkpoint,
+    ) -> Self {
+        self.checkpoint_file = checkpoint_file;
+        self.checkpoint = resume_from;
+        self
+    }
+
+    /// Prints every open port as a line of JSON as soon as it is found, see
+    /// `output::jsonl`, even in greppable mode. Lines are flushed right away.
+    pub fn with_jsonl(mut self, jsonl: bool) -> Self {
+        self.jsonl = jsonl;
+        self
+    }
+
+    /// Only prints results on stdout, moving the partial results warning to
+    /// stderr and leaving out how many hosts answered the ping sweep.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Scans the ports over every protocol, in this order, instead of just
+    /// the one picked by `udp` in `new`.
+    pub fn with_protocols(mut self, protocols: &[Protocol]) -> Self {
+        let mut unique = Vec::new();
+        for protocol in protocols {
+            if !unique.contains(protocol) {
+                unique.push(*protocol);
+            }
+        }
+        if !unique.is_empty() {
+            self.protocols = unique;
+        }
+        self
+    }
+
+    /// Scans the ports of `port_strategy` over `protocol` instead of the
+    /// ones given to `new`, adding the protocol to the ones scanned. Meant
+    /// to be set after `with_protocols`, e.g. for TCP 1-1024 along with UDP
+    /// 53,123,161 only.
+    pub fn with_protocol_ports(mut self, protocol: Protocol, port_strategy: PortStrategy) -> Self {
+        if !self.protocols.contains(&protocol) {
+            self.protocols.push(protocol);
+        }
+        self.protocol_ports.retain(|(known, _)| *known != protocol);
+        self.protocol_ports.push((protocol, port_strategy));
+        self
+    }
+
+    /// Leaves the ports of every rule out of the scan, to probe them on the
+    /// hosts where one of its `when_open` ports turns out open, once the
+    /// other ports are done. Ports a rule unlocks can unlock more in turn.
+    pub fn with_port_rules(mut self, port_rules: Vec<PortRule>) -> Self {
+        self.port_rules = port_rules;
+        self
+    }
+
+    /// Reports the results to `sink` instead of printing them.
+    pub fn with_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Reports the results to `sink` too, on top of printing them or of the
+    /// sink set with `with_sink`.
+    pub fn with_extra_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.extra_sinks.push(sink);
+        self
+    }
+
+    /// Caps the probes in flight to every single IP of a CIDR at the batch
+    /// size paired with it, the first CIDR holding an IP applying. batch_size
+    /// still caps the probes in flight across all IPs.
+    pub fn with_host_batch_sizes(mut self, host_batch_sizes: Vec<(IpCidr, usize)>) -> Self {
+        self.host_batch_sizes = host_batch_sizes;
+        self
+    }
+
+    /// What the scans run so far did: probes sent, retries, counts per
+    /// state and the probing rate achieved.
+    pub fn stats(&self) -> ScanStats {
+        self.stats.snapshot()
+    }
+
+    /// Every socket `run` would probe, reported open without probing it.
+    /// For running scripts on ports already known to be open.
+    pub fn assume_open(&self) -> Vec<ScanResult> {
+        self.protocols
+            .iter()
+            .flat_map(|protocol| {
+                let ports = self.ports(*protocol);
+                SocketIterator::new(&self.ips, &ports)
+                    .map(move |socket| ScanResult {
+                        protocol: *protocol,
+                        ..ScanResult::new(socket, PortState::Open, Duration::ZERO)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The ports to scan over `protocol`, in scan order and without the
+    /// excluded ones.
+    fn ports(&self, protocol: Protocol) -> Vec<u16> {
+        let exclude_ports: HashSet<u16> = self.exclude_ports.iter().copied().collect();
+        let port_strategy = self
+            .protocol_ports
+            .iter()
+            .find(|(known, _)| *known == protocol)
+            .map_or(&self.port_strategy, |(_, port_strategy)| port_strategy);
+        port_strategy
+            .order()
+            .iter()
+            .filter(|&port| !exclude_ports.contains(port))
+            .copied()
+            .collect()
+    }
+
+    /// Runs scan_range with chunk sizes
+    /// If you want to run RustScan normally, this is the entry point used
+    /// Returns a `ScanResult` for every socket that was not found closed.
+    /// For TCP this also yields the filtered ports whose connection timed
+    /// out, for UDP the open|filtered ones that never answered. Use
+    /// `ScanResult::open_sockets` to only keep the open ones.
+    /// Added by wasuaje - 01/26/2024:
+    ///    Filtering port against exclude port list
+    pub async fn run(&self) -> Vec<ScanResult> {
+        self.start_deadline();
+        if !self.knock.is_empty() {
+            self.knock_all().await;
+        }
+        let ips = if self.ping_first {
+            self.ping_sweep().await
+        } else {
+            self.ips.clone()
+        };
+        let start = self.clock.now();
+        let dependent_ports: HashSet<u16> = self
+            .port_rules
+            .iter()
+            .flat_map(|rule| rule.ports.iter().copied())
+            .collect();
+        let ports: Vec<(Protocol, Vec<u16>)> = self
+            .protocols
+            .iter()
+            .map(|protocol| {
+                let mut ports = self.ports(*protocol);
+                ports.retain(|port| !dependent_ports.contains(port));
+                (*protocol, ports)
+            })
+            .collect();
+        if let Some(bar) = &self.progress {
+            let sockets: usize = ports.iter().map(|(_, ports)| ips.len() * ports.len()).sum();
+            bar.set_length(sockets as u64);
+            bar.set_message("0 open");
+        }
+
+        let mut run = RunState {
+            checkpoint: Checkpoint::default(),
+            last_checkpoint: self.clock.now(),
+            output_file: self.open_output_file(),
+            open_count: 0,
+        };
+        let mut results = Vec::new();
+        for (protocol, ports) in &ports {
+            // Checkpoints don't tell the protocols apart, which is why they
+            // can't be combined with --protocols, so every protocol starts
+            // over from the one resumed.
+            run.checkpoint = self.checkpoint.clone();
+            let found = self.scan_protocol(&ips, ports, *protocol, &mut run).await;
+            let unlocked = self
+                .scan_port_rules(&ips, ports, *protocol, &found, &mut run)
+                .await;
+            results.extend(found);
+            results.extend(unlocked);
+        }
+        self.write_checkpoint(&run.checkpoint);
+        if let Some(bar) = &self.progress {
+            bar.finish_and_clear();
+        }
+        self.stats.add_duration(self.clock.now() - start);
+        debug!("Open Sockets found: {:?}", &results);
+        let summary = Summary {
+            results: &results,
+            partial: self.is_interrupted() || self.is_timed_out() || self.is_max_findings_reached(),
+            timed_out: self.is_timed_out(),
+            max_findings_reached: self.is_max_findings_reached(),
+            stats: self.stats(),
+        };
+        for sink in self.sinks() {
+            sink.on_complete(&summary);
+        }
+        results
+    }
+
+    /// Scans the ports the port rules unlock with what `found` has open, wave
+    /// after wave until no more get unlocked. The IPs unlocking the same
+    /// ports are scanned together.
+    async fn scan_port_rules(
+        &self,
+        ips: &[IpAddr],
+        scanned_ports: &[u16],
+        protocol: Protocol,
+        found: &[ScanResult],
+        run: &mut RunState,
+    ) -> Vec<ScanResult> {
+        if self.port_rules.is_empty() {
+            return Vec::new();
+        }
+        let exclude_ports: HashSet<u16> = self.exclude_ports.iter().copied().collect();
+        let mut open: HashMap<IpAddr, HashSet<u16>> = HashMap::new();
+        let mut scanned: HashMap<IpAddr, HashSet<u16>> = ips
+            .iter()
+            .map(|ip| (*ip, scanned_ports.iter().copied().collect()))
+            .collect();
+        let mut results = Vec::new();
+        let mut wave: Vec<&ScanResult> = found.iter().collect();
+
+        while !wave.is_empty() {
+            for result in wave.iter().filter(|result| result.state == PortState::Open) {
+                open.entry(result.ip).or_default().insert(result.port);
+            }
+            // The IPs of every set of unlocked ports, in scan order.
+            let mut groups: Vec<(Vec<u16>, Vec<IpAddr>)> = Vec::new();
+            for ip in ips {
+                let Some(open) = open.get(ip) else {
+                    continue;
+                };
+                let scanned = scanned.entry(*ip).or_default();
+                let mut unlocked = Vec::new();
+                for rule in &self.port_rules {
+                    if rule.when_open.iter().any(|port| open.contains(port)) {
+                        for port in &rule.ports {
+                            if !exclude_ports.contains(port) && scanned.insert(*port) {
+                                unlocked.push(*port);
+                            }
+                        }
+                    }
+                }
+                if unlocked.is_empty() {
+                    continue;
+                }
+                match groups.iter_mut().find(|(ports, _)| *ports == unlocked) {
+                    Some((_, group)) => group.push(*ip),
+                    None => groups.push((unlocked, vec![*ip])),
+                }
+            }
+
+            let start = results.len();
+            for (ports, group) in groups {
+                debug!("Port rules unlocked {:?} on {:?}", ports, group);
+                if let Some(bar) = &self.progress {
+                    bar.inc_length((group.len() * ports.len()) as u64);
+                }
+                results.extend(self.scan_protocol(&group, &ports, protocol, run).await);
+            }
+            wave = results[start..].iter().collect();
+        }
+        results
+    }
+
+    /// Scans every socket of the IPs and ports over a single protocol,
+    /// recording the progress in `run`.
+    async fn scan_protocol(
+        &self,
+        ips: &[IpAddr],
+        ports: &[u16],
+        protocol: Protocol,
+        run: &mut RunState,
+    ) -> Vec<ScanResult> {
+        let mut sockets = match self.max_hosts {
+            Some(max_hosts) => Sockets::Windowed(HostWindow::new(ips, ports, max_hosts)),
+            None => Sockets::Interleaved(SocketIterator::new(ips, ports)),
+        };
+        let mut open_sockets: Vec<ScanResult> = Vec::new();
+        let mut ftrs = FuturesUnordered::new();
+        let mut errors: HashSet<String> = HashSet::new();
+        let mut rate_limiter = self.max_rate.map(RateLimiter::new);
+        let mut adaptive_batch = self
+            .adaptive
+            .then(|| AdaptiveBatch::new(self.batch_size.into()));
+
+        let mut host_limits = HostLimits::new(&self.host_batch_sizes, ips);
+
+        // What the resumed run found open is reported again, the rest of
+        // its sockets are skipped by next_pending_socket.
+        for socket in self.checkpoint.open_sockets() {
+            if ips.contains(&socket.ip()) && ports.contains(&socket.port()) {
+                let result = ScanResult {
+                    protocol,
+                    ..ScanResult::new(socket, PortState::Open, Duration::ZERO)
+                };
+                self.fmt_ports(&result);
+                self.record_finding(socket.ip());
+                open_sockets.push(result);
+            }
+        }
+
+        for _ in 0..self.batch_size {
+            if let Some(socket) =
+                self.next_admitted_socket(&mut sockets, &run.checkpoint, &mut host_limits)
+            {
+                if let Some(limiter) = rate_limiter.as_mut() {
+                    limiter.acquire().await;
+                }
+                ftrs.push(self.scan_tracked_socket(socket, protocol));
+            } else {
+                break;
+            }
+        }
+
+        debug!("Start scanning sockets. \nBatch size {}\nNumber of ip-s {}\nNumber of ports {}\nTargets all together {} ",
+            self.batch_size,
+            ips.len(),
+            &ports.len(),
+            (ips.len() * ports.len()));
+
+        while let Some((socket, result)) = ftrs.next().await {
+            sockets.finished(socket.ip());
+            host_limits.finished(socket.ip());
+            // Counted before refilling the batch, which max_findings may stop.
+            let open = matches!(&result, Ok(result) if result.state == PortState::Open);
+            if open {
+                self.record_finding(socket.ip());
+            }
+            let batch_size = match adaptive_batch.as_mut() {
+                Some(adaptive_batch) => {
+                    adaptive_batch.record(is_scan_error(&result));
+                    adaptive_batch.size()
+                }
+                None => self.batch_size.into(),
+            };
+            while ftrs.len() < batch_size {
+                let Some(socket) =
+                    self.next_admitted_socket(&mut sockets, &run.checkpoint, &mut host_limits)
+                else {
+                    break;
+                };
+                if let Some(limiter) = rate_limiter.as_mut() {
+                    limiter.acquire().await;
+                }
+                ftrs.push(self.scan_tracked_socket(socket, protocol));
+            }
+
+            run.checkpoint.record(socket, open);
+            if self.checkpoint_file.is_some()
+                && self.clock.now() - run.last_checkpoint >= CHECKPOINT_INTERVAL
+            {
+                self.write_checkpoint(&run.checkpoint);
+                run.last_checkpoint = self.clock.now();
+            }
+            if let Some(bar) = &self.progress {
+                if open {
+                    run.open_count += 1;
+                    bar.set_message(format!("{} open", run.open_count));
+                }
+                bar.inc(1);
+            }
+
+            let write_error = match (run.output_file.as_mut(), &result, open) {
+                (Some(writer), Ok(result), true) => writeln!(writer, "{}", result.socket()).err(),
+                _ => None,
+            };
+            if let Some(e) = write_error {
+                // Every later write would most likely fail the same way
+                self.output_file_failed(&e);
+                run.output_file = None;
+            }
+
+            match result {
+                Ok(result) => {
+                    match result.state {
+                        PortState::Open => self.stats.open(),
+                        PortState::Closed => self.stats.closed(),
+                        PortState::Filtered | PortState::OpenFiltered => self.stats.filtered(),
+                    }
+                    open_sockets.push(result);
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => self.stats.closed(),
+                Err(e) => {
+                    self.stats.error();
+                    let error_string = e.to_string();
+                    if errors.len() < self.ips.len() * 1000 {
+                        errors.insert(error_string);
+                    }
+                }
+            }
+        }
+        debug!("Typical socket connection errors {:?}", errors);
+        open_sockets
+    }
+
+    /// Probes every host on PING_PORTS, batch_size connections at a time,
+    /// and returns the ones that answered in their original order.
+    pub async fn ping_sweep(&self) -> Vec<IpAddr> {
+        let mut socket_iterator = SocketIterator::new(&self.ips, &PING_PORTS);
+        let mut ftrs = FuturesUnordered::new();
+        let mut alive: HashSet<IpAddr> = HashSet::new();
+
+        for _ in 0..self.batch_size {
+            if let Some(socket) = self.next_socket(&mut socket_iterator) {
+                ftrs.push(self.ping(socket));
+            } else {
+                break;
+            }
+        }
+
+        while let Some((ip, answered)) = ftrs.next().await {
+            if let Some(socket) = self.next_socket(&mut socket_iterator) {
+                ftrs.push(self.ping(socket));
+            }
+            if answered {
+                alive.insert(ip);
+            }
         }
+
+        let alive: Vec<IpAddr> = self
+            .ips
+            .iter()
+            .filter(|ip| alive.contains(ip))
+            .copied()
+            .collect();
+        if !self.greppable && !self.quiet {
+            tagln!(
+                Detail,
+                "{} of {} hosts answered the ping sweep",
+                alive.len(),
+                self.ips.len()
+            );
+        }
+        alive
+    }
+
+    /// Connects to the first `connects` TCP sockets the scan would probe,
+    /// batch_size at a time, and forgets how it went, so caches are warm
+    /// once the scan is timed. Nothing gets counted in the stats, and it
+    /// stops starting connects once max_time has passed since it began.
+    pub async fn warmup(&self, connects: usize) {
+        if connects == 0 || !self.protocols.contains(&Protocol::Tcp) {
+            return;
+        }
+        self.start_deadline();
+        let ports = self.ports(Protocol::Tcp);
+        let mut sockets = SocketIterator::new(&self.ips, &ports).take(connects);
+        let timeout = self.protocol_timeout(Protocol::Tcp);
+        let mut ftrs: FuturesUnordered<_> = sockets
+            .by_ref()
+            .take(self.batch_size.into())
+            .map(|socket| self.connect(socket, timeout))
+            .collect();
+
+        while let Some(result) = ftrs.next().await {
+            if let Ok(stream) = result {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            if self.is_interrupted() || self.deadline_passed() {
+                continue;
+            }
+            if let Some(socket) = sockets.next() {
+                ftrs.push(self.connect(socket, timeout));
+            }
+        }
+        debug!("Warmed up with {} connects", connects);
+    }
+
+    /// Knocks on every host, batch_size hosts at a time.
+    async fn knock_all(&self) {
+        debug!("Knocking on {:?} before scanning", self.knock);
+        for ips in self.ips.chunks(self.batch_size.into()) {
+            let mut knocks: FuturesUnordered<_> = ips.iter().map(|ip| self.knock(*ip)).collect();
+            while knocks.next().await.is_some() {}
+        }
+    }
+
+    /// Knocks on the sequence of ports of a single host.
+    async fn knock(&self, ip: IpAddr) {
+        for port in &self.knock {
+            if self.is_interrupted() {
+                return;
+            }
+            let started = self.clock.now();
+            if let Ok(stream) = self.connect(SocketAddr::new(ip, *port), KNOCK_DELAY).await {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            let elapsed = self.clock.now().saturating_duration_since(started);
+            self.clock.sleep(KNOCK_DELAY.saturating_sub(elapsed)).await;
+        }
+    }
+
+    /// Whether anything answered on the socket, be it an accepted or a
+    /// refused connection.
+    async fn ping(&self, socket: SocketAddr) -> (IpAddr, bool) {
+        let answered = match self
+            .connect(socket, self.protocol_timeout(Protocol::Tcp))
+            .await
+        {
+            Ok(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+                true
+            }
+            Err(e) => e.kind() == io::ErrorKind::ConnectionRefused,
+        };
+        (socket.ip(), answered)
+    }
+
+    /// Connects to the open socket again for a TLS handshake, see
+    /// `with_tls_probe`.
+    async fn tls_probe(
+        &self,
+        socket: SocketAddr,
+        config: &Arc<ClientConfig>,
+    ) -> Option<tls::TlsInfo> {
+        let stream = self
+            .connect(socket, self.protocol_timeout(Protocol::Tcp))
+            .await
+            .ok()?;
+        tls::handshake(stream, socket.ip(), Arc::clone(config), TLS_TIMEOUT).await
+    }
+
+    /// Opens the file open sockets are appended to, if any. Failing to do
+    /// so is reported but doesn't stop the scan.
+    fn open_output_file(&self) -> Option<LineWriter<File>> {
+        let path = self.output_file.as_ref()?;
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(LineWriter::new(file)),
+            Err(e) => {
+                etagln!(
+                    Warning,
+                    "failed to open output file {}: {e}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Reports that writing to the output file failed, after which nothing
+    /// more is written to it.
+    fn output_file_failed(&self, e: &io::Error) {
+        if let Some(path) = &self.output_file {
+            etagln!(
+                Warning,
+                "failed to write to output file {}, no more open ports are written to it: {e}",
+                path.display()
+            );
+        }
+    }
+
+    /// Same as `scan_socket`, along with the socket probed, which errors
+    /// don't carry.
+    async fn scan_tracked_socket(
+        &self,
+        socket: SocketAddr,
+        protocol: Protocol,
+    ) -> (SocketAddr, io::Result<ScanResult>) {
+        (socket, self.scan_socket(socket, protocol).await)
+    }
+
+    /// Given a socket, scan it self.tries times.
+    /// Turns the address into a SocketAddr
+    /// Deals with the `<result>` type
+    /// If it experiences error ErrorKind::Other then too many files are open and it Panics!
+    /// Else any other error, it returns the error in Result as a string
+    /// If no errors occur, it returns the port number in Result to signify the port is open.
+    /// This function mainly deals with the logic of Results handling.
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// scanner.scan_socket(socket)
+    /// ```
+    ///
+    /// Note: `self` must contain `self.ip`.
+    async fn scan_socket(&self, socket: SocketAddr, protocol: Protocol) -> io::Result<ScanResult> {
+        if protocol == Protocol::Udp {
+            return self.scan_udp_socket(socket).await;
+        }
+
+        let tries = self.tries.get();
+
+        for nr_try in 1..=tries {
+            self.clock.sleep(self.try_delay(nr_try)).await;
+            let start = self.clock.now();
+            self.stats.attempt(nr_try);
+            match self
+                .without_local_errors(|| {
+                    self.connect(socket, self.try_timeout(nr_try, Protocol::Tcp))
+                })
+                .await
+            {
+                Ok(mut x) => {
+                    let rtt = self.clock.now() - start;
+                    let banner = match self.banner_bytes {
+                        Some(banner_bytes) => Some(read_banner(&mut x, banner_bytes).await),
+                        None => None,
+                    };
+                    debug!(
+                        "Connection was successful, shutting down stream {}",
+                        &socket
+                    );
+                    if let Err(e) = x.shutdown(Shutdown::Both) {
+                        debug!("Shutdown stream error {}", &e);
+                    }
+                    let tls = match &self.tls_config {
+                        Some(config) => self.tls_probe(socket, config).await,
+                        None => None,
+                    };
+                    let result = ScanResult {
+                        banner,
+                        tls,
+                        ..ScanResult::new(socket, PortState::Open, rtt)
+                    };
+                    self.fmt_ports(&result);
+
+                    debug!("Return Ok after {} tries", nr_try);
+                    return Ok(result);
+                }
+                Err(e) => {
+                    let mut error_string = e.to_string();
+
+                    assert!(!error_string.to_lowercase().contains("too many open files"), "Too many open files. Please reduce batch size. The default is 5000. Try -b 2500.");
+
+                    if nr_try == tries {
+                        if tcp_error_state(&e) == PortState::Filtered {
+                            debug!("Connection to {} timed out, port is filtered", &socket);
+                            return Ok(ScanResult::new(
+                                socket,
+                                PortState::Filtered,
+                                self.clock.now() - start,
+                            ));
+                        }
+                        error_string.push(' ');
+                        error_string.push_str(&socket.ip().to_string());
+                        return Err(io::Error::new(e.kind(), error_string));
+                    }
+                }
+            };
+        }
+        unreachable!();
+    }
+
+    /// Runs a try of a probe, running it again after local_error_backoff for
+    /// as long as it fails on a local resource, up to
+    /// MAX_LOCAL_ERROR_RETRIES times. Those errors say nothing about the
+    /// port, so retrying them right away or counting them as a try would
+    /// only burn through `tries`.
+    async fn without_local_errors<T, F, P>(&self, mut probe: P) -> io::Result<T>
+    where
+        P: FnMut() -> F,
+        F: Future<Output = io::Result<T>>,
+    {
+        let mut local_retries = 0;
+        loop {
+            match probe().await {
+                Err(e) if is_local_error(&e) && local_retries < MAX_LOCAL_ERROR_RETRIES => {
+                    debug!("Local resource error {}, backing off", e);
+                    self.stats.local_error();
+                    local_retries += 1;
+                    self.clock.sleep(self.local_error_backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Given a socket, probe it over UDP self.tries times.
+    /// A datagram coming back means the port is open, an ICMP port
+    /// unreachable (surfaced as `ConnectionRefused` on a connected socket)
+    /// means it is closed and is returned as an error, like a closed TCP port.
+    /// If every try times out the port is reported as open|filtered.
+    async fn scan_udp_socket(&self, socket: SocketAddr) -> io::Result<ScanResult> {
+        let tries = self.tries.get();
+        let mut rtt = Duration::ZERO;
+
+        for nr_try in 1..=tries {
+            self.clock.sleep(self.try_delay(nr_try)).await;
+            let start = self.clock.now();
+            self.stats.attempt(nr_try);
+            match self
+                .without_local_errors(|| {
+                    self.udp_probe(socket, self.try_timeout(nr_try, Protocol::Udp))
+                })
+                .await
+            {
+                Ok(()) => {
+                    let result = ScanResult {
+                        protocol: Protocol::Udp,
+                        ..ScanResult::new(socket, PortState::Open, self.clock.now() - start)
+                    };
+                    self.fmt_ports(&result);
+
+                    debug!("UDP reply received after {} tries", nr_try);
+                    return Ok(result);
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    debug!("UDP probe to {} timed out, try {}", &socket, nr_try);
+                    rtt = self.clock.now() - start;
+                }
+                Err(e) => {
+                    let mut error_string = e.to_string();
+
+                    assert!(!error_string.to_lowercase().contains("too many open files"), "Too many open files. Please reduce batch size. The default is 5000. Try -b 2500.");
+
+                    error_string.push(' ');
+                    error_string.push_str(&socket.ip().to_string());
+                    return Err(io::Error::new(e.kind(), error_string));
+                }
+            }
+        }
+
+        Ok(ScanResult {
+            protocol: Protocol::Udp,
+            ..ScanResult::new(socket, PortState::OpenFiltered, rtt)
+        })
+    }
+
+    /// Sends an empty datagram to the socket and waits timeout for any reply.
+    async fn udp_probe(&self, socket: SocketAddr, timeout: Duration) -> io::Result<()> {
+        let local_addr = self.local_addr(socket);
+        let udp_socket = if self.device.is_some() || self.fwmark.is_some() {
+            source_address::udp_socket(local_addr, self.device.as_deref(), self.fwmark)?
+        } else {
+            UdpSocket::bind(local_addr).await?
+        };
+        udp_socket.connect(socket).await?;
+
+        io::timeout(timeout, async move {
+            udp_socket.send(&[]).await?;
+            let mut buf = [0u8; 1024];
+            udp_socket.recv(&mut buf).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The local address a probe to `target` is sent from: the source
+    /// address or the interface's address of the family of `target`, if
+    /// any, the unspecified address of that family otherwise, and the next
+    /// port of the local port range, if any, or 0 for the OS to pick one.
+    fn local_addr(&self, target: SocketAddr) -> SocketAddr {
+        let interface_ip = || self.interface.as_ref()?.address_for(target);
+        let ip = match (self.source_address.or_else(interface_ip), target) {
+            (Some(source), _) => source,
+            (None, SocketAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (None, SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let port = self.local_ports.as_ref().map_or(0, LocalPorts::next);
+        SocketAddr::new(ip, port)
+    }
+
+    /// Reports an open socket as soon as it is found.
+    fn fmt_ports(&self, result: &ScanResult) {
+        for sink in self.sinks() {
+            sink.on_open_port(result);
+        }
+    }
+
+    /// The sink set with `with_sink`, or the CLI output, followed by the
+    /// extra sinks.
+    fn sinks(&self) -> Vec<Arc<dyn OutputSink>> {
+        let sink: Arc<dyn OutputSink> = match &self.sink {
+            Some(sink) => Arc::clone(sink),
+            None => Arc::new(CliSink {
+                greppable: self.greppable,
+                accessible: self.accessible,
+                jsonl: self.jsonl,
+                quiet: self.quiet,
+                progress: self.progress.clone(),
+            }),
+        };
+        std::iter::once(sink)
+            .chain(self.extra_sinks.iter().cloned())
+            .collect()
+    }
+
+    /// Performs the connection to the socket with timeout
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+    /// let port: u16 = 80;
+    /// // ip is an IpAddr type
+    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
+    /// let socket = SocketAddr::new(ip, port);
+    /// scanner.connect(socket, Duration::from_millis(1500));
+    /// // returns Result which is either Ok(stream) for port is open, or Er for port is closed.
+    /// // Timeout occurs after the given duration
+    /// ```
+    ///
+    async fn connect(&self, socket: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        let proxy = self.proxy.as_ref();
+        // Without a source address, an interface, local ports or a mark
+        // there is nothing to set up, and the plain connect is left to pick.
+        let local = (self.source_address.is_some()
+            || self.interface.is_some()
+            || self.local_ports.is_some()
+            || self.fwmark.is_some())
+        .then(|| self.local_addr(socket));
+        let (device, fwmark) = (self.device.as_deref(), self.fwmark);
+        let stream = io::timeout(timeout, async move {
+            match (proxy, local) {
+                (Some(Proxy::Socks5(proxy)), _) => socks5::connect(*proxy, socket).await,
+                (Some(Proxy::Http { address, auth }), _) => {
+                    http_proxy::connect(*address, socket, auth.as_deref()).await
+                }
+                (None, Some(local)) => source_address::connect(local, socket, device, fwmark).await,
+                (None, None) => TcpStream::connect(socket).await,
+            }
+        })
+        .await?;
+        Ok(stream)
+    }
+}
+
+/// A proxy every TCP connection of a scan goes through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proxy {
+    Socks5(SocketAddr),
+    /// An HTTP proxy tunnelling connections with CONNECT, along with the
+    /// `user:pass` it takes, if any.
+    Http {
+        address: SocketAddr,
+        auth: Option<String>,
+    },
+}
+
+impl Proxy {
+    /// Parses a `socks5://host:port` or `http://host:port` proxy URL,
+    /// resolving the host. Only HTTP proxies take `auth`.
+    pub fn parse(url: &str, auth: Option<String>) -> Result<Self, String> {
+        if url.starts_with("http://") {
+            let address = http_proxy::parse_proxy_url(url)?;
+            return Ok(Proxy::Http { address, auth });
+        }
+        if auth.is_some() {
+            return Err(
+                "--proxy-auth needs an http:// proxy, SOCKS5 authentication isn't supported."
+                    .to_owned(),
+            );
+        }
+        socks5::parse_proxy_url(url)
+            .map(Proxy::Socks5)
+            .map_err(|_| {
+                format!("Proxy {url:?} is neither a socks5:// nor an http:// host:port URL.")
+            })
+    }
+}
+
+/// What a run carries across its protocols and port rule waves.
+struct RunState {
+    /// The sockets scanned so far, starting from the checkpoint resumed.
+    checkpoint: Checkpoint,
+    /// When the checkpoint file was last written.
+    last_checkpoint: Instant,
+    /// Where open sockets are appended, see `open_output_file`.
+    output_file: Option<LineWriter<File>>,
+    /// The open ports found, for the progress bar.
+    open_count: usize,
+}
+
+/// Where the sockets of a scan come from: every IP at once, or a window of
+/// at most max_hosts IPs.
+enum Sockets<'s> {
+    Interleaved(SocketIterator<'s>),
+    Windowed(HostWindow<'s>),
+}
+
+impl Sockets<'_> {
+    /// Records that a probe to `ip` is done.
+    fn finished(&mut self, ip: IpAddr) {
+        if let Sockets::Windowed(window) = self {
+            window.finished(ip);
+        }
+    }
+}
+
+impl Iterator for Sockets<'_> {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Sockets::Interleaved(sockets) => sockets.next(),
+            Sockets::Windowed(window) => window.next(),
+        }
+    }
+}
+
+/// Whether a probe ran into trouble: a timeout, or an error other than the
+/// refused connection of a closed port.
+fn is_scan_error(result: &io::Result<ScanResult>) -> bool {
+    match result {
+        Ok(result) => result.state == PortState::Filtered,
+        Err(e) => e.kind() != io::ErrorKind::ConnectionRefused,
+    }
+}
+
+/// Whether an error comes from this host running out of something, free
+/// ephemeral ports or buffer space, rather than from the target.
+fn is_local_error(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if matches!(e.raw_os_error(), Some(libc::ENOBUFS | libc::ENOMEM)) {
+        return true;
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::AddrNotAvailable | io::ErrorKind::AddrInUse | io::ErrorKind::OutOfMemory
+    )
+}
+
+/// The state of a TCP port whose connection attempt failed with the given
+/// error. Anything but a timeout means the host answered, most often with a
+/// RST, so the port is closed.
+fn tcp_error_state(e: &io::Error) -> PortState {
+    match e.kind() {
+        io::ErrorKind::TimedOut => PortState::Filtered,
+        _ => PortState::Closed,
+    }
+}
+
+/// Reads whatever the service sends first, up to `banner_bytes`, giving up
+/// with an empty banner after BANNER_TIMEOUT.
+async fn read_banner(stream: &mut TcpStream, banner_bytes: usize) -> String {
+    let mut buf = vec![0u8; banner_bytes];
+    match io::timeout(BANNER_TIMEOUT, stream.read(&mut buf)).await {
+        Ok(read) => String::from_utf8_lossy(&buf[..read]).trim_end().to_owned(),
+        Err(e) => {
+            debug!("No banner read {}", &e);
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{PortRange, PortRule, Protocol, ScanOrder};
+    use async_std::task::block_on;
+    use clock::ManualClock;
+    use std::{net::IpAddr, time::Duration};
+
+    fn states(results: &[ScanResult]) -> Vec<(SocketAddr, PortState)> {
+        results
+            .iter()
+            .map(|result| (result.socket(), result.state))
+            .collect()
+    }
+
+    #[test]
+    fn scanner_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn ipv6_scanner_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["::1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn quad_zero_scanner_runs() {
+        let addrs = vec!["0.0.0.0".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn google_dns_runs() {
+        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 400,
+            end: 445,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn infer_ulimit_lowering_no_panic() {
+        // Test behaviour on MacOS where ulimit is not automatically lowered
+        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+
+        // mac should have this automatically scaled down
+        let range = PortRange {
+            start: 400,
+            end: 600,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn udp_scanner_finds_responder() {
+        // Mock a UDP service on localhost that answers every datagram
+        let responder = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = responder.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while let Ok((_, peer)) = responder.recv_from(&mut buf) {
+                let _ = responder.send_to(b"pong", peer);
+            }
+        });
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            true,
+        );
+        let result = states(&block_on(scanner.run()));
+
+        assert_eq!(
+            result,
+            vec![(SocketAddr::new(addrs[0], port), PortState::Open)]
+        );
+    }
+
+    #[test]
+    fn udp_scanner_silent_port_is_open_filtered() {
+        // The socket stays bound so no ICMP unreachable is sent, but never answers
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = silent.local_addr().unwrap().port();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            2,
+            true,
+            strategy,
+            true,
+            vec![],
+            true,
+        );
+        let result = states(&block_on(scanner.run()));
+
+        assert_eq!(
+            result,
+            vec![(SocketAddr::new(addrs[0], port), PortState::OpenFiltered)]
+        );
+        assert!(ScanResult::open_sockets(&block_on(scanner.run())).is_empty());
+        drop(silent);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn udp_scanner_unreachable_port_is_closed() {
+        // Grab a free port and release it, localhost answers with ICMP port unreachable
+        let port = {
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.local_addr().unwrap().port()
+        };
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            true,
+        );
+        let result = states(&block_on(scanner.run()));
+
+        assert!(result.is_empty());
+    }
+    #[test]
+    fn max_rate_throttles_probes() {
+        // Closed ports on localhost are refused right away, so the rate is
+        // the only thing slowing the scan down.
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let range = PortRange { start: 1, end: 50 };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            50,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_max_rate(Some(100));
+
+        let start = std::time::Instant::now();
+        block_on(scanner.run());
+
+        // 50 probes at 100 per second, minus the first free token.
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[test]
+    fn banner_grabbed_from_open_port() {
+        // Mock a service on localhost that greets every connection
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = std::io::Write::write_all(&mut stream, b"SSH-2.0-OpenSSH_9.6\r\n");
+            }
+        });
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_banner(Some(7));
+        let result = block_on(scanner.run());
+
+        assert_eq!(
+            states(&result),
+            vec![(SocketAddr::new(addrs[0], port), PortState::Open)]
+        );
+        assert_eq!(result[0].banner, Some("SSH-2.0".to_owned()));
+    }
+
+    #[test]
+    fn silent_port_has_empty_banner() {
+        // Accepts connections but never writes, like an HTTP server would
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(5_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_banner(Some(256));
+
+        let start = std::time::Instant::now();
+        let result = block_on(scanner.run());
+
+        assert!(start.elapsed() < Duration::from_millis(5_000));
+        assert_eq!(
+            states(&result),
+            vec![(SocketAddr::new(addrs[0], port), PortState::Open)]
+        );
+        assert_eq!(result[0].banner, Some(String::new()));
+    }
+
+    #[test]
+    fn reverse_order_respects_exclude_ports() {
+        let listeners: Vec<_> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Reverse, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![ports[0]],
+            false,
+        );
+        let result = ScanResult::open_sockets(&block_on(scanner.run()));
+
+        assert_eq!(result, vec![SocketAddr::new(addrs[0], ports[1])]);
+    }
+
+    #[test]
+    fn progress_does_not_change_results() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            false,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_progress(true);
+        let result = ScanResult::open_sockets(&block_on(scanner.run()));
+
+        assert_eq!(result, vec![SocketAddr::new(addrs[0], port)]);
+        assert_eq!(scanner.progress.as_ref().unwrap().position(), 1);
+    }
+
+    #[test]
+    fn retry_backoff_schedule() {
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let new_scanner = |timeout: u64| {
+            Scanner::new(
+                &addrs,
+                10,
+                Duration::from_millis(timeout),
+                5,
+                true,
+                PortStrategy::pick(&None, Some(vec![80]), ScanOrder::Serial, None),
+                true,
+                vec![],
+                false,
+            )
+        };
+
+        let scanner = new_scanner(1_000).with_retry_backoff(Some(2.0));
+        let schedule: Vec<Duration> = (1..=5)
+            .map(|nr_try| scanner.try_timeout(nr_try, Protocol::Tcp))
+            .collect();
+        assert_eq!(
+            schedule,
+            [1_000, 2_000, 4_000, 8_000, 10_000].map(Duration::from_millis)
+        );
+
+        // Without a backoff, or with one that would shrink it, the timeout stays put.
+        for scanner in [
+            new_scanner(1_000),
+            new_scanner(1_000).with_retry_backoff(Some(0.5)),
+        ] {
+            assert_eq!(
+                scanner.try_timeout(5, Protocol::Tcp),
+                Duration::from_millis(1_000)
+            );
+        }
+
+        // A base timeout above the cap is never lowered.
+        let scanner = new_scanner(20_000).with_retry_backoff(Some(2.0));
+        assert_eq!(
+            scanner.try_timeout(3, Protocol::Tcp),
+            Duration::from_millis(20_000)
+        );
+
+        // A protocol's own timeout is what the backoff starts from.
+        let scanner = new_scanner(1_000)
+            .with_retry_backoff(Some(2.0))
+            .with_tcp_timeout(Some(Duration::from_millis(500)))
+            .with_udp_timeout(Some(Duration::from_millis(3_000)));
+        assert_eq!(
+            scanner.try_timeout(2, Protocol::Tcp),
+            Duration::from_millis(1_000)
+        );
+        assert_eq!(
+            scanner.try_timeout(2, Protocol::Udp),
+            Duration::from_millis(6_000)
+        );
+        let scanner = new_scanner(1_000).with_udp_timeout(Some(Duration::from_millis(3_000)));
+        assert_eq!(
+            scanner.try_timeout(1, Protocol::Tcp),
+            Duration::from_millis(1_000)
+        );
+    }
+
+    #[test]
+    fn timeout_bounds_clamp_every_try() {
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            4,
+            true,
+            PortStrategy::pick(&None, Some(vec![80]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_retry_backoff(Some(3.0))
+        .with_udp_timeout(Some(Duration::from_millis(5_000)))
+        .with_timeout_bounds(
+            Some(Duration::from_millis(250)),
+            Some(Duration::from_millis(2_000)),
+        );
+
+        let schedule: Vec<Duration> = (1..=4)
+            .map(|nr_try| scanner.try_timeout(nr_try, Protocol::Tcp))
+            .collect();
+        assert_eq!(
+            schedule,
+            [250, 750, 2_000, 2_000].map(Duration::from_millis)
+        );
+        assert_eq!(
+            scanner.protocol_timeout(Protocol::Udp),
+            Duration::from_millis(2_000)
+        );
+    }
+
+    #[test]
+    fn retry_jitter_delays_retries_only() {
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            3,
+            true,
+            PortStrategy::pick(&None, Some(vec![80]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_retry_jitter(Some(50));
+
+        assert_eq!(scanner.try_delay(1), Duration::ZERO);
+        let delays: Vec<Duration> = (0..100).map(|_| scanner.try_delay(2)).collect();
+        assert!(delays
+            .iter()
+            .all(|&delay| delay <= Duration::from_millis(50)));
+        assert!(delays.iter().any(|&delay| delay != delays[0]));
+
+        let scanner = scanner.with_retry_jitter(Some(0));
+        assert_eq!(scanner.try_delay(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn retries_wait_on_the_scanner_clock() {
+        // A closed port fails every try, so both retries wait their jitter.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let clock = Arc::new(ManualClock::default());
+        let scanner = Scanner::new(
+            &["127.0.0.1".parse().unwrap()],
+            10,
+            Duration::from_millis(1_000),
+            3,
+            true,
+            PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_clock(clock.clone())
+        .with_retry_jitter(Some(60_000));
+
+        let start = std::time::Instant::now();
+        block_on(scanner.run());
+
+        assert_eq!(scanner.stats().attempts, 3);
+        assert!(clock.elapsed() <= Duration::from_secs(120));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn max_time_follows_the_scanner_clock() {
+        let clock = Arc::new(ManualClock::default());
+        let scanner = Scanner::new(
+            &["127.0.0.1".parse().unwrap()],
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            PortStrategy::pick(&None, Some(vec![1]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_clock(clock.clone())
+        .with_max_time(Some(Duration::from_secs(300)));
+
+        let socket = SocketAddr::new("127.0.0.1".parse().unwrap(), 1);
+        clock.advance(Duration::from_secs(600));
+        scanner.start_deadline();
+        clock.advance(Duration::from_secs(299));
+        assert_eq!(scanner.before_deadline(socket), Some(socket));
+        assert!(!scanner.is_timed_out());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(scanner.before_deadline(socket), None);
+        assert!(scanner.is_timed_out());
+    }
+
+    #[test]
+    fn tcp_errors_classified() {
+        let timed_out = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+
+        assert_eq!(tcp_error_state(&timed_out), PortState::Filtered);
+        assert_eq!(tcp_error_state(&refused), PortState::Closed);
+    }
+
+    #[test]
+    fn refused_tcp_port_is_closed() {
+        // Bind then drop a listener so the port is known to refuse connections
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+
+        assert!(states(&block_on(scanner.run())).is_empty());
+    }
+
+    #[test]
+    fn scan_through_socks5_proxy() {
+        use std::io::{Read, Write};
+
+        // Mock a SOCKS5 proxy that only lets connections to port 8080 through
+        let proxy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut client in proxy.incoming().flatten() {
+                let mut greeting = [0u8; 3];
+                let mut request = [0u8; 10];
+                if client.read_exact(&mut greeting).is_err()
+                    || client.write_all(&[5, 0]).is_err()
+                    || client.read_exact(&mut request).is_err()
+                {
+                    continue;
+                }
+                let port = u16::from_be_bytes([request[8], request[9]]);
+                let code = if port == 8080 { 0 } else { 5 };
+                let _ = client.write_all(&[5, code, 0, 1, 127, 0, 0, 1, 0, 0]);
+            }
+        });
+
+        let addrs = vec!["10.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![22, 8080]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_proxy(Some(Proxy::Socks5(proxy_addr)));
+
+        assert_eq!(
+            states(&block_on(scanner.run())),
+            vec![(SocketAddr::new(addrs[0], 8080), PortState::Open)]
+        );
+    }
+
+    #[test]
+    fn scan_through_http_proxy() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // Mock an HTTP proxy that only tunnels to port 8080, for the right
+        // credentials.
+        let proxy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut client in proxy.incoming().flatten() {
+                let mut headers = Vec::new();
+                let mut reader = BufReader::new(client.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok() && line != "\r\n" && !line.is_empty() {
+                    headers.push(std::mem::take(&mut line));
+                }
+                let reply = if !headers.contains(&"Proxy-Authorization: Basic dTpw\r\n".to_owned())
+                {
+                    "HTTP/1.1 407 Proxy Authentication Required\r\n\r\n"
+                } else if headers[0].starts_with("CONNECT 10.0.0.1:8080 ") {
+                    "HTTP/1.1 200 Connection established\r\n\r\n"
+                } else {
+                    "HTTP/1.1 503 Service Unavailable\r\n\r\n"
+                };
+                let _ = client.write_all(reply.as_bytes());
+            }
+        });
+
+        let addrs = vec!["10.0.0.1".parse::<IpAddr>().unwrap()];
+        let scan = |auth: &str| {
+            let strategy = PortStrategy::pick(&None, Some(vec![22, 8080]), ScanOrder::Serial, None);
+            let scanner = Scanner::new(
+                &addrs,
+                10,
+                Duration::from_millis(500),
+                1,
+                true,
+                strategy,
+                true,
+                vec![],
+                false,
+            )
+            .with_proxy(Some(
+                Proxy::parse(&format!("http://{proxy_addr}"), Some(auth.to_owned())).unwrap(),
+            ));
+            states(&block_on(scanner.run()))
+        };
+
+        assert_eq!(
+            scan("u:p"),
+            vec![(SocketAddr::new(addrs[0], 8080), PortState::Open)]
+        );
+        assert!(scan("u:wrong").is_empty());
+    }
+
+    #[test]
+    fn open_sockets_appended_to_output_file() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let output_file = std::env::temp_dir().join(format!("rustscan-output-{port}.txt"));
+        std::fs::write(&output_file, "127.0.0.2:22\n").unwrap();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_output_file(Some(output_file.clone()));
+        block_on(scanner.run());
+
+        let written = std::fs::read_to_string(&output_file).unwrap();
+        std::fs::remove_file(&output_file).unwrap();
+        assert_eq!(written, format!("127.0.0.2:22\n127.0.0.1:{port}\n"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn output_file_dropped_once_writes_fail() {
+        let listeners: Vec<_> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = Scanner::new(
+            &addrs,
+            1,
+            Duration::from_millis(500),
+            1,
+            true,
+            PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_output_file(Some(PathBuf::from("/dev/full")));
+        let mut run = RunState {
+            checkpoint: Checkpoint::default(),
+            last_checkpoint: Instant::now(),
+            output_file: scanner.open_output_file(),
+            open_count: 0,
+        };
+        assert!(run.output_file.is_some());
+        let results = block_on(scanner.scan_protocol(&addrs, &ports, Protocol::Tcp, &mut run));
+
+        assert!(run.output_file.is_none());
+        assert_eq!(ScanResult::open_sockets(&results).len(), 2);
+    }
+
+    #[test]
+    fn ping_sweep_skips_dead_hosts() {
+        // Localhost refuses the ping ports, which still counts as an answer,
+        // while nothing answers for the documentation only 2001:db8::/32.
+        let addrs = vec![
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+            "127.0.0.1".parse::<IpAddr>().unwrap(),
+        ];
+        let strategy = PortStrategy::pick(&None, Some(vec![9]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(300),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_ping_first(true);
+
+        assert_eq!(block_on(scanner.ping_sweep()), vec![addrs[1]]);
+    }
+
+    #[test]
+    fn interrupted_scan_stops_early() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let interrupted = Arc::new(AtomicBool::new(true));
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_interrupt(interrupted);
+
+        assert!(block_on(scanner.run()).is_empty());
+        assert!(scanner.is_interrupted());
+    }
+
+    #[test]
+    fn adaptive_batch_scan_runs() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: port - 100,
+            end: port,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            20,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_adaptive_batch(true);
+
+        assert!(ScanResult::open_sockets(&block_on(scanner.run()))
+            .contains(&SocketAddr::new(addrs[0], port)));
+    }
+
+    #[test]
+    fn open_sockets_keeps_open_results_in_order() {
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let results = vec![
+            ScanResult::new(SocketAddr::new(ip, 80), PortState::Open, Duration::ZERO),
+            ScanResult::new(SocketAddr::new(ip, 81), PortState::Filtered, Duration::ZERO),
+            ScanResult::new(SocketAddr::new(ip, 22), PortState::Open, Duration::ZERO),
+        ];
+
+        assert_eq!(
+            ScanResult::open_sockets(&results),
+            vec![SocketAddr::new(ip, 80), SocketAddr::new(ip, 22)]
+        );
+    }
+
+    #[test]
+    fn open_port_rtt_within_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+        let result = block_on(scanner.run());
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].ip, addrs[0]);
+        assert_eq!(result[0].port, port);
+        assert!(result[0].rtt < Duration::from_millis(1_000));
+        assert_eq!(result[0].banner, None);
+    }
+
+    #[test]
+    fn tls_probe_skips_plaintext_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_tls_probe(true);
+        let result = block_on(scanner.run());
+
+        assert_eq!(
+            states(&result),
+            vec![(SocketAddr::new(addrs[0], port), PortState::Open)]
+        );
+        assert_eq!(result[0].tls, None);
+    }
+
+    #[test]
+    fn stats_count_outcomes() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let closed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(
+            &None,
+            Some(vec![port, closed_port]),
+            ScanOrder::Serial,
+            None,
+        );
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            2,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+        block_on(scanner.run());
+        let stats = scanner.stats();
+
+        // The closed port is tried twice, the open one only once
+        assert_eq!(stats.attempts, 3);
+        assert_eq!(stats.retries, 1);
+        assert_eq!(stats.open, 1);
+        assert_eq!(stats.closed, 1);
+        assert_eq!(stats.filtered, 0);
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[test]
+    fn single_port_across_hosts() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let addrs: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Random, None);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+        let open = ScanResult::open_sockets(&block_on(scanner.run()));
+
+        // One probe per host, the listener only answers on 127.0.0.1
+        assert_eq!(open, vec![SocketAddr::new(addrs[0], port)]);
+        assert_eq!(scanner.stats().attempts, 2);
+    }
+
+    #[test]
+    fn tries_are_exact() {
+        let closed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+
+        // 0 is a single try like 1, without any retry.
+        for (tries, attempts) in [(0, 1), (1, 1), (3, 3)] {
+            let strategy =
+                PortStrategy::pick(&None, Some(vec![closed_port]), ScanOrder::Serial, None);
+            let scanner = Scanner::new(
+                &addrs,
+                10,
+                Duration::from_millis(1_000),
+                tries,
+                true,
+                strategy,
+                true,
+                vec![],
+                false,
+            );
+            block_on(scanner.run());
+            let stats = scanner.stats();
+
+            assert_eq!(stats.attempts, attempts, "--tries {}", tries);
+            assert_eq!(stats.retries, attempts - 1, "--tries {}", tries);
+            assert_eq!(stats.closed, 1);
+        }
+    }
+
+    #[test]
+    fn resume_skips_scanned_sockets() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let resumed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        // The earlier run found resumed_port open, it must not be probed again
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let mut resume_from = Checkpoint::default();
+        resume_from.record(SocketAddr::new(ip, resumed_port), true);
+        let path = std::env::temp_dir().join(format!("rustscan-resume-{port}"));
+
+        let strategy = PortStrategy::pick(
+            &None,
+            Some(vec![port, resumed_port]),
+            ScanOrder::Serial,
+            None,
+        );
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_checkpoint(Some(path.clone()), resume_from);
+        let results = block_on(scanner.run());
+
+        assert_eq!(scanner.stats().attempts, 1);
+        let mut open = ScanResult::open_sockets(&results);
+        open.sort();
+        let mut expected = vec![SocketAddr::new(ip, port), SocketAddr::new(ip, resumed_port)];
+        expected.sort();
+        assert_eq!(open, expected);
+
+        let written = Checkpoint::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.is_scanned(SocketAddr::new(ip, port)));
+        assert!(written.is_scanned(SocketAddr::new(ip, resumed_port)));
+        assert_eq!(written.open_sockets().len(), 2);
+    }
+
+    #[test]
+    fn resume_port_rule_scan() {
+        let listeners: Vec<std::net::TcpListener> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let path = std::env::temp_dir().join(format!("rustscan-resume-rules-{}", ports[0]));
+        let scanner = |resume_from| {
+            Scanner::new(
+                &[ip],
+                10,
+                Duration::from_millis(1_000),
+                1,
+                true,
+                PortStrategy::pick(&None, Some(vec![ports[0]]), ScanOrder::Serial, None),
+                true,
+                vec![],
+                false,
+            )
+            .with_port_rules(vec![PortRule {
+                when_open: vec![ports[0]],
+                ports: vec![ports[1]],
+            }])
+            .with_checkpoint(Some(path.clone()), resume_from)
+        };
+
+        let first = scanner(Checkpoint::default());
+        block_on(first.run());
+        assert_eq!(first.stats().attempts, 2);
+
+        // Both waves are in the checkpoint, the resumed run probes nothing
+        let written = Checkpoint::read(&path).unwrap();
+        assert_eq!(written.open_sockets().len(), 2);
+        let resumed = scanner(written);
+        let results = block_on(resumed.run());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resumed.stats().attempts, 0);
+        assert_eq!(
+            ScanResult::open_sockets(&results),
+            vec![SocketAddr::new(ip, ports[0]), SocketAddr::new(ip, ports[1])]
+        );
+    }
+
+    #[test]
+    fn local_errors_are_retried_apart() {
+        let clock = Arc::new(ManualClock::default());
+        let scanner = Scanner::new(
+            &[],
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            PortStrategy::Manual(vec![]),
+            true,
+            vec![],
+            false,
+        )
+        .with_clock(clock.clone())
+        .with_local_error_backoff(Some(1_000));
+
+        let calls = std::cell::Cell::new(0);
+        let result = block_on(scanner.without_local_errors(|| {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            async move {
+                if call < 3 {
+                    Err(io::Error::from(io::ErrorKind::AddrNotAvailable))
+                } else {
+                    Ok(call)
+                }
+            }
+        }));
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(scanner.stats().local_errors, 2);
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+
+        // It gives up eventually, and errors about the target aren't retried
+        let result = block_on(scanner.without_local_errors(|| async {
+            Err::<(), _>(io::Error::from(io::ErrorKind::AddrInUse))
+        }));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        let result = block_on(scanner.without_local_errors(|| async {
+            Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+        }));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            scanner.stats().local_errors,
+            2 + u64::from(MAX_LOCAL_ERROR_RETRIES)
+        );
+    }
+
+    #[test]
+    fn host_batch_size_scans_every_socket() {
+        let listeners: Vec<_> = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let mut ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        ports.sort_unstable();
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let strategy = PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_host_batch_sizes(vec![("127.0.0.0/8".parse().unwrap(), 1)]);
+        let mut open = ScanResult::open_sockets(&block_on(scanner.run()));
+        open.sort();
+
+        let expected: Vec<SocketAddr> = ports
+            .iter()
+            .map(|port| SocketAddr::new(ip, *port))
+            .collect();
+        assert_eq!(open, expected);
+    }
+
+    #[test]
+    fn max_time_stops_new_probes() {
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let strategy = PortStrategy::pick(
+            &Some(PortRange {
+                start: 1,
+                end: 1_000,
+            }),
+            None,
+            ScanOrder::Serial,
+            None,
+        );
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_max_time(Some(Duration::ZERO));
+        block_on(scanner.run());
+
+        assert!(scanner.is_timed_out());
+        assert_eq!(scanner.stats().attempts, 0);
+
+        let unbounded = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            PortStrategy::pick(&None, Some(vec![1]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_max_time(Some(Duration::from_secs(3_600)));
+        block_on(unbounded.run());
+        assert!(!unbounded.is_timed_out());
+        assert_eq!(unbounded.stats().attempts, 1);
     }
 
-    /// Runs scan_range with chunk sizes
-    /// If you want to run RustScan normally, this is the entry point used
-    /// Returns all open ports as `Vec<u16>`
-    /// Added by wasuaje - 01/26/2024:
-    ///    Filtering port against exclude port list
-    pub async fn run(&self) -> Vec<SocketAddr> {
-        let ports: Vec<u16> = self
-            .port_strategy
-            .order()
-            .iter()
-            .filter(|&port| !self.exclude_ports.contains(port))
-            .copied()
-            .collect();
-        let mut socket_iterator: SocketIterator = SocketIterator::new(&self.ips, &ports);
-        let mut open_sockets: Vec<SocketAddr> = Vec::new();
-        let mut ftrs = FuturesUnordered::new();
-        let mut errors: HashSet<String> = HashSet::new();
+    #[test]
+    fn warmup_is_not_counted() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
 
-        for _ in 0..self.batch_size {
-            if let Some(socket) = socket_iterator.next() {
-                ftrs.push(self.scan_socket(socket));
-            } else {
-                break;
-            }
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        );
+        block_on(scanner.warmup(1));
+
+        assert!(listener.accept().is_ok());
+        assert_eq!(scanner.stats().attempts, 0);
+    }
+
+    #[test]
+    fn warmup_follows_max_time() {
+        let listeners: Vec<_> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        for listener in &listeners {
+            listener.set_nonblocking(true).unwrap();
         }
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let clock = Arc::new(ManualClock::default());
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = |max_time| {
+            Scanner::new(
+                &addrs,
+                1,
+                Duration::from_millis(1_000),
+                1,
+                true,
+                PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial, None),
+                true,
+                vec![],
+                false,
+            )
+            .with_clock(clock.clone())
+            .with_max_time(Some(max_time))
+        };
 
-        debug!("Start scanning sockets. \nBatch size {}\nNumber of ip-s {}\nNumber of ports {}\nTargets all together {} ",
-            self.batch_size,
-            self.ips.len(),
-            &ports.len(),
-            (self.ips.len() * ports.len()));
+        // Past the deadline, only the first batch gets connected
+        block_on(scanner(Duration::ZERO).warmup(2));
+        assert!(listeners[0].accept().is_ok());
+        assert!(listeners[1].accept().is_err());
 
-        while let Some(result) = ftrs.next().await {
-            if let Some(socket) = socket_iterator.next() {
-                ftrs.push(self.scan_socket(socket));
-            }
+        // The time the warmup took isn't taken from the scan
+        let timed = scanner(Duration::from_secs(300));
+        block_on(timed.warmup(2));
+        clock.advance(Duration::from_secs(600));
+        let results = block_on(timed.run());
 
-            match result {
-                Ok(socket) => open_sockets.push(socket),
-                Err(e) => {
-                    let error_string = e.to_string();
-                    if errors.len() < self.ips.len() * 1000 {
-                        errors.insert(error_string);
-                    }
-                }
-            }
-        }
-        debug!("Typical socket connection errors {:?}", errors);
-        debug!("Open Sockets found: {:?}", &open_sockets);
-        open_sockets
+        assert!(!timed.is_timed_out());
+        assert_eq!(timed.stats().attempts, 2);
+        assert_eq!(ScanResult::open_sockets(&results).len(), 2);
     }
 
-    /// Given a socket, scan it self.tries times.
-    /// Turns the address into a SocketAddr
-    /// Deals with the `<result>` type
-    /// If it experiences error ErrorKind::Other then too many files are open and it Panics!
-    /// Else any other error, it returns the error in Result as a string
-    /// If no errors occur, it returns the port number in Result to signify the port is open.
-    /// This function mainly deals with the logic of Results handling.
-    /// # Example
-    ///
-    /// ```compile_fail
-    /// scanner.scan_socket(socket)
-    /// ```
-    ///
-    /// Note: `self` must contain `self.ip`.
-    async fn scan_socket(&self, socket: SocketAddr) -> io::Result<SocketAddr> {
-        let tries = self.tries.get();
-
-        for nr_try in 1..=tries {
-            match self.connect(socket).await {
-                Ok(x) => {
-                    debug!(
-                        "Connection was successful, shutting down stream {}",
-                        &socket
-                    );
-                    if let Err(e) = x.shutdown(Shutdown::Both) {
-                        debug!("Shutdown stream error {}", &e);
-                    }
-                    if !self.greppable {
-                        if self.accessible {
-                            println!("Open {socket}");
-                        } else {
-                            println!("Open {}", socket.to_string().purple());
-                        }
-                    }
+    #[test]
+    fn max_findings_stop_the_scan() {
+        let listeners: Vec<_> = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let scanner = |ips: &[IpAddr], total, per_host| {
+            Scanner::new(
+                ips,
+                1,
+                Duration::from_millis(1_000),
+                1,
+                true,
+                PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial, None),
+                true,
+                vec![],
+                false,
+            )
+            .with_max_findings(total, per_host)
+        };
+        let local = ["127.0.0.1".parse::<IpAddr>().unwrap()];
 
-                    debug!("Return Ok after {} tries", nr_try);
-                    return Ok(socket);
-                }
-                Err(e) => {
-                    let mut error_string = e.to_string();
+        let capped = scanner(&local, Some(2), None);
+        let results = block_on(capped.run());
+        assert_eq!(ScanResult::open_sockets(&results).len(), 2);
+        assert_eq!(capped.stats().attempts, 2);
+        assert!(capped.is_max_findings_reached());
 
-                    assert!(!error_string.to_lowercase().contains("too many open files"), "Too many open files. Please reduce batch size. The default is 5000. Try -b 2500.");
+        // The other host still gets every port scanned
+        let both = ["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let per_host = scanner(&both, None, Some(1));
+        let results = block_on(per_host.run());
+        assert_eq!(ScanResult::open_sockets(&results).len(), 1);
+        assert_eq!(per_host.stats().attempts, 4);
+        assert!(per_host.is_max_findings_reached());
 
-                    if nr_try == tries {
-                        error_string.push(' ');
-                        error_string.push_str(&socket.ip().to_string());
-                        return Err(io::Error::new(io::ErrorKind::Other, error_string));
-                    }
-                }
-            };
-        }
-        unreachable!();
+        let unbounded = scanner(&local, Some(3), None);
+        block_on(unbounded.run());
+        assert!(!unbounded.is_max_findings_reached());
     }
 
-    /// Performs the connection to the socket with timeout
-    /// # Example
-    ///
-    /// ```compile_fail
-    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
-    /// let port: u16 = 80;
-    /// // ip is an IpAddr type
-    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
-    /// let socket = SocketAddr::new(ip, port);
-    /// scanner.connect(socket);
-    /// // returns Result which is either Ok(stream) for port is open, or Er for port is closed.
-    /// // Timeout occurs after self.timeout seconds
-    /// ```
-    ///
-    async fn connect(&self, socket: SocketAddr) -> io::Result<TcpStream> {
-        let stream = io::timeout(
-            self.timeout,
-            async move { TcpStream::connect(socket).await },
+    #[test]
+    fn knock_before_scanning() {
+        let knocked: Vec<std::net::TcpListener> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let sequence: Vec<u16> = knocked
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(1_000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
         )
-        .await?;
-        Ok(stream)
-    }
-}
+        .with_knock(sequence);
+        let results = block_on(scanner.run());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::input::{PortRange, ScanOrder};
-    use async_std::task::block_on;
-    use std::{net::IpAddr, time::Duration};
+        // Knocks aren't probes, only the scanned port counts
+        assert_eq!(scanner.stats().attempts, 1);
+        assert_eq!(
+            ScanResult::open_sockets(&results),
+            vec![SocketAddr::new(ip, port)]
+        );
+        for listener in &knocked {
+            listener.set_nonblocking(true).unwrap();
+            assert!(listener.accept().is_ok());
+        }
+    }
 
     #[test]
-    fn scanner_runs() {
-        // Makes sure the program still runs and doesn't panic
-        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
-        let range = PortRange {
-            start: 1,
-            end: 1_000,
+    fn probes_from_local_port_range() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let local_port = {
+            let free = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            free.local_addr().unwrap().port()
         };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
         let scanner = Scanner::new(
-            &addrs,
+            &[ip],
             10,
-            Duration::from_millis(100),
+            Duration::from_millis(1_000),
             1,
             true,
             strategy,
             true,
-            vec![9000],
+            vec![],
+            false,
+        )
+        .with_local_port_range(Some(&PortRange {
+            start: local_port,
+            end: local_port,
+        }));
+        let results = block_on(scanner.run());
+
+        assert_eq!(
+            ScanResult::open_sockets(&results),
+            vec![SocketAddr::new(ip, port)]
         );
-        block_on(scanner.run());
-        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
-        assert_eq!(1, 1);
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer.port(), local_port);
     }
+
     #[test]
-    fn ipv6_scanner_runs() {
-        // Makes sure the program still runs and doesn't panic
-        let addrs = vec!["::1".parse::<IpAddr>().unwrap()];
-        let range = PortRange {
-            start: 1,
-            end: 1_000,
-        };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+    #[cfg(target_os = "linux")]
+    fn probes_through_interface() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let lo = source_address::interface("lo").unwrap();
+        let bind_device = source_address::check_device("lo").is_ok();
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None);
         let scanner = Scanner::new(
-            &addrs,
+            &[ip],
             10,
-            Duration::from_millis(100),
+            Duration::from_millis(1_000),
             1,
             true,
             strategy,
             true,
-            vec![9000],
+            vec![],
+            false,
+        )
+        .with_interface(Some(lo), bind_device);
+        let results = block_on(scanner.run());
+
+        assert_eq!(
+            ScanResult::open_sockets(&results),
+            vec![SocketAddr::new(ip, port)]
         );
-        block_on(scanner.run());
-        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
-        assert_eq!(1, 1);
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer.ip(), ip);
     }
+
     #[test]
-    fn quad_zero_scanner_runs() {
-        let addrs = vec!["0.0.0.0".parse::<IpAddr>().unwrap()];
-        let range = PortRange {
-            start: 1,
-            end: 1_000,
-        };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+    fn assume_open_lists_every_socket() {
+        let ips: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![22, 80, 443]), ScanOrder::Serial, None);
         let scanner = Scanner::new(
-            &addrs,
+            &ips,
             10,
             Duration::from_millis(100),
             1,
             true,
             strategy,
             true,
-            vec![9000],
+            vec![80],
+            false,
         );
+
+        let mut open = ScanResult::open_sockets(&scanner.assume_open());
+        open.sort();
+        let expected: Vec<SocketAddr> = ips
+            .iter()
+            .flat_map(|ip| vec![SocketAddr::new(*ip, 22), SocketAddr::new(*ip, 443)])
+            .collect();
+        assert_eq!(open, expected);
+        assert_eq!(scanner.stats().attempts, 0);
+    }
+
+    #[test]
+    fn sink_gets_the_results() {
+        #[derive(Default)]
+        struct Collect {
+            open: std::sync::Mutex<Vec<SocketAddr>>,
+            completed: std::sync::Mutex<Vec<(usize, bool)>>,
+        }
+
+        impl OutputSink for Collect {
+            fn on_open_port(&self, result: &ScanResult) {
+                self.open.lock().unwrap().push(result.socket());
+            }
+
+            fn on_complete(&self, summary: &Summary<'_>) {
+                self.completed
+                    .lock()
+                    .unwrap()
+                    .push((summary.results.len(), summary.partial));
+            }
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let sink = Arc::new(Collect::default());
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(1_000),
+            1,
+            false,
+            PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_sink(Arc::clone(&sink) as Arc<dyn OutputSink>);
         block_on(scanner.run());
-        assert_eq!(1, 1);
+
+        assert_eq!(*sink.open.lock().unwrap(), vec![SocketAddr::new(ip, port)]);
+        assert_eq!(*sink.completed.lock().unwrap(), vec![(1, false)]);
     }
+
     #[test]
-    fn google_dns_runs() {
-        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
-        let range = PortRange {
-            start: 400,
-            end: 445,
-        };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+    fn scans_tcp_and_udp() {
+        // A TCP listener and a UDP responder on the same port number
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let responder = std::net::UdpSocket::bind(("127.0.0.1", port)).unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while let Ok((_, peer)) = responder.recv_from(&mut buf) {
+                let _ = responder.send_to(b"pong", peer);
+            }
+        });
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
         let scanner = Scanner::new(
-            &addrs,
+            &[ip],
             10,
-            Duration::from_millis(100),
+            Duration::from_millis(500),
             1,
             true,
-            strategy,
+            PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None),
             true,
-            vec![9000],
+            vec![],
+            false,
+        )
+        .with_protocols(&[Protocol::Tcp, Protocol::Udp, Protocol::Tcp]);
+        let results = block_on(scanner.run());
+
+        let found: Vec<(Protocol, u16, PortState)> = results
+            .iter()
+            .map(|result| (result.protocol, result.port, result.state))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Protocol::Tcp, port, PortState::Open),
+                (Protocol::Udp, port, PortState::Open)
+            ]
         );
-        block_on(scanner.run());
-        assert_eq!(1, 1);
+        assert_eq!(scanner.stats().attempts, 2);
     }
+
     #[test]
-    fn infer_ulimit_lowering_no_panic() {
-        // Test behaviour on MacOS where ulimit is not automatically lowered
-        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+    fn ports_unlocked_by_port_rules() {
+        let listeners: Vec<std::net::TcpListener> = (0..4)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        // A free port, closed, so what it unlocks stays unscanned
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
 
-        // mac should have this automatically scaled down
-        let range = PortRange {
-            start: 400,
-            end: 600,
-        };
-        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let output_file = std::env::temp_dir().join(format!("rustscan-rules-{}.txt", ports[0]));
         let scanner = Scanner::new(
-            &addrs,
+            &[ip],
             10,
-            Duration::from_millis(100),
+            Duration::from_millis(500),
             1,
             true,
-            strategy,
+            PortStrategy::pick(&None, Some(vec![ports[0], closed]), ScanOrder::Serial, None),
             true,
-            vec![9000],
+            vec![],
+            false,
+        )
+        .with_output_file(Some(output_file.clone()))
+        .with_port_rules(vec![
+            PortRule {
+                when_open: vec![ports[0]],
+                ports: vec![ports[1]],
+            },
+            PortRule {
+                when_open: vec![ports[1]],
+                ports: vec![ports[2], ports[1]],
+            },
+            PortRule {
+                when_open: vec![closed],
+                ports: vec![ports[3]],
+            },
+        ]);
+
+        let found: Vec<u16> = ScanResult::open_sockets(&block_on(scanner.run()))
+            .iter()
+            .map(SocketAddr::port)
+            .collect();
+        // ports[1] unlocks itself again, but is only scanned once.
+        assert_eq!(found, ports[..3]);
+        assert_eq!(scanner.stats().attempts, 4);
+
+        // Every wave appends to the same output file
+        let written = std::fs::read_to_string(&output_file).unwrap();
+        std::fs::remove_file(&output_file).unwrap();
+        let expected: String = ports[..3]
+            .iter()
+            .map(|port| format!("127.0.0.1:{port}\n"))
+            .collect();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn ports_per_protocol() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let responder = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp_port = responder.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while let Ok((_, peer)) = responder.recv_from(&mut buf) {
+                let _ = responder.send_to(b"pong", peer);
+            }
+        });
+
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let scanner = Scanner::new(
+            &[ip],
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial, None),
+            true,
+            vec![],
+            false,
+        )
+        .with_protocol_ports(
+            Protocol::Udp,
+            PortStrategy::pick(&None, Some(vec![udp_port]), ScanOrder::Serial, None),
         );
-        block_on(scanner.run());
-        assert_eq!(1, 1);
+        let results = block_on(scanner.run());
+
+        let found: Vec<(Protocol, u16, PortState)> = results
+            .iter()
+            .map(|result| (result.protocol, result.port, result.state))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Protocol::Tcp, port, PortState::Open),
+                (Protocol::Udp, udp_port, PortState::Open)
+            ]
+        );
+        assert_eq!(scanner.assume_open().len(), 2);
     }
 }
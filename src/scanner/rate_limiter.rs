@@ -0,0 +1,70 @@
+use async_std::task;
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+
+/// A token bucket used to cap how many probes are started per second.
+///
+/// Tokens are refilled continuously at `rate` per second and every probe
+/// takes one. The bucket only holds about 10ms worth of tokens, which is
+/// enough to absorb timer imprecision at high rates while still keeping
+/// the traffic smooth instead of sending a burst every second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: NonZeroU32) -> Self {
+        let rate = f64::from(rate.get());
+        Self {
+            rate,
+            capacity: (rate / 100.0).max(1.0),
+            tokens: 1.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn acquire(&mut self) {
+        self.refill();
+        while self.tokens < 1.0 {
+            let missing = 1.0 - self.tokens;
+            task::sleep(Duration::from_secs_f64(missing / self.rate)).await;
+            self.refill();
+        }
+        self.tokens -= 1.0;
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use async_std::task::block_on;
+    use std::num::NonZeroU32;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn acquire_respects_rate() {
+        let mut limiter = RateLimiter::new(NonZeroU32::new(200).unwrap());
+        let start = Instant::now();
+        block_on(async {
+            for _ in 0..41 {
+                limiter.acquire().await;
+            }
+        });
+
+        // The first token is free, the other 40 need 200ms at 200 per second.
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
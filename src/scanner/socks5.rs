@@ -0,0 +1,119 @@
+//! A minimal SOCKS5 client, enough to run connect scans through a proxy.
+//!
+//! Only the CONNECT command without authentication is supported, which is
+//! what SSH dynamic forwarding (`ssh -D`) and most pivoting tools expose.
+use async_std::io;
+use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::prelude::*;
+use std::net::SocketAddr;
+
+const VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const CONNECT: u8 = 0x01;
+const ADDRESS_IPV4: u8 = 0x01;
+const ADDRESS_DOMAIN: u8 = 0x03;
+const ADDRESS_IPV6: u8 = 0x04;
+
+/// Parses a `socks5://host:port` proxy URL, resolving the host.
+pub fn parse_proxy_url(url: &str) -> Result<SocketAddr, String> {
+    let address = url
+        .strip_prefix("socks5://")
+        .ok_or_else(|| format!("Proxy {url:?} is not a socks5://host:port URL."))?
+        .trim_end_matches('/');
+
+    async_std::task::block_on(address.to_socket_addrs())
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("Proxy {url:?} could not be resolved."))
+}
+
+/// Opens a connection to `target` through the SOCKS5 proxy at `proxy`.
+/// A target refused by the proxy is reported as `ConnectionRefused`, like
+/// a direct connection would be.
+pub async fn connect(proxy: SocketAddr, target: SocketAddr) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    stream.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method != [VERSION, NO_AUTHENTICATION] {
+        return Err(io::Error::other("SOCKS5 proxy requires authentication"));
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0x00];
+    match target {
+        SocketAddr::V4(addr) => {
+            request.push(ADDRESS_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            request.push(ADDRESS_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(io::Error::other("invalid SOCKS5 reply"));
+    }
+    if reply[1] != 0x00 {
+        return Err(reply_error(reply[1]));
+    }
+
+    // Skip the address the proxy bound for us, it is of no use to a scan.
+    let bound_address_len = match reply[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        _ => return Err(io::Error::other("invalid SOCKS5 reply")),
+    };
+    let mut bound_address = vec![0u8; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+/// Maps a SOCKS5 reply code to the error a direct connection would give.
+fn reply_error(code: u8) -> io::Error {
+    match code {
+        0x05 => io::Error::from(io::ErrorKind::ConnectionRefused),
+        0x06 => io::Error::from(io::ErrorKind::TimedOut),
+        0x03 => io::Error::other("SOCKS5 proxy: network unreachable"),
+        0x04 => io::Error::other("SOCKS5 proxy: host unreachable"),
+        _ => io::Error::other(format!("SOCKS5 proxy: request failed with code {code}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_proxy_url, reply_error};
+    use async_std::io;
+
+    #[test]
+    fn parse_proxy_urls() {
+        assert_eq!(
+            parse_proxy_url("socks5://127.0.0.1:1080"),
+            Ok("127.0.0.1:1080".parse().unwrap())
+        );
+        assert_eq!(
+            parse_proxy_url("socks5://[::1]:9050/"),
+            Ok("[::1]:9050".parse().unwrap())
+        );
+        assert!(parse_proxy_url("http://127.0.0.1:1080").is_err());
+        assert!(parse_proxy_url("socks5://127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn reply_codes_mapped() {
+        assert_eq!(reply_error(0x05).kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(reply_error(0x06).kind(), io::ErrorKind::TimedOut);
+        assert_eq!(reply_error(0x01).kind(), io::ErrorKind::Other);
+    }
+}
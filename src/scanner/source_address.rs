@@ -0,0 +1,302 @@
+//! TCP connections made from a chosen local address, for hosts with several
+//! interfaces where routing or firewall rules depend on the source address,
+//! or from a chosen local port. On Linux they can also be bound to a network
+//! interface by name, which VPN setups often need, and carry a firewall mark
+//! for policy routing.
+use async_io::Async;
+use async_std::io;
+use async_std::net::{TcpStream, UdpSocket};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// A network interface probes are sent through, with the address of each
+/// IP family they are sent from. Link local IPv6 addresses are left out, as
+/// they can't be used without a scope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl Interface {
+    /// The address of the interface to reach `target` from, if it has one
+    /// of the same IP family.
+    pub fn address_for(&self, target: SocketAddr) -> Option<IpAddr> {
+        match target {
+            SocketAddr::V4(_) => self.ipv4.map(IpAddr::V4),
+            SocketAddr::V6(_) => self.ipv6.map(IpAddr::V6),
+        }
+    }
+}
+
+/// Looks up the interface called `name`, erroring out with NotFound when
+/// there is none.
+#[cfg(unix)]
+pub fn interface(name: &str) -> io::Result<Interface> {
+    use std::ffi::CStr;
+
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in a list that is only read until it is freed
+    // below, and every address is only read as the family it says it is.
+    let (found, ipv4, ipv6) = unsafe {
+        if libc::getifaddrs(&mut addrs) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (mut found, mut ipv4, mut ipv6) = (false, None, None);
+        let mut current = addrs;
+        while !current.is_null() {
+            let entry = &*current;
+            current = entry.ifa_next;
+            if CStr::from_ptr(entry.ifa_name).to_bytes() != name.as_bytes() {
+                continue;
+            }
+            found = true;
+            if entry.ifa_addr.is_null() {
+                continue;
+            }
+            match i32::from((*entry.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let addr = &*entry.ifa_addr.cast::<libc::sockaddr_in>();
+                    ipv4 = ipv4.or(Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))));
+                }
+                libc::AF_INET6 => {
+                    let addr = &*entry.ifa_addr.cast::<libc::sockaddr_in6>();
+                    let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+                    if ip.segments()[0] & 0xffc0 != 0xfe80 {
+                        ipv6 = ipv6.or(Some(ip));
+                    }
+                }
+                _ => {}
+            }
+        }
+        libc::freeifaddrs(addrs);
+        (found, ipv4, ipv6)
+    };
+
+    if !found {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("there is no network interface called {name}"),
+        ));
+    }
+    Ok(Interface {
+        name: name.to_owned(),
+        ipv4,
+        ipv6,
+    })
+}
+
+/// Looking up interfaces isn't supported on this platform.
+#[cfg(not(unix))]
+pub fn interface(name: &str) -> io::Result<Interface> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't look up the network interface {name} on this platform"),
+    ))
+}
+
+/// Checks that sockets can be bound to the interface called `device`, which
+/// takes CAP_NET_RAW on Linux and isn't supported elsewhere.
+pub fn check_device(device: &str) -> io::Result<()> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    bind_device(&socket, device)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+    socket.bind_device(Some(device.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &Socket, device: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't bind sockets to {device} on this platform"),
+    ))
+}
+
+/// Checks that sockets can carry the firewall mark `mark`, which takes
+/// CAP_NET_ADMIN on Linux and isn't supported elsewhere.
+pub fn check_mark(mark: u32) -> io::Result<()> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    set_mark(&socket, mark)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn set_mark(socket: &Socket, mark: u32) -> io::Result<()> {
+    socket.set_mark(mark)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn set_mark(_socket: &Socket, _mark: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "can't mark sockets on this platform",
+    ))
+}
+
+/// Checks that `source` is an address of this host, as connections can only
+/// be bound to those.
+pub fn check(source: IpAddr) -> io::Result<()> {
+    std::net::UdpSocket::bind(SocketAddr::new(source, 0)).map(drop)
+}
+
+/// Connects to `target` from `local`, on a port picked by the OS when its
+/// port is 0, through `device` and with the firewall `mark` when set. A
+/// target of the other IP family than `local` can't be reached and errors
+/// out.
+pub async fn connect(
+    local: SocketAddr,
+    target: SocketAddr,
+    device: Option<&str>,
+    mark: Option<u32>,
+) -> io::Result<TcpStream> {
+    let socket = Socket::new(
+        Domain::for_address(target),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if let Some(device) = device {
+        bind_device(&socket, device)?;
+    }
+    if let Some(mark) = mark {
+        set_mark(&socket, mark)?;
+    }
+    if local.port() != 0 {
+        // Lets a port be bound again while its last connection is in
+        // TIME_WAIT.
+        socket.set_reuse_address(true)?;
+    }
+    socket.bind(&local.into())?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&target.into()) {
+        Err(e) if !in_progress(&e) => return Err(e),
+        _ => {}
+    }
+
+    // The connection is established, or refused, once the socket turns
+    // writable.
+    let stream = Async::new(std::net::TcpStream::from(socket))?;
+    stream.writable().await?;
+    if let Some(e) = stream.get_ref().take_error()? {
+        return Err(e);
+    }
+    Ok(TcpStream::from(stream.into_inner()?))
+}
+
+/// A UDP socket bound to `local`, to `device` and with the firewall `mark`
+/// when set, see `connect`.
+pub fn udp_socket(
+    local: SocketAddr,
+    device: Option<&str>,
+    mark: Option<u32>,
+) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(local), Type::DGRAM, Some(Protocol::UDP))?;
+    if let Some(device) = device {
+        bind_device(&socket, device)?;
+    }
+    if let Some(mark) = mark {
+        set_mark(&socket, mark)?;
+    }
+    socket.bind(&local.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from(std::net::UdpSocket::from(socket)))
+}
+
+/// Whether a non blocking connect is still going on rather than failed.
+fn in_progress(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if e.raw_os_error() == Some(libc::EINPROGRESS) {
+        return true;
+    }
+    e.kind() == io::ErrorKind::WouldBlock
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, check_mark, connect, interface, Interface};
+    use async_std::task::block_on;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    #[test]
+    fn connects_from_source() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap();
+        let source: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let stream = block_on(connect(SocketAddr::new(source, 0), target, None, None)).unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), source);
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer.ip(), source);
+    }
+
+    #[test]
+    fn connects_from_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap();
+        let local = {
+            let free = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            free.local_addr().unwrap()
+        };
+
+        let stream = block_on(connect(local, target, None, None)).unwrap();
+        assert_eq!(stream.local_addr().unwrap(), local);
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer, local);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn connects_with_mark() {
+        // Marking sockets takes CAP_NET_ADMIN.
+        if check_mark(0x64).is_err() {
+            return;
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap();
+
+        block_on(connect(
+            "127.0.0.1:0".parse().unwrap(),
+            target,
+            None,
+            Some(0x64),
+        ))
+        .unwrap();
+        assert!(listener.accept().is_ok());
+    }
+
+    #[test]
+    fn refused_connection_errors() {
+        let target = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let error =
+            block_on(connect("127.0.0.1:0".parse().unwrap(), target, None, None)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn looks_up_interfaces() {
+        // The loopback interface is lo on Linux and lo0 on the BSDs.
+        let lo = interface("lo").or_else(|_| interface("lo0")).unwrap();
+        assert_eq!(lo.ipv4, Some(Ipv4Addr::LOCALHOST));
+        assert_eq!(
+            lo.address_for("127.0.0.2:80".parse().unwrap()),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+
+        let error = interface("rustscan-missing0").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        let only_ipv4 = Interface { ipv6: None, ..lo };
+        assert_eq!(only_ipv4.address_for("[::1]:80".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_foreign_address() {
+        assert!(check("127.0.0.1".parse().unwrap()).is_ok());
+        assert!(check("192.0.2.1".parse().unwrap()).is_err());
+    }
+}
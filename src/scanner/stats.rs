@@ -0,0 +1,136 @@
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// What a scan did, as counted by `Scanner::run`.
+///   - attempts counts every connection attempt or UDP probe, retries
+///     included, of which retries is the part beyond the first try.
+///   - open, closed and filtered count the sockets by their final state,
+///     open|filtered UDP ports being counted as filtered.
+///   - errors counts the sockets whose probe failed in any other way.
+///   - local_errors counts the connections that failed for lack of local
+///     resources, like free ephemeral ports, and were tried again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub attempts: u64,
+    pub retries: u64,
+    pub open: u64,
+    pub closed: u64,
+    pub filtered: u64,
+    pub errors: u64,
+    pub local_errors: u64,
+    pub duration: Duration,
+}
+
+impl ScanStats {
+    /// The connection attempts started per second over the whole scan.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn probes_per_second(&self) -> f64 {
+        let seconds = self.duration.as_secs_f64();
+        if seconds > 0.0 {
+            self.attempts as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The counters behind ScanStats, shared by the probes of a scan.
+#[derive(Debug, Default)]
+pub struct ScanCounters {
+    attempts: AtomicU64,
+    retries: AtomicU64,
+    open: AtomicU64,
+    closed: AtomicU64,
+    filtered: AtomicU64,
+    errors: AtomicU64,
+    local_errors: AtomicU64,
+    duration_us: AtomicU64,
+}
+
+impl ScanCounters {
+    /// Counts one try of a probe, `nr_try` counting from 1.
+    pub fn attempt(&self, nr_try: u8) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        if nr_try > 1 {
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn open(&self) {
+        self.open.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn closed(&self) {
+        self.closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn local_error(&self) {
+        self.local_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the time a run took to the scan duration.
+    pub fn add_duration(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.duration_us.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ScanStats {
+        ScanStats {
+            attempts: self.attempts.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            open: self.open.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            local_errors: self.local_errors.load(Ordering::Relaxed),
+            duration: Duration::from_micros(self.duration_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScanCounters, ScanStats};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_retries_apart() {
+        let counters = ScanCounters::default();
+        counters.attempt(1);
+        counters.attempt(2);
+        counters.attempt(1);
+        counters.open();
+        counters.filtered();
+        counters.local_error();
+        counters.add_duration(Duration::from_secs(2));
+
+        assert_eq!(
+            counters.snapshot(),
+            ScanStats {
+                attempts: 3,
+                retries: 1,
+                open: 1,
+                closed: 0,
+                filtered: 1,
+                errors: 0,
+                local_errors: 1,
+                duration: Duration::from_secs(2),
+            }
+        );
+        assert!((counters.snapshot().probes_per_second() - 1.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn no_rate_without_duration() {
+        assert!(ScanStats::default().probes_per_second().abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,189 @@
+//! TLS detection for open TCP ports.
+//!
+//! The handshake is only used to find out whether a service speaks TLS and
+//! what it negotiates, so the server certificate is never verified.
+use async_std::io;
+use async_std::net::TcpStream;
+use futures_rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use futures_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use futures_rustls::rustls::crypto::{ring, CryptoProvider};
+use futures_rustls::rustls::{
+    ClientConfig, DigitallySignedStruct, Error, ProtocolVersion, SignatureScheme,
+};
+use futures_rustls::TlsConnector;
+use serde_derive::Serialize;
+use std::{fmt, net::IpAddr, sync::Arc, time::Duration};
+
+/// ALPN protocols offered to the server, most preferred first.
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// What a TLS handshake with an open port negotiated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TlsInfo {
+    pub version: String,
+    /// The ALPN protocol the server picked, if it picked any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.alpn {
+            Some(alpn) => write!(f, "{} {alpn}", self.version),
+            None => write!(f, "{}", self.version),
+        }
+    }
+}
+
+/// The client config every probe shares, offering TLS 1.2 and 1.3 along
+/// with ALPN_PROTOCOLS.
+pub fn client_config() -> Arc<ClientConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let mut config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .expect("the ring provider supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+        .with_no_client_auth();
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    Arc::new(config)
+}
+
+/// Runs a TLS handshake over the stream, giving up after `timeout`. Returns
+/// None when the service doesn't speak TLS or didn't answer in time.
+pub async fn handshake(
+    stream: TcpStream,
+    ip: IpAddr,
+    config: Arc<ClientConfig>,
+    timeout: Duration,
+) -> Option<TlsInfo> {
+    let connector = TlsConnector::from(config);
+    let server_name = ServerName::IpAddress(ip.into());
+    let stream = io::timeout(timeout, connector.connect(server_name, stream))
+        .await
+        .ok()?;
+
+    let (_, connection) = stream.get_ref();
+    Some(TlsInfo {
+        version: version_name(connection.protocol_version()?),
+        alpn: connection
+            .alpn_protocol()
+            .map(|alpn| String::from_utf8_lossy(alpn).into_owned()),
+    })
+}
+
+fn version_name(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".to_owned(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".to_owned(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Accepts whatever certificate the server presents, self signed and
+/// expired ones included, as they are still TLS services.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_config, handshake, TlsInfo};
+    use async_std::net::TcpStream;
+    use async_std::task::block_on;
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn plaintext_service_is_not_tls() {
+        // Answers the ClientHello like an HTTP server would
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        });
+
+        let info = block_on(async {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            handshake(stream, addr.ip(), client_config(), Duration::from_secs(5)).await
+        });
+
+        assert_eq!(info, None);
+    }
+
+    #[test]
+    fn silent_service_times_out() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let start = Instant::now();
+        let info = block_on(async {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            handshake(
+                stream,
+                addr.ip(),
+                client_config(),
+                Duration::from_millis(200),
+            )
+            .await
+        });
+
+        assert_eq!(info, None);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn tls_info_display() {
+        let info = TlsInfo {
+            version: "TLSv1.3".to_owned(),
+            alpn: Some("h2".to_owned()),
+        };
+        assert_eq!(info.to_string(), "TLSv1.3 h2");
+
+        let info = TlsInfo {
+            version: "TLSv1.2".to_owned(),
+            alpn: None,
+        };
+        assert_eq!(info.to_string(), "TLSv1.2");
+    }
+}
@@ -35,6 +35,11 @@
 //! `home_dir/.rustscan_scripts` and will try to read all the files, and parse
 //! them into a vector of [`ScriptFile`].
 //!
+//! `--scripts-dir` replaces that folder, and can be given more than once to
+//! layer script collections, e.g. a team's and a personal one. Scripts are
+//! merged by file name, a script in a later folder replacing the one of the
+//! same name in an earlier folder.
+//!
 //! Filtering on tags means the tags found in the `rustscan_scripts.toml` file
 //! will also have to be present in the [`ScriptFile`], otherwise the script
 //! will not be selected.
@@ -42,6 +47,10 @@
 //! All of the `rustscan_script.toml` tags have to be present at minimum in a
 //! [`ScriptFile`] to get selected, but can be also more.
 //!
+//! `--script-tags` picks the scripts from the command line instead, selecting
+//! the ones with at least one of the tags given, e.g. `--script-tags
+//! http,recon` runs the scripts tagged either `http` or `recon`.
+//!
 //! Config file example:
 //!
 //! - `fixtures/test_rustscan_scripts.toml`
@@ -53,40 +62,60 @@
 //! - `fixtures/test_script.sh`
 //! - `fixtures/test_script.txt`
 //!
-//! `call_format` in script files can be of 2 variants:
+//! `call_format` in script files is a shell command line with placeholders:
+//!
+//! - `{{script}}` is replaced with the scriptfile full path gathered while
+//!   parsing available scripts. Only script files can use it, not the
+//!   embedded default script.
+//! - `{{ip}}` is replaced with the ip we got from the scan.
+//! - `{{port}}` and `{{ports}}` are replaced with the open ports separated
+//!   with the `ports_separator` found in the script file, or the script's
+//!   `port` when it sets one.
+//! - `{{port_count}}` is replaced with the number of open ports.
 //!
-//! One is where all of the possible tags `{{script}}`, `{{ip}}` and `{{port}}`
-//! are there.
+//! A script file can set `ports_format` to pass the ports as more than one
+//! argument instead, which takes precedence over `ports_separator`:
 //!
-//! - The `{{script}}` part will be replaced with the scriptfile full path
-//!   gathered while parsing available scripts.
-//! - The `{{ip}}` part will be replaced with the ip we got from the scan.
-//! - The `{{port}}` part will be reaplced with the ports separated with the
-//!   `ports_separator` found in the script file
+//! - `ports_format = "comma"` joins them as `80,443`, the default.
+//! - `ports_format = "space"` makes every port an argument of its own, as in
+//!   `80 443`.
+//! - `ports_format = { repeated = "-p {{port}}" }` repeats the template for
+//!   every port, as in `-p 80 -p 443`.
 //!
-//! And when there is only `{{ip}}` and `{{port}}` is in the format, only those
-//! will be replaced with the arguments from the scan.
+//! Every value is quoted for the shell when it contains anything but letters,
+//! digits and `_-.,:/@%+=`, so a script path with spaces stays one argument.
+//! The same goes for the ports, even when the separator is a space.
 //!
 //! This makes it easy to run a system installed command like `nmap`, and give
-//! any kind of arguments to it.
+//! any kind of arguments to it, e.g. `nmap -vvv -p {{ports}} {{ip}}`.
 //!
-//! If the format is different, the script will be silently discarded and will
-//! not run. With the `Debug` option it's possible to see where it goes wrong.
+//! If the format uses any other placeholder, the script is reported as failed
+//! and will not run. With the `Debug` option it's possible to see where it
+//! goes wrong.
+//!
+//! A script file can also list `trigger_ports`, e.g. `trigger_ports = [80, 443]`.
+//! Such a script only runs on IPs where at least one of those ports is open,
+//! scripts without it run on every IP with open ports.
 
 #![allow(clippy::module_name_repetitions)]
 
 use crate::input::ScriptsRequired;
-use anyhow::{anyhow, Result};
 use log::debug;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::convert::TryInto;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, prelude::*};
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
-use subprocess::{Exec, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use subprocess::{Exec, ExitStatus, Popen, PopenConfig, PopenError, Redirection};
 use text_placeholder::Template;
 
 static DEFAULT: &str = r#"tags = ["core_approved", "RustScan", "default"]
@@ -95,8 +124,66 @@ ports_separator = ","
 call_format = "nmap -vvv -p {{port}} {{ip}}"
 "#;
 
+/// Why finding, parsing or running scripts failed.
+#[derive(Debug)]
+pub enum ScriptError {
+    /// A script header, the script config or a call format is invalid.
+    ParseError(String),
+    /// Something scripts are looked up in doesn't exist, e.g. the scripts
+    /// folder.
+    NotFound(String),
+    /// The script exited with a non zero status, or was killed by a signal.
+    ExecFailed { status: i32 },
+    /// The script was killed after running for this long.
+    Timeout(Duration),
+    /// The script couldn't be started or its output couldn't be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParseError(reason) => f.write_str(reason),
+            Self::NotFound(what) => write!(f, "Can't find {what}"),
+            Self::ExecFailed { status } => write!(f, "Exit code = {status}"),
+            Self::Timeout(timeout) => write!(f, "Script timed out after {}s", timeout.as_secs()),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ScriptError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<PopenError> for ScriptError {
+    fn from(e: PopenError) -> Self {
+        match e {
+            PopenError::IoError(e) => Self::Io(e),
+            e => Self::Io(io::Error::other(e)),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ScriptError>;
+
 #[cfg(not(tarpaulin_include))]
-pub fn init_scripts(scripts: ScriptsRequired) -> Result<Vec<ScriptFile>> {
+pub fn init_scripts(
+    scripts: ScriptsRequired,
+    scripts_dirs: &[PathBuf],
+    script_tags: &[String],
+) -> Result<Vec<ScriptFile>> {
     let mut scripts_to_run: Vec<ScriptFile> = Vec::new();
 
     match scripts {
@@ -108,50 +195,81 @@ pub fn init_scripts(scripts: ScriptsRequired) -> Result<Vec<ScriptFile>> {
             Ok(scripts_to_run)
         }
         ScriptsRequired::Custom => {
-            let Some(scripts_dir_base) = dirs::home_dir() else {
-                return Err(anyhow!("Could not infer scripts path."));
-            };
-            let script_paths = match find_scripts(scripts_dir_base) {
-                Ok(script_paths) => script_paths,
-                Err(e) => return Err(anyhow!(e)),
+            let script_paths = if scripts_dirs.is_empty() {
+                let Some(scripts_dir_base) = dirs::home_dir() else {
+                    return Err(ScriptError::NotFound("the home directory".to_owned()));
+                };
+                find_scripts(scripts_dir_base)?
+            } else {
+                layer_scripts_dirs(scripts_dirs)?
             };
             debug!("Scripts paths \n{:?}", script_paths);
 
             let parsed_scripts = parse_scripts(script_paths);
             debug!("Scripts parsed \n{:?}", parsed_scripts);
 
-            let script_config = match ScriptConfig::read_config() {
-                Ok(script_config) => script_config,
-                Err(e) => return Err(anyhow!(e)),
-            };
+            let script_config = ScriptConfig::read_config()?;
             debug!("Script config \n{:?}", script_config);
 
-            // Only Scripts that contain all the tags found in ScriptConfig will be selected.
-            if script_config.tags.is_some() {
-                let config_hashset: HashSet<String> =
-                    script_config.tags.unwrap().into_iter().collect();
-                for script in &parsed_scripts {
-                    if script.tags.is_some() {
-                        let script_hashset: HashSet<String> =
-                            script.tags.clone().unwrap().into_iter().collect();
-                        if config_hashset.is_subset(&script_hashset) {
-                            scripts_to_run.push(script.clone());
-                        } else {
-                            debug!(
-                                "\nScript tags does not match config tags {:?} {}",
-                                &script_hashset,
-                                script.path.clone().unwrap().display()
-                            );
-                        }
-                    }
-                }
-            }
+            scripts_to_run = select_scripts(parsed_scripts, script_config.tags, script_tags);
             debug!("\nScript(s) to run {:?}", scripts_to_run);
             Ok(scripts_to_run)
         }
     }
 }
 
+/// The scripts to run out of the parsed ones. With `script_tags`, the ones
+/// having any of them, otherwise the ones having all the tags of the script
+/// config.
+pub fn select_scripts(
+    parsed_scripts: Vec<ScriptFile>,
+    config_tags: Option<Vec<String>>,
+    script_tags: &[String],
+) -> Vec<ScriptFile> {
+    if !script_tags.is_empty() {
+        return parsed_scripts
+            .into_iter()
+            .filter(|script| {
+                let selected = script
+                    .tags
+                    .iter()
+                    .flatten()
+                    .any(|tag| script_tags.contains(tag));
+                if !selected {
+                    debug!(
+                        "\nScript tags {:?} not in --script-tags {}",
+                        &script.tags,
+                        script.path.clone().unwrap_or_default().display()
+                    );
+                }
+                selected
+            })
+            .collect();
+    }
+
+    let mut scripts_to_run = Vec::new();
+    // Only Scripts that contain all the tags found in ScriptConfig will be selected.
+    if let Some(config_tags) = config_tags {
+        let config_hashset: HashSet<String> = config_tags.into_iter().collect();
+        for script in &parsed_scripts {
+            if script.tags.is_some() {
+                let script_hashset: HashSet<String> =
+                    script.tags.clone().unwrap().into_iter().collect();
+                if config_hashset.is_subset(&script_hashset) {
+                    scripts_to_run.push(script.clone());
+                } else {
+                    debug!(
+                        "\nScript tags does not match config tags {:?} {}",
+                        &script_hashset,
+                        script.path.clone().unwrap().display()
+                    );
+                }
+            }
+        }
+    }
+    scripts_to_run
+}
+
 pub fn parse_scripts(scripts: Vec<PathBuf>) -> Vec<ScriptFile> {
     let mut parsed_scripts: Vec<ScriptFile> = Vec::with_capacity(scripts.len());
     for script in scripts {
@@ -163,6 +281,34 @@ pub fn parse_scripts(scripts: Vec<PathBuf>) -> Vec<ScriptFile> {
     parsed_scripts
 }
 
+/// Runs the scripts of every IP, working on at most `parallelism` IPs at the
+/// same time. The scripts of a single IP still run one after the other.
+///
+/// `on_complete` is called with the outputs of an IP as soon as all of its
+/// scripts are done. Calls never overlap, which lets the caller print an IP's
+/// output as a single block.
+pub fn run_scripts<F>(jobs: Vec<(IpAddr, Vec<Script>)>, parallelism: usize, on_complete: F)
+where
+    F: FnMut(IpAddr, Vec<Result<String>>) + Send,
+{
+    let workers = parallelism.clamp(1, jobs.len().max(1));
+    let queue = Mutex::new(jobs.into_iter());
+    let on_complete = Mutex::new(on_complete);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().next();
+                let Some((ip, scripts)) = next else {
+                    break;
+                };
+                let outputs: Vec<Result<String>> = scripts.into_iter().map(Script::run).collect();
+                (on_complete.lock().unwrap())(ip, outputs);
+            });
+        }
+    });
+}
+
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub struct Script {
@@ -181,24 +327,31 @@ pub struct Script {
     // Character to join ports in case we want to use a string format of them, for example nmap -p.
     ports_separator: Option<String>,
 
+    // How the ports are passed as arguments, overrides ports_separator.
+    ports_format: Option<PortsFormat>,
+
     // Tags found in ScriptFile.
     tags: Option<Vec<String>>,
 
     // The format how we want the script to run.
     call_format: Option<String>,
-}
 
-#[derive(Serialize)]
-struct ExecPartsScript {
-    script: String,
-    ip: String,
-    port: String,
+    // How long the script may run before it gets killed.
+    timeout: Option<Duration>,
+
+    // Whether stdout is printed line by line as the script writes it.
+    stream: bool,
 }
 
+/// The values `call_format` placeholders are replaced with, already quoted
+/// for the shell.
 #[derive(Serialize)]
 struct ExecParts {
+    script: String,
     ip: String,
     port: String,
+    ports: String,
+    port_count: String,
 }
 
 impl Script {
@@ -219,81 +372,313 @@ impl Script {
             ports_separator,
             tags,
             call_format,
+            ports_format: None,
+            timeout: None,
+            stream: false,
         }
     }
 
-    // Some variables get changed before read, and compiler throws warning on warn(unused_assignments)
-    #[allow(unused_assignments)]
+    /// Passes the ports as `format` says rather than joined with the ports
+    /// separator.
+    pub fn with_ports_format(mut self, format: Option<PortsFormat>) -> Self {
+        self.ports_format = format;
+        self
+    }
+
+    /// Kills the script if it's still running after `timeout`.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Prints every line the script writes to stdout as soon as it is
+    /// written, prefixed with the IP, instead of leaving it all to the
+    /// caller once the script exits. The output is still returned by `run`.
+    pub fn with_streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
     pub fn run(self) -> Result<String> {
         debug!("run self {:?}", &self);
 
-        let separator = self.ports_separator.unwrap_or_else(|| ",".into());
+        let Some(call_format) = &self.call_format else {
+            return Err(ScriptError::ParseError(
+                "Failed to parse execution format.".to_owned(),
+            ));
+        };
+        let to_run = self.fill_call_format(call_format)?;
+        debug!("\nScript format to run {}", to_run);
 
-        let mut ports_str = self
-            .open_ports
-            .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<String>>()
-            .join(&separator);
-        if let Some(port) = self.trigger_port {
-            ports_str = port;
+        if self.stream {
+            let ip = self.ip;
+            return execute_script_streaming(&to_run, self.timeout, |line| {
+                println!("[{ip}] {line}");
+            });
         }
+        execute_script(&to_run, self.timeout)
+    }
 
-        let mut final_call_format = String::new();
-        if let Some(call_format) = self.call_format {
-            final_call_format = call_format;
-        } else {
-            return Err(anyhow!("Failed to parse execution format."));
-        }
-        let default_template: Template = Template::new(&final_call_format);
-        let mut to_run = String::new();
-
-        if final_call_format.contains("{{script}}") {
-            let exec_parts_script: ExecPartsScript = ExecPartsScript {
-                script: self.path.unwrap().to_str().unwrap().to_string(),
-                ip: self.ip.to_string(),
-                port: ports_str,
-            };
-            to_run = default_template.fill_with_struct(&exec_parts_script)?;
-        } else {
-            let exec_parts: ExecParts = ExecParts {
-                ip: self.ip.to_string(),
-                port: ports_str,
-            };
-            to_run = default_template.fill_with_struct(&exec_parts)?;
-        }
-        debug!("\nScript format to run {}", to_run);
+    /// Replaces the placeholders of `call_format` with the values of this
+    /// script, see the module documentation for the ones supported.
+    fn fill_call_format(&self, call_format: &str) -> Result<String> {
+        let ports: Vec<String> = match &self.trigger_port {
+            Some(port) => vec![port.clone()],
+            None => self.open_ports.iter().map(ToString::to_string).collect(),
+        };
+        let ports_str = match &self.ports_format {
+            None => shell_quote(&ports.join(self.ports_separator.as_deref().unwrap_or(","))),
+            Some(PortsFormat::Comma) => shell_quote(&ports.join(",")),
+            Some(PortsFormat::Space) => ports
+                .iter()
+                .map(|port| shell_quote(port))
+                .collect::<Vec<String>>()
+                .join(" "),
+            Some(PortsFormat::Repeated(template)) => ports
+                .iter()
+                .map(|port| template.replace("{{port}}", &shell_quote(port)))
+                .collect::<Vec<String>>()
+                .join(" "),
+        };
 
-        execute_script(&to_run)
+        let script = match &self.path {
+            Some(path) => shell_quote(&path.to_string_lossy()),
+            None if call_format.contains("{{script}}") => {
+                return Err(ScriptError::ParseError(
+                    "{{script}} needs a script file path.".to_owned(),
+                ));
+            }
+            None => String::new(),
+        };
+        let exec_parts = ExecParts {
+            script,
+            ip: shell_quote(&self.ip.to_string()),
+            port: ports_str.clone(),
+            ports: ports_str,
+            port_count: self.open_ports.len().to_string(),
+        };
+        Template::new(call_format)
+            .fill_with_struct_strict(&exec_parts)
+            .map_err(|e| ScriptError::ParseError(e.to_string()))
+    }
+}
+
+/// Quotes a value so the shell passes it on as a single argument. Values
+/// made of characters the shell gives no meaning to are left as they are.
+fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return value.to_owned();
+    }
+
+    #[cfg(unix)]
+    {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+
+    #[cfg(not(unix))]
+    {
+        format!("\"{}\"", value.replace('"', "\"\""))
     }
 }
 
 #[cfg(not(tarpaulin_include))]
-fn execute_script(script: &str) -> Result<String> {
+fn execute_script(script: &str, timeout: Option<Duration>) -> Result<String> {
     debug!("\nScript arguments {}", script);
+    if let Some(timeout) = timeout {
+        return execute_script_with_timeout(script, timeout);
+    }
+
     let process = Exec::shell(script);
     match process.capture() {
         Ok(c) => {
-            let es = match c.exit_status {
-                ExitStatus::Exited(c) => c.try_into().unwrap(),
-                ExitStatus::Signaled(c) => c.into(),
-                ExitStatus::Other(c) => c,
-                ExitStatus::Undetermined => -1,
-            };
-            if es != 0 {
-                return Err(anyhow!("Exit code = {}", es));
+            let status = exit_code(c.exit_status);
+            if status != 0 {
+                return Err(ScriptError::ExecFailed { status });
             }
             Ok(c.stdout_str())
         }
         Err(error) => {
             debug!("Command error {}", error.to_string());
-            Err(anyhow!(error.to_string()))
+            Err(error.into())
+        }
+    }
+}
+
+/// Runs the script like `execute_script` but kills it, along with anything
+/// it spawned, once `timeout` has passed.
+#[cfg(not(tarpaulin_include))]
+fn execute_script_with_timeout(script: &str, timeout: Duration) -> Result<String> {
+    let started = Instant::now();
+    let mut process = spawn_script(script)?;
+
+    let stdout = match process
+        .communicate_start(None)
+        .limit_time(timeout)
+        .read_string()
+    {
+        Ok((stdout, _)) => stdout.unwrap_or_default(),
+        Err(e) if e.error.kind() == io::ErrorKind::TimedOut => {
+            kill_script(&mut process);
+            return Err(ScriptError::Timeout(timeout));
+        }
+        Err(e) => {
+            debug!("Command error {}", e.error.to_string());
+            return Err(e.error.into());
+        }
+    };
+
+    // The script may close its stdout without exiting, so the wait must
+    // respect what's left of the deadline as well.
+    let Some(status) = process.wait_timeout(timeout.saturating_sub(started.elapsed()))? else {
+        kill_script(&mut process);
+        return Err(ScriptError::Timeout(timeout));
+    };
+
+    let status = exit_code(status);
+    if status != 0 {
+        return Err(ScriptError::ExecFailed { status });
+    }
+    Ok(stdout)
+}
+
+/// Runs the script, handing every line of its stdout to `on_line` as soon as
+/// it is written, and returns the whole output once it exits. With a
+/// timeout the script is killed, along with anything it spawned, once it
+/// has passed.
+#[cfg(not(tarpaulin_include))]
+fn execute_script_streaming<F>(
+    script: &str,
+    timeout: Option<Duration>,
+    mut on_line: F,
+) -> Result<String>
+where
+    F: FnMut(&str),
+{
+    debug!("\nScript arguments {}", script);
+    let started = Instant::now();
+    let mut process = spawn_script(script)?;
+    let stdout = process
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::other("Failed to capture the script output."))?;
+    let timed_out = AtomicBool::new(false);
+
+    let mut output = String::new();
+    let read = thread::scope(|scope| {
+        let (done, finished) = mpsc::channel::<()>();
+        if let Some(timeout) = timeout {
+            let process = &mut process;
+            let timed_out = &timed_out;
+            // Killing the script closes its stdout, which ends the reads.
+            scope.spawn(move || {
+                if finished.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                    timed_out.store(true, Ordering::SeqCst);
+                    kill_script(process);
+                }
+            });
+        }
+
+        let mut reader = io::BufReader::new(stdout);
+        let mut line = Vec::new();
+        let read = loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&line);
+                    on_line(line.trim_end_matches(['\r', '\n']));
+                    output.push_str(&line);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        drop(done);
+        read
+    });
+
+    if timed_out.load(Ordering::SeqCst) {
+        return Err(ScriptError::Timeout(timeout.unwrap_or_default()));
+    }
+    read?;
+
+    // The script may close its stdout without exiting, so the wait must
+    // respect what's left of the deadline as well.
+    let status = match timeout {
+        Some(timeout) => {
+            let Some(status) = process.wait_timeout(timeout.saturating_sub(started.elapsed()))?
+            else {
+                kill_script(&mut process);
+                return Err(ScriptError::Timeout(timeout));
+            };
+            status
         }
+        None => process.wait()?,
+    };
+
+    let status = exit_code(status);
+    if status != 0 {
+        return Err(ScriptError::ExecFailed { status });
+    }
+    Ok(output)
+}
+
+/// Spawns the script through the shell with its stdout captured. On unix it
+/// gets a process group of its own so that it can be killed as a whole.
+fn spawn_script(script: &str) -> Result<Popen> {
+    #[cfg(unix)]
+    {
+        let config = PopenConfig {
+            stdout: Redirection::Pipe,
+            setpgid: true,
+            ..Default::default()
+        };
+        Ok(Popen::create(&["sh", "-c", script], config)?)
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(Exec::shell(script).stdout(Redirection::Pipe).popen()?)
+    }
+}
+
+/// Kills a script started by `spawn_script`, including every process in its
+/// process group on unix, e.g. the nmap started by a wrapper shell.
+fn kill_script(process: &mut Popen) {
+    #[cfg(unix)]
+    {
+        use std::convert::TryFrom;
+
+        if let Some(pid) = process.pid().and_then(|pid| i32::try_from(pid).ok()) {
+            // Safety: kill only sends a signal, a negative pid targets the group.
+            unsafe {
+                libc::kill(-pid, libc::SIGKILL);
+            }
+        }
+    }
+
+    if let Err(e) = process.kill() {
+        debug!("Failed to kill script {}", e);
+    }
+    let _ = process.wait();
+}
+
+fn exit_code(status: ExitStatus) -> i32 {
+    match status {
+        ExitStatus::Exited(c) => c.try_into().unwrap(),
+        ExitStatus::Signaled(c) => c.into(),
+        ExitStatus::Other(c) => c,
+        ExitStatus::Undetermined => -1,
     }
 }
 
 pub fn find_scripts(mut path: PathBuf) -> Result<Vec<PathBuf>> {
     path.push(".rustscan_scripts");
+    list_scripts_dir(&path)
+}
+
+/// The files of a scripts folder.
+pub fn list_scripts_dir(path: &Path) -> Result<Vec<PathBuf>> {
     if path.is_dir() {
         debug!("Scripts folder found {}", &path.display());
         let mut files_vec: Vec<PathBuf> = Vec::new();
@@ -303,8 +688,44 @@ pub fn find_scripts(mut path: PathBuf) -> Result<Vec<PathBuf>> {
         }
         Ok(files_vec)
     } else {
-        Err(anyhow!("Can't find scripts folder {}", path.display()))
+        Err(ScriptError::NotFound(format!(
+            "scripts folder {}",
+            path.display()
+        )))
+    }
+}
+
+/// The files of every `--scripts-dir`, merged by file name: a file of a
+/// later folder takes the place of the earlier file of the same name.
+pub fn layer_scripts_dirs(dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        for file in list_scripts_dir(dir)? {
+            match files
+                .iter_mut()
+                .find(|known| known.file_name() == file.file_name())
+            {
+                Some(known) => {
+                    debug!("Script {} overrides {}", file.display(), known.display());
+                    *known = file;
+                }
+                None => files.push(file),
+            }
+        }
     }
+    Ok(files)
+}
+
+/// How the open ports are passed to a script, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortsFormat {
+    /// A single argument, the ports joined with commas.
+    Comma,
+    /// An argument per port.
+    Space,
+    /// The template with `{{port}}` replaced, once per port.
+    Repeated(String),
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -314,27 +735,53 @@ pub struct ScriptFile {
     pub developer: Option<Vec<String>>,
     pub port: Option<String>,
     pub ports_separator: Option<String>,
+    pub ports_format: Option<PortsFormat>,
     pub call_format: Option<String>,
+    /// Seconds the script may run before it gets killed.
+    pub timeout: Option<u64>,
+    /// Ports of which at least one has to be open for the script to run.
+    pub trigger_ports: Option<Vec<u16>>,
 }
 
 impl ScriptFile {
+    /// Whether the script should run on an IP with the given open ports.
+    pub fn is_triggered_by(&self, open_ports: &[u16]) -> bool {
+        match &self.trigger_ports {
+            Some(trigger_ports) => trigger_ports.iter().any(|port| open_ports.contains(port)),
+            None => true,
+        }
+    }
+
     fn new(script: PathBuf) -> Option<ScriptFile> {
+        match Self::read(script) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                debug!("{e}");
+                None
+            }
+        }
+    }
+
+    /// Reads the TOML header of a script, the comments following its first
+    /// line.
+    pub fn read(script: PathBuf) -> Result<ScriptFile> {
         let real_path = script.clone();
         let mut lines_buf = String::new();
-        if let Ok(file) = File::open(script) {
-            for mut line in io::BufReader::new(file).lines().skip(1).flatten() {
-                if line.starts_with('#') {
-                    line.retain(|c| c != '#');
-                    line = line.trim().to_string();
-                    line.push('\n');
-                    lines_buf.push_str(&line);
-                } else {
-                    break;
-                }
+        let Ok(file) = File::open(script) else {
+            return Err(ScriptError::NotFound(format!(
+                "script {}",
+                real_path.display()
+            )));
+        };
+        for mut line in io::BufReader::new(file).lines().skip(1).flatten() {
+            if line.starts_with('#') {
+                line.retain(|c| c != '#');
+                line = line.trim().to_string();
+                line.push('\n');
+                lines_buf.push_str(&line);
+            } else {
+                break;
             }
-        } else {
-            debug!("Failed to read file: {}", &real_path.display());
-            return None;
         }
         debug!("ScriptFile {} lines\n{}", &real_path.display(), &lines_buf);
 
@@ -342,13 +789,12 @@ impl ScriptFile {
             Ok(mut parsed) => {
                 debug!("Parsed ScriptFile{} \n{:?}", &real_path.display(), &parsed);
                 parsed.path = Some(real_path);
-                // parsed_scripts.push(parsed);
-                Some(parsed)
-            }
-            Err(e) => {
-                debug!("Failed to parse ScriptFile headers {}", e.to_string());
-                None
+                Ok(parsed)
             }
+            Err(e) => Err(ScriptError::ParseError(format!(
+                "Invalid script header {}: {e}",
+                real_path.display()
+            ))),
         }
     }
 }
@@ -364,19 +810,30 @@ pub struct ScriptConfig {
 impl ScriptConfig {
     pub fn read_config() -> Result<ScriptConfig> {
         let Some(mut home_dir) = dirs::home_dir() else {
-            return Err(anyhow!("Could not infer ScriptConfig path."));
+            return Err(ScriptError::NotFound("the home directory".to_owned()));
         };
         home_dir.push(".rustscan_scripts.toml");
 
-        let content = fs::read_to_string(home_dir)?;
-        let config = toml::from_str::<ScriptConfig>(&content)?;
-        Ok(config)
+        let content = fs::read_to_string(&home_dir).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                ScriptError::NotFound(format!("script config {}", home_dir.display()))
+            }
+            _ => e.into(),
+        })?;
+        toml::from_str::<ScriptConfig>(&content).map_err(|e| {
+            ScriptError::ParseError(format!("Invalid script config {}: {e}", home_dir.display()))
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{find_scripts, parse_scripts, Script, ScriptFile};
+    use super::{
+        execute_script_streaming, find_scripts, layer_scripts_dirs, parse_scripts, run_scripts,
+        select_scripts, shell_quote, PortsFormat, Script, ScriptError, ScriptFile,
+    };
+    use std::net::IpAddr;
+    use std::sync::Mutex;
 
     // Function for testing only, it inserts static values into ip and open_ports
     // Doesn't use impl in case it's implemented in the super module at some point
@@ -399,6 +856,72 @@ mod tests {
         assert_eq!(scripts.len(), 4);
     }
 
+    #[test]
+    fn scripts_dirs_layered_by_name() {
+        let files = layer_scripts_dirs(&[
+            "fixtures/.rustscan_scripts".into(),
+            "fixtures/team_scripts".into(),
+        ])
+        .unwrap();
+        let mut names: Vec<String> = files
+            .iter()
+            .map(|file| file.strip_prefix("fixtures").unwrap().display().to_string())
+            .collect();
+        names.sort();
+
+        assert_eq!(
+            names,
+            vec![
+                ".rustscan_scripts/test_script.pl",
+                ".rustscan_scripts/test_script.py",
+                ".rustscan_scripts/test_script.txt",
+                ".rustscan_scripts/test_script_invalid_headers.txt",
+                "team_scripts/team_script.sh",
+                "team_scripts/test_script.sh",
+            ]
+        );
+        assert!(matches!(
+            layer_scripts_dirs(&["fixtures/team_scripts".into(), "fixtures/nowhere".into()]),
+            Err(ScriptError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn scripts_selected_by_tags() {
+        let scripts = parse_scripts(
+            layer_scripts_dirs(&[
+                "fixtures/.rustscan_scripts".into(),
+                "fixtures/team_scripts".into(),
+            ])
+            .unwrap(),
+        );
+        let names = |selected: Vec<ScriptFile>| -> Vec<String> {
+            let mut names: Vec<String> = selected
+                .iter()
+                .map(|script| script.path.clone().unwrap().display().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(
+            names(select_scripts(
+                scripts.clone(),
+                Some(vec!["core_approved".to_owned()]),
+                &["team".to_owned(), "recon".to_owned()]
+            )),
+            vec![
+                "fixtures/team_scripts/team_script.sh",
+                "fixtures/team_scripts/test_script.sh"
+            ]
+        );
+        assert_eq!(
+            select_scripts(scripts.clone(), Some(vec!["core_approved".to_owned()]), &[]).len(),
+            5
+        );
+        assert!(select_scripts(scripts, None, &["nothing".to_owned()]).is_empty());
+    }
+
     #[test]
     #[should_panic]
     fn find_invalid_folder() {
@@ -491,4 +1014,260 @@ mod tests {
         // output has a newline at the end by default, .trim() trims it
         assert_eq!(output.trim(), "Total args passed to fixtures/.rustscan_scripts/test_script.pl : 2\nArg # 1 : 127.0.0.1\nArg # 2 : 80,8080");
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_scripts_in_parallel() {
+        let ips: Vec<IpAddr> = (1..=4)
+            .map(|i| format!("127.0.0.{i}").parse().unwrap())
+            .collect();
+        let jobs = ips
+            .iter()
+            .map(|ip| {
+                let script = Script::build(
+                    None,
+                    *ip,
+                    vec![80],
+                    None,
+                    None,
+                    None,
+                    Some("sleep 0.5; echo {{ip}}".to_string()),
+                );
+                (*ip, vec![script])
+            })
+            .collect();
+        let completed = Mutex::new(Vec::new());
+
+        let start = std::time::Instant::now();
+        run_scripts(jobs, 4, |ip, outputs| {
+            let output = outputs.into_iter().next().unwrap().unwrap();
+            assert_eq!(output.trim(), ip.to_string());
+            completed.lock().unwrap().push(ip);
+        });
+
+        // Four half second scripts running one at a time would need 2 seconds.
+        assert!(start.elapsed() < std::time::Duration::from_millis(1500));
+        let mut completed = completed.into_inner().unwrap();
+        completed.sort();
+        assert_eq!(completed, ips);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_script_timeout() {
+        let script = Script::build(
+            None,
+            "127.0.0.1".parse().unwrap(),
+            vec![80],
+            None,
+            None,
+            None,
+            Some("sleep 10; echo {{ip}} {{port}}".to_string()),
+        )
+        .with_timeout(Some(std::time::Duration::from_secs(1)));
+
+        let start = std::time::Instant::now();
+        let error = script.run().unwrap_err();
+
+        assert_eq!(error.to_string(), "Script timed out after 1s");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_script_within_timeout() {
+        let script_f = ScriptFile::new("fixtures/.rustscan_scripts/test_script.sh".into()).unwrap();
+        let script: Script =
+            into_script(script_f).with_timeout(Some(std::time::Duration::from_secs(10)));
+        let output = script.run().unwrap();
+        assert_eq!(output.trim(), "127.0.0.1 80,8080");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stream_script_output() {
+        let mut lines = Vec::new();
+        let output = execute_script_streaming("echo one; echo two", None, |line| {
+            lines.push(line.to_owned());
+        })
+        .unwrap();
+
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(output, "one\ntwo\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stream_script_timeout() {
+        let mut lines = Vec::new();
+        let start = std::time::Instant::now();
+        let error = execute_script_streaming(
+            "echo started; sleep 10; echo done",
+            Some(std::time::Duration::from_secs(1)),
+            |line| lines.push(line.to_owned()),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "Script timed out after 1s");
+        assert_eq!(lines, vec!["started"]);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stream_script_exit_code() {
+        let error = execute_script_streaming("echo partial; exit 3", None, |_| {}).unwrap_err();
+        assert_eq!(error.to_string(), "Exit code = 3");
+    }
+
+    #[test]
+    fn trigger_ports_select_scripts() {
+        let script_f: ScriptFile = toml::from_str(
+            r#"tags = ["http"]
+trigger_ports = [80, 443]
+call_format = "nikto -h {{ip}}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(script_f.trigger_ports, Some(vec![80, 443]));
+        assert!(script_f.is_triggered_by(&[22, 443]));
+        assert!(!script_f.is_triggered_by(&[22, 8080]));
+
+        let always: ScriptFile = toml::from_str(r#"call_format = "nmap {{ip}}""#).unwrap();
+        assert!(always.is_triggered_by(&[22]));
+    }
+
+    #[test]
+    fn fill_call_format_placeholders() {
+        let script = Script::build(
+            Some("/opt/my scripts/scan.sh".into()),
+            "127.0.0.1".parse().unwrap(),
+            vec![80, 8080],
+            None,
+            Some(" ".to_owned()),
+            None,
+            None,
+        );
+
+        assert_eq!(
+            script
+                .fill_call_format("bash {{script}} {{ip}} {{ports}} {{port_count}}")
+                .unwrap(),
+            "bash '/opt/my scripts/scan.sh' 127.0.0.1 '80 8080' 2"
+        );
+        assert_eq!(
+            script.fill_call_format("nmap -p {{port}} {{ip}}").unwrap(),
+            "nmap -p '80 8080' 127.0.0.1"
+        );
+        assert!(script.fill_call_format("nmap {{host}}").is_err());
+    }
+
+    #[test]
+    fn fill_call_format_ports_formats() {
+        let script_f: ScriptFile = toml::from_str(
+            r#"
+            ports_separator = ";"
+            ports_format = { repeated = "-p {{port}}" }
+            call_format = "scan {{ports}} {{ip}}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            script_f.ports_format,
+            Some(PortsFormat::Repeated("-p {{port}}".to_owned()))
+        );
+        let script = into_script(script_f.clone()).with_ports_format(script_f.ports_format);
+        assert_eq!(
+            script.fill_call_format("scan {{ports}} {{ip}}").unwrap(),
+            "scan -p 80 -p 8080 127.0.0.1"
+        );
+
+        let script = script.with_ports_format(Some(PortsFormat::Space));
+        assert_eq!(
+            script.fill_call_format("scan {{ports}}").unwrap(),
+            "scan 80 8080"
+        );
+        let script = script.with_ports_format(Some(PortsFormat::Comma));
+        assert_eq!(
+            script.fill_call_format("scan {{ports}}").unwrap(),
+            "scan 80,8080"
+        );
+        let script = script.with_ports_format(None);
+        assert_eq!(
+            script.fill_call_format("scan {{ports}}").unwrap(),
+            "scan '80;8080'"
+        );
+        assert!(toml::from_str::<ScriptFile>(r#"ports_format = "tabs""#).is_err());
+    }
+
+    #[test]
+    fn fill_call_format_script_needs_path() {
+        let script = Script::build(
+            None,
+            "::1".parse().unwrap(),
+            vec![22],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(
+            script.fill_call_format("nmap -p {{ports}} {{ip}}").unwrap(),
+            "nmap -p 22 ::1"
+        );
+        assert!(script.fill_call_format("{{script}} {{ip}}").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shell_quote_values() {
+        assert_eq!(shell_quote("80,443"), "80,443");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's; rm -rf /"), r"'it'\''s; rm -rf /'");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn script_errors_match_failure_modes() {
+        assert!(matches!(
+            find_scripts("Cargo.toml".into()),
+            Err(ScriptError::NotFound(_))
+        ));
+
+        let mut script_f =
+            ScriptFile::new("fixtures/.rustscan_scripts/test_script.txt".into()).unwrap();
+        script_f.call_format = None;
+        assert!(matches!(
+            into_script(script_f).run(),
+            Err(ScriptError::ParseError(_))
+        ));
+
+        let failing = Script::build(
+            None,
+            "127.0.0.1".parse().unwrap(),
+            vec![80],
+            None,
+            None,
+            None,
+            Some("exit 3".to_owned()),
+        );
+        assert!(matches!(
+            failing.run(),
+            Err(ScriptError::ExecFailed { status: 3 })
+        ));
+
+        let slow = Script::build(
+            None,
+            "127.0.0.1".parse().unwrap(),
+            vec![80],
+            None,
+            None,
+            None,
+            Some("sleep 5".to_owned()),
+        )
+        .with_timeout(Some(std::time::Duration::from_millis(200)));
+        assert!(matches!(slow.run(), Err(ScriptError::Timeout(_))));
+    }
 }
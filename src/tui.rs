@@ -1,11 +1,126 @@
 //! Utilities for terminal output during scanning.
+//!
+//! Every coloured bit of output goes through [`styled`], so `--color` and
+//! accessible mode apply to all of it. Colours are on for a stream when
+//! `--color always` was given, or with `--color auto` when the stream is a
+//! terminal and `NO_COLOR` isn't set. Accessible mode turns them off.
+//!
+//! ```rust
+//! # use rustscan::input::ColorChoice;
+//! # use rustscan::tui::{init_color, tag, Stream, Tag};
+//! init_color(ColorChoice::Never, false);
+//! assert_eq!(tag(Tag::Warning, Stream::Stderr), "[!]");
+//! ```
+use crate::input::ColorChoice;
+use ansi_term::{Colour, Style};
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Whether stdout and stderr get colours, settled once per process.
+static COLORS: OnceLock<(bool, bool)> = OnceLock::new();
+
+/// Where a line is printed to, as colours are picked per stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// The tags lines start with.
+///   - Warning `[!]` for what may be wrong with the scan.
+///   - Detail `[~]` for what the scan is about to do or did.
+///   - Output `[>]` for results and errors.
+///   - Question `[?]` for prompts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Warning,
+    Detail,
+    Output,
+    Question,
+}
+
+/// Settles whether the output gets colours. The first call wins, output
+/// printed before any call is coloured as with `ColorChoice::Auto`.
+pub fn init_color(choice: ColorChoice, accessible: bool) {
+    let _ = COLORS.set(pick_colors(choice, accessible));
+}
+
+fn pick_colors(choice: ColorChoice, accessible: bool) -> (bool, bool) {
+    match choice {
+        _ if accessible => (false, false),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Always => (true, true),
+        ColorChoice::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            (
+                !no_color && std::io::stdout().is_terminal(),
+                !no_color && std::io::stderr().is_terminal(),
+            )
+        }
+    }
+}
+
+/// Whether what's printed to `stream` gets colours.
+pub fn color_enabled(stream: Stream) -> bool {
+    let (stdout, stderr) = *COLORS.get_or_init(|| pick_colors(ColorChoice::Auto, false));
+    match stream {
+        Stream::Stdout => stdout,
+        Stream::Stderr => stderr,
+    }
+}
+
+/// `text` in `style`, or as is when `stream` gets no colours.
+pub fn styled(text: &str, style: Style, stream: Stream) -> String {
+    if color_enabled(stream) {
+        style.paint(text).to_string()
+    } else {
+        text.to_owned()
+    }
+}
+
+/// The tag for a line printed to `stream`, e.g. `[!]`.
+pub fn tag(tag: Tag, stream: Stream) -> String {
+    let (text, style) = match tag {
+        Tag::Warning => ("[!]", Colour::Red.bold()),
+        Tag::Detail => ("[~]", Colour::Blue.bold()),
+        Tag::Output => ("[>]", Colour::RGB(0, 255, 9).bold()),
+        Tag::Question => ("[?]", Colour::Yellow.bold()),
+    };
+    styled(text, style, stream)
+}
+
+/// Prints a line to stdout behind a tag, e.g.
+/// `tagln!(Output, "{} open", count)`.
+#[macro_export]
+macro_rules! tagln {
+    ($tag:ident, $($arg:tt)*) => {
+        println!(
+            "{} {}",
+            $crate::tui::tag($crate::tui::Tag::$tag, $crate::tui::Stream::Stdout),
+            format_args!($($arg)*)
+        )
+    };
+}
+
+/// Prints a line to stderr behind a tag, e.g.
+/// `etagln!(Warning, "{} failed", what)`.
+#[macro_export]
+macro_rules! etagln {
+    ($tag:ident, $($arg:tt)*) => {
+        eprintln!(
+            "{} {}",
+            $crate::tui::tag($crate::tui::Tag::$tag, $crate::tui::Stream::Stderr),
+            format_args!($($arg)*)
+        )
+    };
+}
 
 /// Terminal User Interface Module for RustScan
 /// Defines macros to use
 #[macro_export]
 macro_rules! warning {
     ($name:expr) => {
-        println!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), $name);
+        $crate::tagln!(Warning, "{}", $name);
     };
     ($name:expr, $greppable:expr, $accessible:expr) => {
         // if not greppable then print, otherwise no else statement so do not print.
@@ -14,7 +129,7 @@ macro_rules! warning {
                 // Don't print the ascii art
                 println!("{}", $name);
             } else {
-                println!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), $name);
+                $crate::tagln!(Warning, "{}", $name);
             }
         }
     };
@@ -23,7 +138,7 @@ macro_rules! warning {
 #[macro_export]
 macro_rules! detail {
     ($name:expr) => {
-        println!("{} {}", ansi_term::Colour::Blue.bold().paint("[~]"), $name);
+        $crate::tagln!(Detail, "{}", $name);
     };
     ($name:expr, $greppable:expr, $accessible:expr) => {
         // if not greppable then print, otherwise no else statement so do not print.
@@ -32,7 +147,7 @@ macro_rules! detail {
                 // Don't print the ascii art
                 println!("{}", $name);
             } else {
-                println!("{} {}", ansi_term::Colour::Blue.bold().paint("[~]"), $name);
+                $crate::tagln!(Detail, "{}", $name);
             }
         }
     };
@@ -41,11 +156,7 @@ macro_rules! detail {
 #[macro_export]
 macro_rules! output {
     ($name:expr) => {
-        println!(
-            "{} {}",
-            RGansi_term::Colour::RGB(0, 255, 9).bold().paint("[>]"),
-            $name
-        );
+        $crate::tagln!(Output, "{}", $name);
     };
     ($name:expr, $greppable:expr, $accessible:expr) => {
         // if not greppable then print, otherwise no else statement so do not print.
@@ -54,11 +165,7 @@ macro_rules! output {
                 // Don't print the ascii art
                 println!("{}", $name);
             } else {
-                println!(
-                    "{} {}",
-                    ansi_term::Colour::RGB(0, 255, 9).bold().paint("[>]"),
-                    $name
-                );
+                $crate::tagln!(Output, "{}", $name);
             }
         }
     };
@@ -99,3 +206,18 @@ macro_rules! funny_opening {
         println!("{}\n", random_quote);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::pick_colors;
+    use crate::input::ColorChoice;
+
+    #[test]
+    fn accessible_mode_is_never_coloured() {
+        assert_eq!(pick_colors(ColorChoice::Always, false), (true, true));
+        assert_eq!(pick_colors(ColorChoice::Always, true), (false, false));
+        assert_eq!(pick_colors(ColorChoice::Never, false), (false, false));
+        // Test output is captured, neither stream is a terminal.
+        assert_eq!(pick_colors(ColorChoice::Auto, false), (false, false));
+    }
+}
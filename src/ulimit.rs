@@ -0,0 +1,192 @@
+//! Picking a batch size that fits the open file limit, as every probe in
+//! flight holds a file descriptor.
+//!
+//! Inferring the batch size has no side effects, reading and raising the
+//! limit of the process are left to separate functions, so embedding RustScan
+//! doesn't touch the limits of the host binary unless it asks to.
+//!
+//! ```rust
+//! # use rustscan::input::Opts;
+//! # use rustscan::ulimit::recommended_batch_size;
+//! let opts = Opts {
+//!     batch_size: 4500,
+//!     ..Default::default()
+//! };
+//! assert_eq!(recommended_batch_size(1024, &opts), 512);
+//! ```
+use crate::input::Opts;
+use std::convert::TryFrom;
+
+/// Average value for Ubuntu.
+pub const DEFAULT_FILE_DESCRIPTORS_LIMIT: u64 = 8000;
+/// Safest batch size based on experimentation.
+pub const AVERAGE_BATCH_SIZE: u16 = 3000;
+
+/// The batch size to use with `opts` given a file limit of `ulimit`: the one
+/// of `opts`, lowered when the limit can't fit it.
+pub fn recommended_batch_size(ulimit: u64, opts: &Opts) -> u16 {
+    infer(ulimit, opts).0
+}
+
+/// Advice on the limit and the batch size that goes along with
+/// `recommended_batch_size`, for the command line to print.
+pub fn batch_size_notes(ulimit: u64, opts: &Opts) -> Vec<String> {
+    infer(ulimit, opts).1
+}
+
+/// The batch size for the limit, and the notes on why it was picked.
+fn infer(ulimit: u64, opts: &Opts) -> (u16, Vec<String>) {
+    let mut notes = Vec::new();
+    let mut batch_size: u64 = opts.batch_size.into();
+
+    // adjust the batch size when the ulimit value is lower than the desired batch size
+    if ulimit < batch_size {
+        notes.push("file limit is lower than default batch size. consider upping with --ulimit. may cause harm to sensitive servers".to_owned());
+
+        // when the os supports high file limits like 8000, but the user
+        // selected a batch size higher than this we should reduce it to
+        // a lower number.
+        if ulimit < AVERAGE_BATCH_SIZE.into() {
+            // ulimit is smaller than aveage batch size
+            // user must have very small ulimit
+            // decrease batch size to half of ulimit
+            notes.push("your file limit is very small, which negatively impacts rustscan's speed. use the docker image, or up the ulimit with '--ulimit 5000'. ".to_owned());
+            notes.push(
+                "halving batch_size because ulimit is smaller than average batch size".to_owned(),
+            );
+            batch_size = ulimit / 2;
+        } else if ulimit > DEFAULT_FILE_DESCRIPTORS_LIMIT {
+            notes.push("batch size is now average batch size".to_owned());
+            batch_size = AVERAGE_BATCH_SIZE.into();
+        } else {
+            batch_size = ulimit - 100;
+        }
+    }
+    // when the ulimit is higher than the batch size let the user know that the
+    // batch size can be increased unless they specified the ulimit themselves.
+    else if ulimit + 2 > batch_size && (opts.ulimit.is_none()) {
+        notes.push(format!(
+            "file limit higher than batch size. can increase speed by increasing batch size '-b {}'.",
+            ulimit - 100
+        ));
+    }
+
+    // A --ulimit that couldn't be set, which macOS does for large values,
+    // leaves less room than asked for. Half the limit the process is left
+    // with keeps the scan clear of running out of file descriptors.
+    if let Some(asked) = opts.ulimit.filter(|&asked| ulimit < asked) {
+        let safe = (ulimit / 2).max(1);
+        if batch_size > safe {
+            notes.push(format!(
+                "the file limit stayed at {ulimit} instead of {asked}, lowering the batch size to {safe} so the scan doesn't run out of file descriptors."
+            ));
+            batch_size = safe;
+        }
+    }
+
+    // Only ever lowered from a u16, so this always fits.
+    (u16::try_from(batch_size).unwrap_or(u16::MAX), notes)
+}
+
+/// The soft limit on open files of this process.
+#[cfg(unix)]
+pub fn file_limit() -> std::io::Result<u64> {
+    let (soft, _) = rlimit::Resource::NOFILE.get()?;
+    Ok(soft)
+}
+
+/// Sets both the soft and the hard limit on open files of this process to
+/// `limit`. Raising the hard limit takes privileges.
+#[cfg(unix)]
+pub fn set_file_limit(limit: u64) -> std::io::Result<()> {
+    rlimit::Resource::NOFILE.set(limit, limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{batch_size_notes, recommended_batch_size};
+    use crate::input::Opts;
+
+    #[test]
+    fn batch_size_lowered() {
+        let opts = Opts {
+            batch_size: 50_000,
+            ..Default::default()
+        };
+        let batch_size = recommended_batch_size(120, &opts);
+
+        assert!(batch_size < opts.batch_size);
+    }
+
+    #[test]
+    fn batch_size_lowered_average_size() {
+        let opts = Opts {
+            batch_size: 50_000,
+            ..Default::default()
+        };
+        let batch_size = recommended_batch_size(9_000, &opts);
+
+        assert_eq!(batch_size, 3_000);
+    }
+
+    #[test]
+    fn batch_size_equals_ulimit_lowered() {
+        // because ulimit and batch size are same size, batch size is lowered
+        // to ULIMIT - 100
+        let opts = Opts {
+            batch_size: 50_000,
+            ..Default::default()
+        };
+        let batch_size = recommended_batch_size(5_000, &opts);
+
+        assert_eq!(batch_size, 4_900);
+    }
+
+    #[test]
+    fn test_high_ulimit_no_greppable_mode() {
+        let opts = Opts {
+            batch_size: 10,
+            greppable: false,
+            ..Default::default()
+        };
+
+        let batch_size = recommended_batch_size(1_000_000, &opts);
+
+        assert_eq!(batch_size, opts.batch_size);
+    }
+
+    #[test]
+    fn batch_size_lowered_when_ulimit_not_raised() {
+        let opts = Opts {
+            batch_size: 4_500,
+            ulimit: Some(65_535),
+            ..Default::default()
+        };
+
+        // Left at 10240, the batch size would otherwise be kept.
+        assert_eq!(recommended_batch_size(10_240, &opts), 4_500);
+        assert_eq!(recommended_batch_size(6_000, &opts), 3_000);
+        assert_eq!(
+            batch_size_notes(6_000, &opts),
+            ["the file limit stayed at 6000 instead of 65535, lowering the batch size to 3000 so the scan doesn't run out of file descriptors."]
+        );
+        assert_eq!(recommended_batch_size(65_535, &opts), 4_500);
+    }
+
+    #[test]
+    fn notes_follow_the_batch_size() {
+        let opts = Opts {
+            batch_size: 50_000,
+            ..Default::default()
+        };
+        assert_eq!(batch_size_notes(120, &opts).len(), 3);
+        assert_eq!(batch_size_notes(9_000, &opts).len(), 2);
+
+        let given_ulimit = Opts {
+            batch_size: 10,
+            ulimit: Some(1_000_000),
+            ..Default::default()
+        };
+        assert!(batch_size_notes(1_000_000, &given_ulimit).is_empty());
+    }
+}